@@ -0,0 +1,43 @@
+use crate::{CommitmentQueue, ErrorCode};
+use anchor_lang::prelude::*;
+
+/// Number of commitments a queue shard can hold before it has to be cranked. This is a hard
+/// limit: a shard is a fixed ring buffer without paging, and transact_queued fails with
+/// CommitmentQueueFull while it is full. Such transactions can use another shard or retry once
+/// process_commitment_queue has made room; each one queues two commitments.
+pub const COMMITMENT_QUEUE_CAPACITY: usize = 256;
+
+impl CommitmentQueue {
+    /// Adds a commitment at the back of the queue. `has_origin` marks deposit outputs, whose
+    /// leaf index goes into their deposit origin record when they are inserted.
+    pub fn push(&mut self, commitment: [u8; 32], has_origin: bool) -> Result<()> {
+        require!(
+            (self.len as usize) < COMMITMENT_QUEUE_CAPACITY,
+            ErrorCode::CommitmentQueueFull
+        );
+
+        let tail = (self.head as usize + self.len as usize) % COMMITMENT_QUEUE_CAPACITY;
+        self.commitments[tail] = commitment;
+        self.has_origin[tail] = has_origin as u8;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Removes up to `max_count` commitments from the front of the queue, oldest first, each with
+    /// its `has_origin` flag.
+    pub fn pop_front(&mut self, max_count: usize) -> Vec<([u8; 32], bool)> {
+        let count = max_count.min(self.len as usize);
+        let popped = (0..count)
+            .map(|i| {
+                let position = (self.head as usize + i) % COMMITMENT_QUEUE_CAPACITY;
+                (self.commitments[position], self.has_origin[position] != 0)
+            })
+            .collect();
+
+        self.head = ((self.head as usize + count) % COMMITMENT_QUEUE_CAPACITY) as u64;
+        self.len -= count as u64;
+
+        popped
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Proof;
+#[cfg(feature = "cpi")]
+use crate::ExtDataMinified;
+use anchor_lang::prelude::*;
+
+/// Program addresses a `deposit_for` CPI needs besides the depositor, the payer
+/// and the fee recipient.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DepositForAddresses {
+    pub tree_account: Pubkey,
+    pub tree_token_account: Pubkey,
+    pub global_config: Pubkey,
+    pub nullifier0: Pubkey,
+    pub nullifier1: Pubkey,
+    pub nullifier2: Pubkey,
+    pub nullifier3: Pubkey,
+}
+
+/// Derives the pool and nullifier PDAs for a deposit proof. `tree_generation` is the
+/// newest tree generation (GlobalConfig::current_tree_generation).
+pub fn deposit_for_addresses(proof: &Proof, tree_generation: u64) -> DepositForAddresses {
+    let find = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &crate::ID).0;
+
+    DepositForAddresses {
+        tree_account: find(&[b"merkle_tree", crate::merkle_tree::generation_seed(tree_generation).as_slice()]),
+        tree_token_account: find(&[b"tree_token"]),
+        global_config: find(&[b"global_config"]),
+        nullifier0: find(&[b"nullifier0", proof.input_nullifiers[0].as_ref()]),
+        nullifier1: find(&[b"nullifier1", proof.input_nullifiers[1].as_ref()]),
+        nullifier2: find(&[b"nullifier0", proof.input_nullifiers[1].as_ref()]),
+        nullifier3: find(&[b"nullifier1", proof.input_nullifiers[0].as_ref()]),
+    }
+}
+
+/// Calls `deposit_for` with a PDA of the calling program as depositor.
+/// `depositor_seeds` are the depositor's seeds including its bump.
+#[cfg(feature = "cpi")]
+pub fn deposit_for_signed<'info>(
+    zkcash_program: AccountInfo<'info>,
+    accounts: crate::cpi::accounts::DepositFor<'info>,
+    depositor_seeds: &[&[u8]],
+    proof: Proof,
+    ext_data_minified: ExtDataMinified,
+    encrypted_output1: Vec<u8>,
+    encrypted_output2: Vec<u8>,
+    tree_index: u64,
+) -> Result<()> {
+    crate::cpi::deposit_for(
+        CpiContext::new_with_signer(zkcash_program, accounts, &[depositor_seeds]),
+        proof,
+        ext_data_minified,
+        encrypted_output1,
+        encrypted_output2,
+        tree_index,
+    )
+}
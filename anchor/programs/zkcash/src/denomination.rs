@@ -0,0 +1,78 @@
+use crate::{ErrorCode, GlobalConfig, TreeTokenAccount};
+use anchor_lang::prelude::*;
+
+/// Number of denominations a fixed-denomination pool can be configured with.
+pub const MAX_DENOMINATIONS: usize = 8;
+
+impl GlobalConfig {
+    /// Sets the pool mode at initialize: no denominations keeps variable amounts, otherwise
+    /// deposits and withdrawals must move exactly one of the given amounts.
+    pub fn set_denominations(&mut self, denominations: &[u64]) -> Result<()> {
+        require!(denominations.len() <= MAX_DENOMINATIONS, ErrorCode::InvalidDenominations);
+        require!(!denominations.contains(&0), ErrorCode::InvalidDenominations);
+        // Strictly increasing, so every amount has a single counter
+        require!(
+            denominations.windows(2).all(|pair| pair[0] < pair[1]),
+            ErrorCode::InvalidDenominations
+        );
+
+        self.denominations = [0u64; MAX_DENOMINATIONS];
+        self.denominations[..denominations.len()].copy_from_slice(denominations);
+        self.denomination_count = denominations.len() as u8;
+
+        Ok(())
+    }
+
+    pub fn is_fixed_denomination(&self) -> bool {
+        self.denomination_count > 0
+    }
+
+    /// Returns the index of the denomination `ext_amount` moves, or None for variable-amount
+    /// pools and internal transfers. Fails in fixed-denomination pools if the amount is not one
+    /// of the denominations.
+    pub fn denomination_index(&self, ext_amount: i64) -> Result<Option<usize>> {
+        if !self.is_fixed_denomination() || ext_amount == 0 {
+            return Ok(None);
+        }
+
+        let denominations = &self.denominations[..self.denomination_count as usize];
+        let index = denominations
+            .binary_search(&ext_amount.unsigned_abs())
+            .map_err(|_| ErrorCode::InvalidDenomination)?;
+
+        Ok(Some(index))
+    }
+
+    /// Splitting a withdrawal over several payouts would make its amounts arbitrary again, so
+    /// fixed-denomination pools only pay out to a single recipient.
+    pub fn check_payout_count(&self, payout_count: usize) -> Result<()> {
+        require!(
+            !self.is_fixed_denomination() || payout_count == 1,
+            ErrorCode::SplitDenominationWithdrawal
+        );
+        Ok(())
+    }
+}
+
+impl TreeTokenAccount {
+    /// Counts a fixed-denomination deposit or withdrawal towards its denomination's anonymity set.
+    pub fn record_denomination(&mut self, global_config: &GlobalConfig, ext_amount: i64) -> Result<()> {
+        let Some(index) = global_config.denomination_index(ext_amount)? else {
+            return Ok(());
+        };
+
+        let counter = if ext_amount > 0 {
+            &mut self.denomination_deposits[index]
+        } else {
+            &mut self.denomination_withdrawals[index]
+        };
+        *counter = counter.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    /// Notes of the denomination deposited and not yet withdrawn.
+    pub fn denomination_anonymity_set(&self, index: usize) -> u64 {
+        self.denomination_deposits[index].saturating_sub(self.denomination_withdrawals[index])
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{DenyList, ErrorCode};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+/// Number of addresses the deny list can hold. This is a hard limit: the list is a single
+/// account without paging, and add_to_denylist fails with DenyListFull once it is reached,
+/// until addresses are removed again.
+pub const DENY_LIST_CAPACITY: usize = 256;
+
+/// The deny list stores sha256 hashes of addresses rather than the addresses themselves.
+pub fn address_hash(address: &Pubkey) -> [u8; 32] {
+    hash(address.as_ref()).to_bytes()
+}
+
+impl DenyList {
+    fn entries(&self) -> &[[u8; 32]] {
+        &self.entries[..self.len as usize]
+    }
+
+    pub fn contains(&self, address: &Pubkey) -> bool {
+        self.entries().binary_search(&address_hash(address)).is_ok()
+    }
+
+    /// Inserts the address hash, keeping the entries sorted.
+    pub fn insert(&mut self, address: &Pubkey) -> Result<()> {
+        let entry = address_hash(address);
+        let position = match self.entries().binary_search(&entry) {
+            Ok(_) => return err!(ErrorCode::AddressAlreadyDenied),
+            Err(position) => position,
+        };
+        let len = self.len as usize;
+        require!(len < DENY_LIST_CAPACITY, ErrorCode::DenyListFull);
+
+        self.entries.copy_within(position..len, position + 1);
+        self.entries[position] = entry;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Removes the address hash, keeping the entries sorted.
+    pub fn remove(&mut self, address: &Pubkey) -> Result<()> {
+        let position = self
+            .entries()
+            .binary_search(&address_hash(address))
+            .map_err(|_| ErrorCode::AddressNotDenied)?;
+        let len = self.len as usize;
+
+        self.entries.copy_within(position + 1..len, position);
+        self.entries[len - 1] = [0u8; 32];
+        self.len -= 1;
+
+        Ok(())
+    }
+}
+
+/// Rejects deposits funded by an address on the deny list.
+pub fn check_depositor(deny_list: &DenyList, depositor: &Pubkey) -> Result<()> {
+    require!(!deny_list.contains(depositor), ErrorCode::DepositorDenied);
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::{ErrorCode, FeeSchedule, GlobalConfig, MerkleTreeAccount};
+use anchor_lang::prelude::*;
+
+/// Upper bound on the configuration timelock (~30 days at 400ms slots), so the
+/// authority cannot lock itself out of governance by mistake.
+pub const MAX_CONFIG_TIMELOCK_SLOTS: u64 = 6_480_000;
+
+/// A configuration change that can be applied directly (when no timelock is set)
+/// or queued in a `PendingConfigChange` account until its effective slot.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub enum ConfigChange {
+    Fees {
+        deposit_fee: Option<FeeSchedule>,
+        withdrawal_fee: Option<FeeSchedule>,
+        transfer_fee: Option<FeeSchedule>,
+        fee_error_margin: Option<u16>,
+    },
+    DepositLimit {
+        max_deposit_amount: u64,
+    },
+    Timelock {
+        delay_slots: u64,
+    },
+    ArchivedRootMaxAge {
+        max_age_slots: u64,
+    },
+    WithdrawalDelay {
+        large_withdrawal_threshold: u64,
+        delay_slots: u64,
+    },
+    Guardian {
+        guardian: Pubkey,
+    },
+    UsdLimits {
+        price_feed: Pubkey,
+        max_price_age_slots: u64,
+        max_deposit_usd: u64,
+        max_tvl_usd: u64,
+    },
+    VelocityLimit {
+        window_slots: u64,
+        max_deposit_per_window: u64,
+    },
+}
+
+impl ConfigChange {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ConfigChange::Fees {
+                deposit_fee,
+                withdrawal_fee,
+                transfer_fee,
+                fee_error_margin,
+            } => {
+                for schedule in [deposit_fee, withdrawal_fee, transfer_fee].into_iter().flatten() {
+                    schedule.validate()?;
+                }
+                if let Some(fee_error_margin_val) = fee_error_margin {
+                    require!(*fee_error_margin_val <= 10000, ErrorCode::InvalidFeeRate);
+                }
+            }
+            ConfigChange::DepositLimit { .. } => {}
+            ConfigChange::Timelock { delay_slots } => {
+                require!(
+                    *delay_slots <= MAX_CONFIG_TIMELOCK_SLOTS,
+                    ErrorCode::InvalidConfigTimelock
+                );
+            }
+            ConfigChange::ArchivedRootMaxAge { .. } => {}
+            ConfigChange::WithdrawalDelay { delay_slots, .. } => {
+                require!(
+                    *delay_slots <= MAX_CONFIG_TIMELOCK_SLOTS,
+                    ErrorCode::InvalidConfigTimelock
+                );
+            }
+            ConfigChange::Guardian { .. } => {}
+            ConfigChange::UsdLimits { .. } => {}
+            ConfigChange::VelocityLimit { window_slots, .. } => {
+                require!(
+                    *window_slots > 0 && *window_slots <= MAX_CONFIG_TIMELOCK_SLOTS,
+                    ErrorCode::InvalidVelocityWindow
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the change into the configuration accounts. The change must have been validated.
+    pub fn apply(&self, global_config: &mut GlobalConfig, tree_account: &mut MerkleTreeAccount) {
+        match self {
+            ConfigChange::Fees {
+                deposit_fee,
+                withdrawal_fee,
+                transfer_fee,
+                fee_error_margin,
+            } => {
+                if let Some(deposit_schedule) = deposit_fee {
+                    global_config.deposit_fee = *deposit_schedule;
+                    msg!("Deposit fee updated to: {} lamports + {} basis points (min: {}, max: {})",
+                        deposit_schedule.flat_fee, deposit_schedule.rate_bps, deposit_schedule.min_fee, deposit_schedule.max_fee);
+                }
+
+                if let Some(withdrawal_schedule) = withdrawal_fee {
+                    global_config.withdrawal_fee = *withdrawal_schedule;
+                    msg!("Withdrawal fee updated to: {} lamports + {} basis points (min: {}, max: {})",
+                        withdrawal_schedule.flat_fee, withdrawal_schedule.rate_bps, withdrawal_schedule.min_fee, withdrawal_schedule.max_fee);
+                }
+
+                if let Some(transfer_schedule) = transfer_fee {
+                    global_config.transfer_fee = *transfer_schedule;
+                    msg!("Transfer fee updated to: {} lamports + {} basis points (min: {}, max: {})",
+                        transfer_schedule.flat_fee, transfer_schedule.rate_bps, transfer_schedule.min_fee, transfer_schedule.max_fee);
+                }
+
+                if let Some(fee_error_margin_val) = fee_error_margin {
+                    global_config.fee_error_margin = *fee_error_margin_val;
+                    msg!("Fee error margin updated to: {} basis points", fee_error_margin_val);
+                }
+            }
+            ConfigChange::DepositLimit { max_deposit_amount } => {
+                tree_account.max_deposit_amount = *max_deposit_amount;
+                msg!("Deposit limit updated to: {} lamports", max_deposit_amount);
+            }
+            ConfigChange::Timelock { delay_slots } => {
+                global_config.config_timelock_slots = *delay_slots;
+                msg!("Config timelock updated to: {} slots", delay_slots);
+            }
+            ConfigChange::ArchivedRootMaxAge { max_age_slots } => {
+                global_config.max_archived_root_age_slots = *max_age_slots;
+                msg!("Archived root max age updated to: {} slots", max_age_slots);
+            }
+            ConfigChange::WithdrawalDelay { large_withdrawal_threshold, delay_slots } => {
+                global_config.large_withdrawal_threshold = *large_withdrawal_threshold;
+                global_config.withdrawal_delay_slots = *delay_slots;
+                msg!("Withdrawals above {} lamports are now delayed by {} slots", large_withdrawal_threshold, delay_slots);
+            }
+            ConfigChange::Guardian { guardian } => {
+                global_config.guardian = *guardian;
+                msg!("Guardian updated to: {}", guardian);
+            }
+            ConfigChange::UsdLimits { price_feed, max_price_age_slots, max_deposit_usd, max_tvl_usd } => {
+                global_config.price_feed = *price_feed;
+                global_config.max_price_age_slots = *max_price_age_slots;
+                global_config.max_deposit_usd = *max_deposit_usd;
+                global_config.max_tvl_usd = *max_tvl_usd;
+                msg!("USD limits updated: feed {}, max price age {} slots, deposit limit {} micro-USD, TVL cap {} micro-USD",
+                    price_feed, max_price_age_slots, max_deposit_usd, max_tvl_usd);
+            }
+            ConfigChange::VelocityLimit { window_slots, max_deposit_per_window } => {
+                global_config.velocity_window_slots = *window_slots;
+                global_config.max_deposit_per_window = *max_deposit_per_window;
+                msg!("Depositors are now limited to {} lamports per {} slots", max_deposit_per_window, window_slots);
+            }
+        }
+    }
+}
+
+/// Returns the first slot at which a change queued at `current_slot` may be executed.
+pub fn calculate_effective_slot(current_slot: u64, delay_slots: u64) -> Result<u64> {
+    Ok(current_slot
+        .checked_add(delay_slots)
+        .ok_or(ErrorCode::ArithmeticOverflow)?)
+}
@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
 use light_hasher::Poseidon;
 use anchor_lang::solana_program::sysvar::rent::Rent;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke_signed;
 use ark_ff::PrimeField;
 use ark_bn254::Fr;
 
@@ -10,12 +12,33 @@ pub mod merkle_tree;
 pub mod utils;
 pub mod groth16;
 pub mod errors;
+pub mod governance;
+pub mod multisig;
+pub mod cpi_helpers;
+pub mod commitment_queue;
+pub mod root_archive;
+pub mod solvency;
+pub mod deny_list;
+pub mod ragequit;
+pub mod pending_withdrawal;
+pub mod denomination;
+pub mod price_feed;
+pub mod velocity;
+pub mod migration;
 
 use merkle_tree::MerkleTree;
+use governance::ConfigChange;
+use multisig::{MultisigAction, MAX_MULTISIG_ACTION_ACCOUNTS, MAX_MULTISIG_MEMBERS};
+use commitment_queue::COMMITMENT_QUEUE_CAPACITY;
+use root_archive::ROOT_ARCHIVE_PAGE_SIZE;
+use deny_list::DENY_LIST_CAPACITY;
+use denomination::MAX_DENOMINATIONS;
+use velocity::VELOCITY_BUCKETS;
 
 // Constants
 const MERKLE_TREE_HEIGHT: u8 = 26;
 
+// Overrides the upgrade authority check in initialize when set
 #[cfg(any(feature = "localnet", test))]
 pub const ADMIN_PUBKEY: Option<Pubkey> = None;
 
@@ -24,14 +47,19 @@ pub const ADMIN_PUBKEY: Option<Pubkey> = Some(pubkey!("AWexibGxNFKTa1b5R5MN4PJr9
 
 #[program]
 pub mod zkcash {
-    use crate::utils::{verify_proof, VERIFYING_KEY};
-
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        if let Some(admin_key) = ADMIN_PUBKEY {
-            require!(ctx.accounts.authority.key().eq(&admin_key), ErrorCode::Unauthorized);
-        }
+    /**
+     * Pass no denominations for a variable-amount pool. Otherwise the pool is fixed-denomination:
+     * every deposit and withdrawal must move exactly one of them, so amounts cannot link the two.
+     * The mode cannot be changed afterwards.
+     */
+    pub fn initialize(ctx: Context<Initialize>, denominations: Vec<u64>) -> Result<()> {
+        utils::validate_initializer(
+            &ctx.accounts.authority.key(),
+            ADMIN_PUBKEY,
+            ctx.accounts.program_data.upgrade_authority_address,
+        )?;
         
         let tree_account = &mut ctx.accounts.tree_account.load_init()?;
         tree_account.authority = ctx.accounts.authority.key();
@@ -41,8 +69,10 @@ pub mod zkcash {
         tree_account.max_deposit_amount = 1_000_000_000_000; // 1000 SOL default limit
         tree_account.height = MERKLE_TREE_HEIGHT; // Hardcoded height
         tree_account.root_history_size = 100; // Hardcoded root history size
+        tree_account.generation = 0;
 
         MerkleTree::initialize::<Poseidon>(tree_account)?;
+        tree_account.root_history_slots[0] = Clock::get()?.slot;
         
         let token_account = &mut ctx.accounts.tree_token_account;
         token_account.authority = ctx.accounts.authority.key();
@@ -51,421 +81,2832 @@ pub mod zkcash {
         // Initialize global config
         let global_config = &mut ctx.accounts.global_config;
         global_config.authority = ctx.accounts.authority.key();
-        global_config.deposit_fee_rate = 0; // 0% - Free deposits
-        global_config.withdrawal_fee_rate = 25; // 0.25% (25 basis points)
+        global_config.deposit_fee = FeeSchedule::from_rate(0); // 0% - Free deposits
+        global_config.withdrawal_fee = FeeSchedule::from_rate(25); // 0.25% (25 basis points)
+        global_config.transfer_fee = FeeSchedule::from_rate(0); // Free internal transfers
         global_config.fee_error_margin = 500; // 5% (500 basis points)
+        global_config.config_timelock_slots = 0; // Changes apply immediately until a timelock is queued
+        global_config.next_config_change_id = 0;
+        global_config.max_archived_root_age_slots = 1_512_000; // ~7 days at 400ms slots
+        global_config.current_tree_generation = 0;
+        global_config.guardian = ctx.accounts.authority.key();
+        global_config.large_withdrawal_threshold = 0; // Withdrawals are paid out immediately until a threshold is set
+        global_config.withdrawal_delay_slots = 216_000; // ~1 day at 400ms slots
+        global_config.set_denominations(&denominations)?; // empty keeps variable amounts
+        global_config.price_feed = Pubkey::default(); // USD limits are off until a price feed is configured
+        global_config.max_price_age_slots = 150; // ~1 minute at 400ms slots
+        global_config.max_deposit_usd = 0;
+        global_config.max_tvl_usd = 0;
+        global_config.velocity_window_slots = 216_000; // ~1 day at 400ms slots
+        global_config.max_deposit_per_window = 0; // Velocity limits are off until a cap is set
         global_config.bump = ctx.bumps.global_config;
+
+        let deny_list = &mut ctx.accounts.deny_list.load_init()?;
+        deny_list.bump = ctx.bumps.deny_list;
         
         msg!("Sparse Merkle Tree initialized successfully with height: {}, root history size: {}, deposit limit: {} lamports, 
             deposit fee rate: {}, withdrawal fee rate: {}, fee error margin: {}",
-            MERKLE_TREE_HEIGHT, 100, tree_account.max_deposit_amount, global_config.deposit_fee_rate, global_config.withdrawal_fee_rate, global_config.fee_error_margin);
+            MERKLE_TREE_HEIGHT, 100, tree_account.max_deposit_amount, global_config.deposit_fee.rate_bps, global_config.withdrawal_fee.rate_bps, global_config.fee_error_margin);
+        Ok(())
+    }
+
+    /**
+     * Move a pool initialized by the first deployment to the current account layouts. Only the
+     * authority stored in the legacy global config can call this, and only once: the tree, vault
+     * and global config are grown in place (the authority pays the extra rent), keep their
+     * addresses and funds, and the deny list initialize would have created is created.
+     */
+    pub fn migrate_legacy_accounts(ctx: Context<MigrateLegacyAccounts>) -> Result<()> {
+        let tree_info = ctx.accounts.tree_account.to_account_info();
+        let token_info = ctx.accounts.tree_token_account.to_account_info();
+        let config_info = ctx.accounts.global_config.to_account_info();
+        let authority_info = ctx.accounts.authority.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        migration::check_legacy_layout::<MerkleTreeAccount>(&tree_info, migration::LEGACY_MERKLE_TREE_ACCOUNT_SIZE)?;
+        migration::check_legacy_layout::<TreeTokenAccount>(&token_info, migration::LEGACY_TREE_TOKEN_ACCOUNT_SIZE)?;
+        migration::check_legacy_layout::<GlobalConfig>(&config_info, migration::LEGACY_GLOBAL_CONFIG_SIZE)?;
+
+        let legacy_config = migration::LegacyGlobalConfig::deserialize(&mut &config_info.try_borrow_data()?[8..])?;
+        require!(legacy_config.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        let legacy_token = migration::LegacyTreeTokenAccount::deserialize(&mut &token_info.try_borrow_data()?[8..])?;
+        let legacy_tree: migration::LegacyMerkleTreeAccount =
+            bytemuck::pod_read_unaligned(&tree_info.try_borrow_data()?[8..]);
+
+        let tree_account = legacy_tree.migrate(Clock::get()?.slot);
+        let tree_len = 8 + std::mem::size_of::<MerkleTreeAccount>();
+        migration::grow_account(&tree_info, tree_len, &authority_info, &system_program_info)?;
+        tree_info.try_borrow_mut_data()?[8..].copy_from_slice(bytemuck::bytes_of(&tree_account));
+
+        let token_len = 8 + std::mem::size_of::<TreeTokenAccount>();
+        migration::grow_account(&token_info, token_len, &authority_info, &system_program_info)?;
+        let shielded = token_info.lamports()
+            .checked_sub(Rent::get()?.minimum_balance(token_len))
+            .ok_or(ErrorCode::InvariantViolated)?;
+        let tree_token_account = legacy_token.migrate(shielded);
+        tree_token_account.try_serialize(&mut &mut token_info.try_borrow_mut_data()?[..])?;
+
+        let global_config = legacy_config.migrate();
+        let config_len = 8 + std::mem::size_of::<GlobalConfig>();
+        migration::grow_account(&config_info, config_len, &authority_info, &system_program_info)?;
+        global_config.try_serialize(&mut &mut config_info.try_borrow_mut_data()?[..])?;
+
+        let deny_list = &mut ctx.accounts.deny_list.load_init()?;
+        deny_list.bump = ctx.bumps.deny_list;
+
+        msg!("Migrated legacy pool: {} leaves, {} roots recorded", tree_account.next_index, tree_account.root_count);
         Ok(())
     }
 
     /**
-     * Update the maximum deposit amount limit. Only the authority can call this.
+     * Update the maximum deposit amount limit. Only the authority can call this,
+     * and only while no config timelock is set (otherwise use queue_config_change).
      */
     pub fn update_deposit_limit(ctx: Context<UpdateDepositLimit>, new_limit: u64) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
         let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        require!(global_config.config_timelock_slots == 0, ErrorCode::ConfigTimelocked);
+
+        let change = ConfigChange::DepositLimit { max_deposit_amount: new_limit };
+        change.validate()?;
+        change.apply(global_config, tree_account);
         
-        tree_account.max_deposit_amount = new_limit;
-        
-        msg!("Deposit limit updated to: {} lamports", new_limit);
         Ok(())
     }
 
     /**
-     * Update global configuration. Only the authority can call this.
+     * Update global configuration. Only the authority can call this,
+     * and only while no config timelock is set (otherwise use queue_config_change).
      */
     pub fn update_global_config(
         ctx: Context<UpdateGlobalConfig>, 
-        deposit_fee_rate: Option<u16>,
-        withdrawal_fee_rate: Option<u16>,
+        deposit_fee: Option<FeeSchedule>,
+        withdrawal_fee: Option<FeeSchedule>,
+        transfer_fee: Option<FeeSchedule>,
         fee_error_margin: Option<u16>
     ) -> Result<()> {
         let global_config = &mut ctx.accounts.global_config;
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        require!(global_config.config_timelock_slots == 0, ErrorCode::ConfigTimelocked);
+
+        let change = ConfigChange::Fees {
+            deposit_fee,
+            withdrawal_fee,
+            transfer_fee,
+            fee_error_margin,
+        };
+        change.validate()?;
+        change.apply(global_config, tree_account);
         
-        if let Some(deposit_rate) = deposit_fee_rate {
-            require!(deposit_rate <= 10000, ErrorCode::InvalidFeeRate);
-            global_config.deposit_fee_rate = deposit_rate;
-            msg!("Deposit fee rate updated to: {} basis points", deposit_rate);
-        }
-        
-        if let Some(withdrawal_rate) = withdrawal_fee_rate {
-            require!(withdrawal_rate <= 10000, ErrorCode::InvalidFeeRate);
-            global_config.withdrawal_fee_rate = withdrawal_rate;
-            msg!("Withdrawal fee rate updated to: {} basis points", withdrawal_rate);
-        }
-        
-        if let Some(fee_error_margin_val) = fee_error_margin {
-            require!(fee_error_margin_val <= 10000, ErrorCode::InvalidFeeRate);
-            global_config.fee_error_margin = fee_error_margin_val;
-            msg!("Fee error margin updated to: {} basis points", fee_error_margin_val);
-        }
-        
         Ok(())
     }
 
+    /**
+     * Publish a price on the caller's mock price feed, creating it on first use. For local testing
+     * of the USD limits; a feed is only read once the authority configures it as the price feed.
+     */
+    #[cfg(feature = "localnet")]
+    pub fn set_mock_price(ctx: Context<SetMockPrice>, micro_usd_per_sol: u64) -> Result<()> {
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.authority = ctx.accounts.authority.key();
+        price_feed.micro_usd_per_sol = micro_usd_per_sol;
+        price_feed.publish_slot = Clock::get()?.slot;
+        price_feed.bump = ctx.bumps.price_feed;
+
+        Ok(())
+    }
+
+    /**
+     * Add an address to the deposit deny list. Deposits funded by a denied address are rejected.
+     * Only the authority can call this; screening is not subject to the config timelock.
+     * The list holds at most deny_list::DENY_LIST_CAPACITY addresses.
+     */
+    pub fn add_to_denylist(ctx: Context<UpdateDenyList>, address: Pubkey) -> Result<()> {
+        ctx.accounts.deny_list.load_mut()?.insert(&address)?;
+        emit!(DenyListUpdated { address, denied: true });
+        Ok(())
+    }
+
+    /**
+     * Remove an address from the deposit deny list. Only the authority can call this.
+     */
+    pub fn remove_from_denylist(ctx: Context<UpdateDenyList>, address: Pubkey) -> Result<()> {
+        ctx.accounts.deny_list.load_mut()?.remove(&address)?;
+        emit!(DenyListUpdated { address, denied: false });
+        Ok(())
+    }
+
+    /**
+     * Queue a configuration change. It can be executed by anyone once
+     * config_timelock_slots have passed, and cancelled by the authority before that.
+     */
+    pub fn queue_config_change(ctx: Context<QueueConfigChange>, change: ConfigChange) -> Result<()> {
+        change.validate()?;
+
+        let global_config = &mut ctx.accounts.global_config;
+        let current_slot = Clock::get()?.slot;
+        let effective_slot = governance::calculate_effective_slot(current_slot, global_config.config_timelock_slots)?;
+
+        let pending_change = &mut ctx.accounts.pending_config_change;
+        pending_change.id = global_config.next_config_change_id;
+        pending_change.change = change.clone();
+        pending_change.queued_slot = current_slot;
+        pending_change.effective_slot = effective_slot;
+        pending_change.bump = ctx.bumps.pending_config_change;
+
+        global_config.next_config_change_id = global_config.next_config_change_id
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ConfigChangeQueued {
+            id: pending_change.id,
+            change,
+            queued_slot: current_slot,
+            effective_slot,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Execute a queued configuration change once its effective slot is reached.
+     * Anyone can call this; the rent of the pending change goes back to the authority.
+     */
+    pub fn execute_config_change(ctx: Context<ExecuteConfigChange>) -> Result<()> {
+        let pending_change = &ctx.accounts.pending_config_change;
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot >= pending_change.effective_slot,
+            ErrorCode::ConfigChangeNotReady
+        );
+
+        let global_config = &mut ctx.accounts.global_config;
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        pending_change.change.apply(global_config, tree_account);
+
+        emit!(ConfigChangeExecuted {
+            id: pending_change.id,
+            change: pending_change.change.clone(),
+            effective_slot: pending_change.effective_slot,
+            executed_slot: current_slot,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Cancel a queued configuration change. Only the authority can call this.
+     */
+    pub fn cancel_config_change(ctx: Context<CancelConfigChange>, id: u64) -> Result<()> {
+        let pending_change = &ctx.accounts.pending_config_change;
+
+        emit!(ConfigChangeCancelled {
+            id,
+            change: pending_change.change.clone(),
+            effective_slot: pending_change.effective_slot,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Switch pool administration to an M-of-N multisig. Only the authority can call this.
+     * The multisig PDA becomes the authority of the global config, the tree and the tree token account,
+     * and runs admin instructions through execute_multisig_proposal.
+     */
+    pub fn create_multisig(ctx: Context<CreateMultisig>, members: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        require!(ctx.accounts.global_config.config_timelock_slots == 0, ErrorCode::ConfigTimelocked);
+
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.nonce = 0;
+        multisig.next_proposal_id = 0;
+        multisig.bump = ctx.bumps.multisig;
+        multisig.set_members(&members, threshold)?;
+
+        let multisig_key = multisig.key();
+        ctx.accounts.global_config.authority = multisig_key;
+        ctx.accounts.tree_account.load_mut()?.authority = multisig_key;
+        ctx.accounts.tree_token_account.authority = multisig_key;
+
+        emit!(MultisigMembersUpdated {
+            members,
+            threshold,
+            nonce: multisig.nonce,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Propose an admin action. Only multisig members can call this; the proposer's approval is counted.
+     * `accounts` are the accounts of the admin instruction, in the order it expects them; members
+     * approve them together with the action, and execute_multisig_proposal only runs with them.
+     */
+    pub fn propose_multisig_action(ctx: Context<ProposeMultisigAction>, action: MultisigAction, accounts: Vec<Pubkey>) -> Result<()> {
+        multisig::validate_action_accounts(&accounts)?;
+        let multisig = &mut ctx.accounts.multisig;
+        let member_index = multisig.member_index(&ctx.accounts.proposer.key())
+            .ok_or(ErrorCode::NotMultisigMember)?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.id = multisig.next_proposal_id;
+        proposal.action = action.clone();
+        proposal.accounts = accounts.clone();
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.multisig_nonce = multisig.nonce;
+        proposal.approvals = 1 << member_index;
+        proposal.bump = ctx.bumps.proposal;
+
+        multisig.next_proposal_id = multisig.next_proposal_id
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(MultisigProposalCreated {
+            id: proposal.id,
+            action,
+            accounts,
+            proposer: proposal.proposer,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Approve a multisig proposal. Only multisig members can call this.
+     */
+    pub fn approve_multisig_proposal(ctx: Context<ApproveMultisigProposal>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(proposal.multisig_nonce == multisig.nonce, ErrorCode::StaleMultisigProposal);
+
+        let member_index = multisig.member_index(&ctx.accounts.member.key())
+            .ok_or(ErrorCode::NotMultisigMember)?;
+        let member_bit = 1u16 << member_index;
+        require!(proposal.approvals & member_bit == 0, ErrorCode::MultisigProposalAlreadyApproved);
+        proposal.approvals |= member_bit;
+
+        emit!(MultisigProposalApproved {
+            id: proposal.id,
+            member: ctx.accounts.member.key(),
+            approval_count: proposal.approvals.count_ones() as u8,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Execute a multisig proposal once the threshold is met. Only multisig members can call this.
+     * 
+     * The approved admin instruction is invoked on this program with the multisig PDA as signing authority.
+     * Its accounts are passed as remaining_accounts and must be the ones bound in the proposal,
+     * so the instruction's own account constraints apply unchanged.
+     */
+    pub fn execute_multisig_proposal<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteMultisigProposal<'info>>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &ctx.accounts.proposal;
+        require!(proposal.multisig_nonce == multisig.nonce, ErrorCode::StaleMultisigProposal);
+        require!(
+            multisig.member_index(&ctx.accounts.member.key()).is_some(),
+            ErrorCode::NotMultisigMember
+        );
+        require!(multisig.is_approved(proposal.approvals), ErrorCode::MultisigThresholdNotMet);
+        proposal.check_accounts(ctx.remaining_accounts)?;
+
+        let multisig_key = multisig.key();
+        let accounts = ctx.remaining_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer || account.key() == multisig_key,
+                is_writable: account.is_writable,
+            })
+            .collect();
+        let instruction = Instruction {
+            program_id: crate::ID,
+            accounts,
+            data: proposal.action.instruction_data(),
+        };
+
+        let mut account_infos = ctx.remaining_accounts.to_vec();
+        account_infos.push(ctx.accounts.zkcash_program.to_account_info());
+        invoke_signed(&instruction, &account_infos, &[&[b"multisig", &[multisig.bump]]])?;
+
+        emit!(MultisigProposalExecuted {
+            id: proposal.id,
+            action: proposal.action.clone(),
+        });
+
+        // The invoked instruction may have changed the multisig (e.g. SetMultisigMembers)
+        ctx.accounts.multisig.reload()?;
+
+        Ok(())
+    }
+
+    /**
+     * Replace the multisig member set and threshold. Only the multisig itself can call this,
+     * through execute_multisig_proposal. Open proposals become stale.
+     */
+    pub fn set_multisig_members(ctx: Context<SetMultisigMembers>, members: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.set_members(&members, threshold)?;
+
+        emit!(MultisigMembersUpdated {
+            members,
+            threshold,
+            nonce: multisig.nonce,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Close a proposal left stale by a member set change and return its rent to the proposer.
+     * Anyone can call this. Executed proposals are already closed by execute_multisig_proposal.
+     */
+    pub fn close_multisig_proposal(ctx: Context<CloseMultisigProposal>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        require!(ctx.accounts.multisig.is_stale(proposal), ErrorCode::MultisigProposalNotStale);
+
+        emit!(MultisigProposalClosed {
+            id: proposal.id,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Quote the fee for an operation moving ext_amount in (positive) or out (negative) of the pool.
+     * Read-only: meant to be simulated, the quote is returned through the transaction return data.
+     */
+    pub fn quote_fee(ctx: Context<QuoteFee>, ext_amount: i64) -> Result<FeeQuote> {
+        let global_config = &ctx.accounts.global_config;
+
+        utils::calculate_fee_quote(
+            ext_amount,
+            &global_config.deposit_fee,
+            &global_config.withdrawal_fee,
+            &global_config.transfer_fee,
+            global_config.fee_error_margin,
+        )
+    }
+
+    /**
+     * Return the current tree and fee configuration. Read-only, meant to be simulated.
+     */
+    pub fn get_pool_state(ctx: Context<GetPoolState>) -> Result<PoolState> {
+        let tree_account = ctx.accounts.tree_account.load()?;
+        let global_config = &ctx.accounts.global_config;
+
+        Ok(PoolState {
+            authority: tree_account.authority,
+            next_index: tree_account.next_index,
+            root: tree_account.root,
+            root_index: tree_account.root_index,
+            height: tree_account.height,
+            max_deposit_amount: tree_account.max_deposit_amount,
+            deposit_fee: global_config.deposit_fee,
+            withdrawal_fee: global_config.withdrawal_fee,
+            transfer_fee: global_config.transfer_fee,
+            fee_error_margin: global_config.fee_error_margin,
+            config_timelock_slots: global_config.config_timelock_slots,
+        })
+    }
+
+    /**
+     * Return whether root is in the root history, i.e. whether transact would accept it.
+     * Read-only, meant to be simulated.
+     */
+    pub fn is_root_known(ctx: Context<IsRootKnown>, root: [u8; 32]) -> Result<bool> {
+        let tree_account = ctx.accounts.tree_account.load()?;
+
+        Ok(MerkleTree::is_known_root(&tree_account, root))
+    }
+
     /**
      * Users deposit or withdraw from the program.
      * 
      * Reentrant attacks are not possible, because nullifier creation is checked by anchor first.
      * 
      * encrypted_output1 and encrypted_output2 are passed as separate parameters to save instruction data space (~170 bytes).
+     * 
+     * tree_index is the generation of the tree proof.root belongs to. Outputs always go to the
+     * newest tree; a proof against an older generation also needs that tree as root_tree.
      */
-    pub fn transact(ctx: Context<Transact>, proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>) -> Result<()> {
+    pub fn transact(ctx: Context<Transact>, proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64) -> Result<()> {
         let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
         let global_config = &ctx.accounts.global_config;
 
         // Reconstruct full ExtData from minified version and context accounts
-        let ext_data = ExtData::from_minified(&ctx, ext_data_minified);
-
-        // check if proof.root is in the tree_account's proof history
-        require!(
-            MerkleTree::is_known_root(&tree_account, proof.root),
-            ErrorCode::UnknownRoot
-        );
-
-        // check if the ext_data hashes to the same ext_data in the proof
-        let calculated_ext_data_hash = utils::calculate_complete_ext_data_hash(
-            ext_data.recipient,
-            ext_data.ext_amount,
-            &encrypted_output1,
-            &encrypted_output2,
-            ext_data.fee,
-            ext_data.fee_recipient,
-            ext_data.mint_address,
-        )?;
-
-        require!(
-            Fr::from_le_bytes_mod_order(&calculated_ext_data_hash) == Fr::from_be_bytes_mod_order(&proof.ext_data_hash),
-            ErrorCode::ExtDataHashMismatch
+        let ext_data = ExtData::from_minified(
+            ctx.accounts.recipient.key(),
+            ctx.accounts.fee_recipient_account.key(),
+            ext_data_minified,
         );
 
-        require!(
-            utils::check_public_amount(ext_data.ext_amount, ext_data.fee, proof.public_amount),
-            ErrorCode::InvalidPublicAmountData
-        );
+        let proof_tree = ProofTree {
+            tree_index,
+            tree: ctx.accounts.tree_account.key(),
+            root_tree: ctx.accounts.root_tree.as_ref(),
+            root_archive: ctx.accounts.root_archive.as_ref(),
+        };
+        verify_transaction_in_tree(tree_account, &proof_tree, global_config, &proof, &ext_data, &encrypted_output1, &encrypted_output2)?;
         
         let ext_amount = ext_data.ext_amount;
         let fee = ext_data.fee;
 
-        // Validate fee calculation using utility function
-        utils::validate_fee(
-            ext_amount,
-            fee,
-            global_config.deposit_fee_rate,
-            global_config.withdrawal_fee_rate,
-            global_config.fee_error_margin,
-        )?;
-
-        // verify the proof
-        require!(verify_proof(proof.clone(), VERIFYING_KEY), ErrorCode::InvalidProof);
-
         let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
         let rent = Rent::get()?;
         let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
 
+        let mut delayed_withdrawal = None;
         if ext_amount > 0 {
-            // Check deposit limit for deposits
-            let deposit_amount = ext_amount as u64;
-            require!(
-                deposit_amount <= tree_account.max_deposit_amount,
-                ErrorCode::DepositLimitExceeded
-            );
-            
+            deny_list::check_depositor(&*ctx.accounts.deny_list.load()?, &ctx.accounts.signer.key())?;
+            // USD limits need a fresh price: the feed's staleness is checked here
+            let price = price_feed::load_price(global_config, ctx.accounts.price_feed.as_deref())?;
+            global_config.check_usd_limits(price, ext_amount as u64, ctx.accounts.tree_token_account.net_shielded()?, Clock::get()?.slot)?;
+            // Caps the signer's volume across transactions, not just per deposit
+            velocity::record_deposit(
+                ctx.accounts.depositor_velocity.as_deref_mut(),
+                ctx.bumps.depositor_velocity,
+                global_config,
+                ctx.accounts.signer.key(),
+                ext_amount as u64,
+                Clock::get()?.slot,
+            )?;
             // If it's a deposit, transfer the SOL to the tree token account.
-            anchor_lang::system_program::transfer(
-                CpiContext::new(
-                    ctx.accounts.system_program.to_account_info(),
-                    anchor_lang::system_program::Transfer {
-                        from: ctx.accounts.signer.to_account_info(),
-                        to: ctx.accounts.tree_token_account.to_account_info(),
-                    },
-                ),
+            deposit_to_pool(
+                &ctx.accounts.system_program,
+                ctx.accounts.signer.to_account_info(),
+                tree_token_account_info.clone(),
                 ext_amount as u64,
+                tree_account.max_deposit_amount,
             )?;
         } else if ext_amount < 0 {
-            // PDA can't directly sign transactions, so we need to transfer SOL via try_borrow_mut_lamports
-            // No limit on withdrawals
-            let recipient_account_info = ctx.accounts.recipient.to_account_info();
-
             let ext_amount_abs: u64 = ext_amount.checked_neg()
                 .ok_or(ErrorCode::ArithmeticOverflow)?
                 .try_into()
                 .map_err(|_| ErrorCode::InvalidExtAmount)?;
-            
-            let total_required = ext_amount_abs
-                .checked_add(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?
-                .checked_add(rent_exempt_minimum)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            
-            require!(
-                tree_token_account_info.lamports() >= total_required,
-                ErrorCode::InsufficientFundsForWithdrawal
-            );
 
-            let tree_token_balance = tree_token_account_info.lamports();
-            let recipient_balance = recipient_account_info.lamports();
-            
-            let new_tree_token_balance = tree_token_balance.checked_sub(ext_amount_abs)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            let new_recipient_balance = recipient_balance.checked_add(ext_amount_abs)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-                
-            **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
-            **recipient_account_info.try_borrow_mut_lamports()? = new_recipient_balance;
+            check_withdrawal_funds(&tree_token_account_info, ext_amount_abs, fee, rent_exempt_minimum)?;
+            // Withdrawals above the threshold are paid out by claim_withdrawal after the delay
+            delayed_withdrawal = global_config.delay_withdrawal(ext_amount_abs, Clock::get()?.slot)?;
+            if delayed_withdrawal.is_none() {
+                withdraw_from_pool(&tree_token_account_info, &ctx.accounts.recipient.to_account_info(), ext_amount_abs)?;
+            }
         }
         
-        if fee > 0 {
-            let fee_recipient_account_info = ctx.accounts.fee_recipient_account.to_account_info();
-
-            if ext_amount >= 0 {
-                let total_required = fee
-                    .checked_add(rent_exempt_minimum)
-                    .ok_or(ErrorCode::ArithmeticOverflow)?;
-                
-                require!(
-                    tree_token_account_info.lamports() >= total_required,
-                    ErrorCode::InsufficientFundsForFee
-                );
-            }
+        pay_fee(
+            &tree_token_account_info,
+            &ctx.accounts.fee_recipient_account.to_account_info(),
+            fee,
+            ext_amount,
+            rent_exempt_minimum,
+        )?;
+
+        ctx.accounts.tree_token_account.record_transaction(ext_amount, fee)?;
+        ctx.accounts.tree_token_account.record_denomination(global_config, ext_amount)?;
+
+        ragequit::record_deposit_origin(
+            ctx.accounts.deposit_origin.as_deref_mut(),
+            ctx.bumps.deposit_origin,
+            ext_amount,
+            fee,
+            ctx.accounts.signer.key(),
+            proof.output_commitments,
+            Some(tree_account.next_index),
+        )?;
 
-            let tree_token_balance = tree_token_account_info.lamports();
-            let fee_recipient_balance = fee_recipient_account_info.lamports();
-            
-            let new_tree_token_balance = tree_token_balance.checked_sub(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            let new_fee_recipient_balance = fee_recipient_balance.checked_add(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-                
-            **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
-            **fee_recipient_account_info.try_borrow_mut_lamports()? = new_fee_recipient_balance;
+        pending_withdrawal::record_pending_withdrawal(
+            ctx.accounts.pending_withdrawal.as_deref_mut(),
+            ctx.bumps.pending_withdrawal,
+            delayed_withdrawal,
+            ctx.accounts.recipient.key(),
+            ctx.accounts.signer.key(),
+            proof.input_nullifiers[0],
+        )?;
+        if let Some(delayed) = delayed_withdrawal {
+            emit!(WithdrawalQueued {
+                nullifier: proof.input_nullifiers[0],
+                recipient: ctx.accounts.recipient.key(),
+                amount: delayed.amount,
+                release_slot: delayed.release_slot,
+            });
         }
 
-        let next_index_to_insert = tree_account.next_index;
-        MerkleTree::append::<Poseidon>(proof.output_commitments[0], tree_account)?;
-        MerkleTree::append::<Poseidon>(proof.output_commitments[1], tree_account)?;
+        append_commitments(tree_account, &proof.output_commitments, vec![encrypted_output1, encrypted_output2])
+    }
 
-        let second_index = next_index_to_insert.checked_add(1)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    /**
+     * Pay out a withdrawal queued by transact or ragequit once its delay has passed. Anyone can call this;
+     * the rent of the pending withdrawal goes back to whoever paid it.
+     */
+    pub fn claim_withdrawal(ctx: Context<ClaimWithdrawal>) -> Result<()> {
+        let pending_withdrawal = &ctx.accounts.pending_withdrawal;
+        require!(!pending_withdrawal.cancelled, ErrorCode::PendingWithdrawalCancelled);
+        require!(pending_withdrawal.is_claimable(Clock::get()?.slot), ErrorCode::WithdrawalStillDelayed);
 
-        emit!(CommitmentData {
-            index: next_index_to_insert,
-            commitment: proof.output_commitments[0],
-            encrypted_output: encrypted_output1.to_vec(),
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+
+        check_withdrawal_funds(&tree_token_account_info, pending_withdrawal.amount, 0, rent_exempt_minimum)?;
+        withdraw_from_pool(&tree_token_account_info, &ctx.accounts.recipient.to_account_info(), pending_withdrawal.amount)?;
+
+        emit!(WithdrawalClaimed {
+            nullifier: pending_withdrawal.nullifier,
+            recipient: pending_withdrawal.recipient,
+            amount: pending_withdrawal.amount,
         });
 
-        emit!(CommitmentData {
-            index: second_index,
-            commitment: proof.output_commitments[1],
-            encrypted_output: encrypted_output2.to_vec(),
+        Ok(())
+    }
+
+    /**
+     * Cancel a queued withdrawal during its delay, e.g. when an exploit is detected. Only the
+     * guardian can call this. The nullifiers stay spent and the lamports stay in the pool until
+     * resolve_cancelled_withdrawal pays them out.
+     */
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        let pending_withdrawal = &mut ctx.accounts.pending_withdrawal;
+        require!(!pending_withdrawal.cancelled, ErrorCode::PendingWithdrawalCancelled);
+        require!(!pending_withdrawal.is_claimable(Clock::get()?.slot), ErrorCode::WithdrawalDelayElapsed);
+        pending_withdrawal.cancelled = true;
+
+        emit!(WithdrawalCancelled {
+            nullifier: pending_withdrawal.nullifier,
+            recipient: pending_withdrawal.recipient,
+            amount: pending_withdrawal.amount,
         });
-        
+
         Ok(())
     }
-}
 
-#[event]
-pub struct CommitmentData {
-    pub index: u64,
-    pub commitment: [u8; 32],
-    pub encrypted_output: Vec<u8>,
-}
+    /**
+     * Pay out the cancelled withdrawal of `nullifier` to a recipient chosen by the authority, e.g.
+     * the original recipient once cleared or the victim of an exploit. Only the authority (the
+     * multisig once handed over) can call this. The rent of the pending withdrawal goes back to
+     * whoever paid it.
+     */
+    pub fn resolve_cancelled_withdrawal(ctx: Context<ResolveCancelledWithdrawal>, nullifier: [u8; 32], recipient: Pubkey) -> Result<()> {
+        let pending_withdrawal = &ctx.accounts.pending_withdrawal;
+        require!(pending_withdrawal.cancelled, ErrorCode::PendingWithdrawalNotCancelled);
 
-// all public inputs needs to be in big endian format
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Proof {
-    pub proof_a: [u8; 64],
-    pub proof_b: [u8; 128],
-    pub proof_c: [u8; 64],
-    pub root: [u8; 32],
-    pub public_amount: [u8; 32],
-    pub ext_data_hash: [u8; 32],
-    pub input_nullifiers: [[u8; 32]; 2],
-    pub output_commitments: [[u8; 32]; 2],
-}
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ExtData {
-    pub recipient: Pubkey,
-    pub ext_amount: i64,
-    pub fee: u64,
-    pub fee_recipient: Pubkey,
-    pub mint_address: Pubkey,
-}
+        check_withdrawal_funds(&tree_token_account_info, pending_withdrawal.amount, 0, rent_exempt_minimum)?;
+        withdraw_from_pool(&tree_token_account_info, &ctx.accounts.recipient_account.to_account_info(), pending_withdrawal.amount)?;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ExtDataMinified {
-    pub ext_amount: i64,
-    pub fee: u64,
-}
+        emit!(CancelledWithdrawalResolved {
+            nullifier,
+            recipient,
+            amount: pending_withdrawal.amount,
+        });
 
-impl ExtData {
-    fn from_minified(ctx: &Context<Transact>, minified: ExtDataMinified) -> Self {
-        use crate::utils::SOL_ADDRESS;
-        Self {
-            recipient: ctx.accounts.recipient.key(),
-            ext_amount: minified.ext_amount,
-            fee: minified.fee,
-            fee_recipient: ctx.accounts.fee_recipient_account.key(),
-            mint_address: SOL_ADDRESS,
+        Ok(())
+    }
+
+    /**
+     * Create a commitment queue shard for transact_queued. Only the authority can call this.
+     * Each shard is a separate account, so transactions using different shards do not contend
+     * for the same write lock.
+     */
+    pub fn initialize_commitment_queue(ctx: Context<InitializeCommitmentQueue>, shard: u8) -> Result<()> {
+        let commitment_queue = &mut ctx.accounts.commitment_queue.load_init()?;
+        commitment_queue.shard = shard;
+        commitment_queue.bump = ctx.bumps.commitment_queue;
+
+        Ok(())
+    }
+
+    /**
+     * Same as transact, but the output commitments go into a commitment queue shard instead of the tree.
+     * The tree is only read (root check), so concurrent transactions on different shards do not
+     * serialize on the tree's write lock. The outputs become spendable once process_commitment_queue
+     * has inserted them. tree_index and root_tree work as in transact. A shard holds at most
+     * commitment_queue::COMMITMENT_QUEUE_CAPACITY commitments; see there for when it is full.
+     */
+    pub fn transact_queued(ctx: Context<TransactQueued>, proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64) -> Result<()> {
+        let tree_account = &ctx.accounts.tree_account.load()?;
+        let commitment_queue = &mut ctx.accounts.commitment_queue.load_mut()?;
+        let global_config = &ctx.accounts.global_config;
+
+        let ext_data = ExtData::from_minified(
+            ctx.accounts.recipient.key(),
+            ctx.accounts.fee_recipient_account.key(),
+            ext_data_minified,
+        );
+
+        let proof_tree = ProofTree {
+            tree_index,
+            tree: ctx.accounts.tree_account.key(),
+            root_tree: ctx.accounts.root_tree.as_ref(),
+            root_archive: None,
+        };
+        verify_transaction_in_tree(tree_account, &proof_tree, global_config, &proof, &ext_data, &encrypted_output1, &encrypted_output2)?;
+
+        let ext_amount = ext_data.ext_amount;
+        let fee = ext_data.fee;
+
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+
+        if ext_amount > 0 {
+            deny_list::check_depositor(&*ctx.accounts.deny_list.load()?, &ctx.accounts.signer.key())?;
+            let price = price_feed::load_price(global_config, ctx.accounts.price_feed.as_deref())?;
+            global_config.check_usd_limits(price, ext_amount as u64, ctx.accounts.tree_token_account.net_shielded()?, Clock::get()?.slot)?;
+            velocity::record_deposit(
+                ctx.accounts.depositor_velocity.as_deref_mut(),
+                ctx.bumps.depositor_velocity,
+                global_config,
+                ctx.accounts.signer.key(),
+                ext_amount as u64,
+                Clock::get()?.slot,
+            )?;
+            deposit_to_pool(
+                &ctx.accounts.system_program,
+                ctx.accounts.signer.to_account_info(),
+                tree_token_account_info.clone(),
+                ext_amount as u64,
+                tree_account.max_deposit_amount,
+            )?;
+        } else if ext_amount < 0 {
+            let ext_amount_abs: u64 = ext_amount.checked_neg()
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                .try_into()
+                .map_err(|_| ErrorCode::InvalidExtAmount)?;
+
+            check_withdrawal_funds(&tree_token_account_info, ext_amount_abs, fee, rent_exempt_minimum)?;
+            global_config.check_immediate_withdrawal(ext_amount_abs)?;
+            withdraw_from_pool(&tree_token_account_info, &ctx.accounts.recipient.to_account_info(), ext_amount_abs)?;
+        }
+
+        pay_fee(
+            &tree_token_account_info,
+            &ctx.accounts.fee_recipient_account.to_account_info(),
+            fee,
+            ext_amount,
+            rent_exempt_minimum,
+        )?;
+
+        ctx.accounts.tree_token_account.record_transaction(ext_amount, fee)?;
+        ctx.accounts.tree_token_account.record_denomination(global_config, ext_amount)?;
+
+        // The outputs' leaf indices are only known once process_commitment_queue inserts them
+        ragequit::record_deposit_origin(
+            ctx.accounts.deposit_origin.as_deref_mut(),
+            ctx.bumps.deposit_origin,
+            ext_amount,
+            fee,
+            ctx.accounts.signer.key(),
+            proof.output_commitments,
+            None,
+        )?;
+
+        for (commitment, encrypted_output) in proof.output_commitments.iter().zip([encrypted_output1, encrypted_output2]) {
+            commitment_queue.push(*commitment, ext_amount > 0)?;
+
+            emit!(CommitmentQueued {
+                shard: commitment_queue.shard,
+                commitment: *commitment,
+                encrypted_output,
+            });
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Insert up to max_leaves queued commitments into the tree, oldest first. Anyone can call this.
+     * The root history advances once per call rather than once per leaf. Deposit outputs record
+     * their leaf index for ragequit: the deposit origin record of each one is passed writable as
+     * remaining_accounts, in queue order.
+     */
+    pub fn process_commitment_queue<'info>(ctx: Context<'_, '_, 'info, 'info, ProcessCommitmentQueue<'info>>, max_leaves: u16) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        let commitment_queue = &mut ctx.accounts.commitment_queue.load_mut()?;
+
+        let queued = commitment_queue.pop_front(max_leaves as usize);
+        require!(!queued.is_empty(), ErrorCode::CommitmentQueueEmpty);
+
+        let start_index = tree_account.next_index;
+        ragequit::record_queued_leaf_indices(&queued, start_index, ctx.remaining_accounts)?;
+        let commitments: Vec<[u8; 32]> = queued.iter().map(|(commitment, _)| *commitment).collect();
+        MerkleTree::append_batch::<Poseidon>(&commitments, tree_account, Clock::get()?.slot)?;
+
+        emit!(QueuedCommitmentsInserted {
+            shard: commitment_queue.shard,
+            start_index,
+            commitments,
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Create the root archive page `page` of the tree. Anyone can call this; the payer covers the rent.
+     */
+    pub fn initialize_root_archive(ctx: Context<InitializeRootArchive>, page: u64) -> Result<()> {
+        let root_archive = &mut ctx.accounts.root_archive.load_init()?;
+        root_archive.tree = ctx.accounts.tree_account.key();
+        root_archive.page = page;
+        root_archive.bump = ctx.bumps.root_archive;
+
+        Ok(())
+    }
+
+    /**
+     * Copy the roots recorded since the last call into the current archive page, so they stay
+     * usable in transact after the root history wraps around. Anyone can call this, and it must
+     * run at least once every root_history_size roots for the archive to be complete.
+     * Appends never wait for it: roots evicted before being archived are skipped, and notes
+     * proven against them need a proof against a newer root.
+     */
+    pub fn archive_roots(ctx: Context<ArchiveRoots>) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        let root_archive = &mut ctx.accounts.root_archive.load_mut()?;
+
+        let archived = root_archive::archive_roots(tree_account, root_archive)?;
+        require!(archived > 0, ErrorCode::NoRootsToArchive);
+
+        msg!("Archived {} roots in page {}", archived, root_archive.page);
+        Ok(())
+    }
+
+    /**
+     * Check that the vault covers all outstanding notes plus its rent-exempt minimum, and emit
+     * the figures. Anyone can call this; it fails if the invariant does not hold.
+     */
+    pub fn check_invariants(ctx: Context<CheckInvariants>) -> Result<()> {
+        let tree_token_account = &ctx.accounts.tree_token_account;
+        let tree_token_account_info = tree_token_account.to_account_info();
+        let vault_balance = tree_token_account_info.lamports();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(tree_token_account_info.data_len());
+
+        emit!(SolvencyReport {
+            vault_balance,
+            total_deposited: tree_token_account.total_deposited,
+            total_withdrawn: tree_token_account.total_withdrawn,
+            total_fees: tree_token_account.total_fees,
+            rent_exempt_minimum,
+        });
+
+        solvency::check_solvency(tree_token_account, vault_balance, rent_exempt_minimum)
+    }
+
+    /**
+     * Open the next generation of the tree once the newest one is nearly full (see
+     * merkle_tree::ROLLOVER_THRESHOLD). New commitments go to the new tree, while roots of
+     * older trees stay spendable through transact's tree_index. Anyone can call this.
+     */
+    pub fn rollover_tree(ctx: Context<RolloverTree>) -> Result<()> {
+        let tree_account = &ctx.accounts.tree_account.load()?;
+        let new_tree_account = &mut ctx.accounts.new_tree_account.load_init()?;
+
+        MerkleTree::initialize_successor::<Poseidon>(tree_account, new_tree_account)?;
+        new_tree_account.bump = ctx.bumps.new_tree_account;
+        new_tree_account.root_history_slots[0] = Clock::get()?.slot;
+
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.current_tree_generation = new_tree_account.generation;
+
+        emit!(TreeRolledOver {
+            generation: new_tree_account.generation,
+            tree: ctx.accounts.new_tree_account.key(),
+        });
+
+        Ok(())
+    }
+
+    /**
+     * Withdraw to several recipients with a single proof.
+     * 
+     * Works like a transact withdrawal, but the ext data commits to a list of (recipient, amount)
+     * pairs through utils::calculate_payouts_commitment, used in place of the recipient. The amounts
+     * must add up to -ext_amount. The recipient accounts are passed as remaining_accounts, writable
+     * and in the same order as the payouts. tree_index and root_tree work as in transact.
+     * Fixed-denomination pools only accept a single payout.
+     */
+    pub fn withdraw_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawMulti<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        payouts: Vec<Payout>,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+        tree_index: u64,
+    ) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        let global_config = &ctx.accounts.global_config;
+
+        let withdrawal_amount = utils::validate_payouts(&payouts, ext_data_minified.ext_amount)?;
+        global_config.check_payout_count(payouts.len())?;
+        require!(
+            ctx.remaining_accounts.len() == payouts.len(),
+            ErrorCode::RecipientMismatch
+        );
+
+        let ext_data = ExtData::from_minified(
+            utils::calculate_payouts_commitment(&payouts)?,
+            ctx.accounts.fee_recipient_account.key(),
+            ext_data_minified,
+        );
+
+        let proof_tree = ProofTree {
+            tree_index,
+            tree: ctx.accounts.tree_account.key(),
+            root_tree: ctx.accounts.root_tree.as_ref(),
+            root_archive: None,
+        };
+        verify_transaction_in_tree(tree_account, &proof_tree, global_config, &proof, &ext_data, &encrypted_output1, &encrypted_output2)?;
+
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+
+        check_withdrawal_funds(&tree_token_account_info, withdrawal_amount, ext_data.fee, rent_exempt_minimum)?;
+        global_config.check_immediate_withdrawal(withdrawal_amount)?;
+        for (payout, recipient_account_info) in payouts.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(
+                recipient_account_info.key() == payout.recipient && recipient_account_info.is_writable,
+                ErrorCode::RecipientMismatch
+            );
+            withdraw_from_pool(&tree_token_account_info, recipient_account_info, payout.amount)?;
+        }
+
+        pay_fee(
+            &tree_token_account_info,
+            &ctx.accounts.fee_recipient_account.to_account_info(),
+            ext_data.fee,
+            ext_data.ext_amount,
+            rent_exempt_minimum,
+        )?;
+
+        ctx.accounts.tree_token_account.record_transaction(ext_data.ext_amount, ext_data.fee)?;
+        ctx.accounts.tree_token_account.record_denomination(&ctx.accounts.global_config, ext_data.ext_amount)?;
+
+        append_commitments(tree_account, &proof.output_commitments, vec![encrypted_output1, encrypted_output2])
+    }
+
+    /**
+     * Deposit into the pool on behalf of another program.
+     * 
+     * Works like a transact deposit, but the deposited lamports come from `depositor`, which may be
+     * a PDA signed for through invoke_signed, while `payer` covers the rent of the nullifier accounts.
+     * The depositor must be owned by the system program so that it can be debited. The proof's ext data
+     * must use the depositor as recipient, binding the proof to the funding source. tree_index and
+     * root_tree work as in transact. The deny list and the velocity limit apply to the depositor,
+     * so callers funding many users' deposits from one PDA share a single velocity limit.
+     */
+    pub fn deposit_for(ctx: Context<DepositFor>, proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        let global_config = &ctx.accounts.global_config;
+
+        let ext_data = ExtData::from_minified(
+            ctx.accounts.depositor.key(),
+            ctx.accounts.fee_recipient_account.key(),
+            ext_data_minified,
+        );
+        require!(ext_data.ext_amount > 0, ErrorCode::DepositRequired);
+
+        let proof_tree = ProofTree {
+            tree_index,
+            tree: ctx.accounts.tree_account.key(),
+            root_tree: ctx.accounts.root_tree.as_ref(),
+            root_archive: None,
+        };
+        verify_transaction_in_tree(tree_account, &proof_tree, global_config, &proof, &ext_data, &encrypted_output1, &encrypted_output2)?;
+
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+
+        deny_list::check_depositor(&*ctx.accounts.deny_list.load()?, &ctx.accounts.depositor.key())?;
+        let price = price_feed::load_price(&ctx.accounts.global_config, ctx.accounts.price_feed.as_deref())?;
+        ctx.accounts.global_config.check_usd_limits(price, ext_data.ext_amount as u64, ctx.accounts.tree_token_account.net_shielded()?, Clock::get()?.slot)?;
+        velocity::record_deposit(
+            ctx.accounts.depositor_velocity.as_deref_mut(),
+            ctx.bumps.depositor_velocity,
+            &ctx.accounts.global_config,
+            ctx.accounts.depositor.key(),
+            ext_data.ext_amount as u64,
+            Clock::get()?.slot,
+        )?;
+        deposit_to_pool(
+            &ctx.accounts.system_program,
+            ctx.accounts.depositor.to_account_info(),
+            tree_token_account_info.clone(),
+            ext_data.ext_amount as u64,
+            tree_account.max_deposit_amount,
+        )?;
+
+        pay_fee(
+            &tree_token_account_info,
+            &ctx.accounts.fee_recipient_account.to_account_info(),
+            ext_data.fee,
+            ext_data.ext_amount,
+            rent_exempt_minimum,
+        )?;
+
+        ctx.accounts.tree_token_account.record_transaction(ext_data.ext_amount, ext_data.fee)?;
+        ctx.accounts.tree_token_account.record_denomination(&ctx.accounts.global_config, ext_data.ext_amount)?;
+
+        ragequit::record_deposit_origin(
+            Some(&mut *ctx.accounts.deposit_origin),
+            Some(ctx.bumps.deposit_origin),
+            ext_data.ext_amount,
+            ext_data.fee,
+            ctx.accounts.depositor.key(),
+            proof.output_commitments,
+            Some(tree_account.next_index),
+        )?;
+
+        append_commitments(tree_account, &proof.output_commitments, vec![encrypted_output1, encrypted_output2])
+    }
+
+    /**
+     * Public exit for a deposited note, e.g. one excluded from the approved set. The note owner
+     * spends output output_index of a deposit with a transaction proof whose root is the tree
+     * holding only that note, at the leaf index its deposit origin record holds
+     * (MerkleTree::singleton_root). The input's nullifier is then the one a private spend of the
+     * note reveals, so the note can only be spent once either way. -ext_amount is paid to the
+     * original depositor, who is also the ext data's recipient and fee recipient; no fee is
+     * charged and change goes to the proof's outputs like in transact. Together a deposit's notes
+     * can return at most what the deposit put in after its fee. Anyone can submit the proof.
+     * Above the large withdrawal threshold the payout is queued for claim_withdrawal like a
+     * transact withdrawal, which then needs the pending withdrawal account.
+     */
+    pub fn ragequit(ctx: Context<Ragequit>, proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, output_index: u8) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+
+        let (commitment, leaf_index) = ctx.accounts.deposit_origin.note(output_index)?;
+        require!(
+            proof.root == MerkleTree::singleton_root::<Poseidon>(commitment, leaf_index, tree_account.height),
+            ErrorCode::UnknownRoot
+        );
+
+        let ext_data = ExtData::from_minified(
+            ctx.accounts.depositor.key(),
+            ctx.accounts.depositor.key(),
+            ext_data_minified,
+        );
+        require!(ext_data.ext_amount < 0, ErrorCode::InvalidExtAmount);
+        require!(ext_data.fee == 0, ErrorCode::RagequitFeeNotAllowed);
+        verify_ext_data(&proof, &ext_data, &encrypted_output1, &encrypted_output2)?;
+        require!(utils::verify_proof(proof.clone(), utils::VERIFYING_KEY), ErrorCode::InvalidProof);
+
+        let amount: u64 = ext_data.ext_amount.checked_neg()
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::InvalidExtAmount)?;
+        ctx.accounts.deposit_origin.withdraw(amount)?;
+
+        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
+        let rent = Rent::get()?;
+        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+
+        check_withdrawal_funds(&tree_token_account_info, amount, 0, rent_exempt_minimum)?;
+        // Large ragequits wait out the withdrawal delay like transact withdrawals
+        let delayed_withdrawal = ctx.accounts.global_config.delay_withdrawal(amount, Clock::get()?.slot)?;
+        if delayed_withdrawal.is_none() {
+            withdraw_from_pool(&tree_token_account_info, &ctx.accounts.depositor.to_account_info(), amount)?;
+        }
+
+        ctx.accounts.tree_token_account.record_transaction(ext_data.ext_amount, 0)?;
+        // Like any withdrawal, a ragequit from a fixed-denomination pool must move a denomination
+        ctx.accounts.tree_token_account.record_denomination(&ctx.accounts.global_config, ext_data.ext_amount)?;
+
+        pending_withdrawal::record_pending_withdrawal(
+            ctx.accounts.pending_withdrawal.as_deref_mut(),
+            ctx.bumps.pending_withdrawal,
+            delayed_withdrawal,
+            ctx.accounts.depositor.key(),
+            ctx.accounts.signer.key(),
+            proof.input_nullifiers[0],
+        )?;
+        if let Some(delayed) = delayed_withdrawal {
+            emit!(WithdrawalQueued {
+                nullifier: proof.input_nullifiers[0],
+                recipient: ctx.accounts.depositor.key(),
+                amount: delayed.amount,
+                release_slot: delayed.release_slot,
+            });
+        }
+
+        emit!(NoteRagequit {
+            commitment,
+            input_nullifiers: proof.input_nullifiers,
+            depositor: ctx.accounts.depositor.key(),
+            amount,
+        });
+
+        append_commitments(tree_account, &proof.output_commitments, vec![encrypted_output1, encrypted_output2])
+    }
+}
+
+/// Where a proof's root is looked up: the tree of generation `tree_index`, which is `tree` when
+/// that is the newest tree and `root_tree` otherwise, and that tree's root archive if passed.
+struct ProofTree<'a, 'info> {
+    tree_index: u64,
+    tree: Pubkey,
+    root_tree: Option<&'a AccountLoader<'info, MerkleTreeAccount>>,
+    root_archive: Option<&'a AccountLoader<'info, RootArchive>>,
+}
+
+/// verify_transaction against the tree `proof_tree` selects. `tree_account` is the newest tree.
+#[allow(clippy::too_many_arguments)]
+fn verify_transaction_in_tree(
+    tree_account: &MerkleTreeAccount,
+    proof_tree: &ProofTree,
+    global_config: &GlobalConfig,
+    proof: &Proof,
+    ext_data: &ExtData,
+    encrypted_output1: &[u8],
+    encrypted_output2: &[u8],
+) -> Result<()> {
+    let root_archive = proof_tree.root_archive.map(|archive| archive.load()).transpose()?;
+    if proof_tree.tree_index == tree_account.generation {
+        if let Some(archive) = &root_archive {
+            require_keys_eq!(archive.tree, proof_tree.tree, ErrorCode::InvalidRootArchive);
         }
+        verify_transaction(tree_account, global_config, root_archive.as_deref(), proof, ext_data, encrypted_output1, encrypted_output2)
+    } else {
+        let root_tree = proof_tree.root_tree.ok_or(ErrorCode::InvalidTreeIndex)?;
+        if let Some(archive) = &root_archive {
+            require_keys_eq!(archive.tree, root_tree.key(), ErrorCode::InvalidRootArchive);
+        }
+        let root_tree_account = root_tree.load()?;
+        verify_transaction(&root_tree_account, global_config, root_archive.as_deref(), proof, ext_data, encrypted_output1, encrypted_output2)
+    }
+}
+
+/// Checks shared by every proof-carrying instruction but ragequit: known root, ext data hash,
+/// public amount, fee bounds and the proof itself.
+fn verify_transaction(
+    tree_account: &MerkleTreeAccount,
+    global_config: &GlobalConfig,
+    root_archive: Option<&RootArchive>,
+    proof: &Proof,
+    ext_data: &ExtData,
+    encrypted_output1: &[u8],
+    encrypted_output2: &[u8],
+) -> Result<()> {
+    // check if proof.root is in the tree_account's proof history, or archived and not too old
+    let root_known = MerkleTree::is_known_root(tree_account, proof.root) || match root_archive {
+        Some(archive) => archive.is_valid_root(proof.root, Clock::get()?.slot, global_config.max_archived_root_age_slots),
+        None => false,
+    };
+    require!(root_known, ErrorCode::UnknownRoot);
+
+    verify_ext_data(proof, ext_data, encrypted_output1, encrypted_output2)?;
+
+    // In fixed-denomination pools, deposits and withdrawals must move one of the denominations
+    global_config.denomination_index(ext_data.ext_amount)?;
+
+    // Validate fee calculation using utility function
+    utils::validate_fee(
+        ext_data.ext_amount,
+        ext_data.fee,
+        &global_config.deposit_fee,
+        &global_config.withdrawal_fee,
+        &global_config.transfer_fee,
+        global_config.fee_error_margin,
+    )?;
+
+    // Never charge more than the user signed for
+    utils::validate_max_fee(ext_data.fee, ext_data.max_fee)?;
+
+    // verify the proof
+    require!(utils::verify_proof(proof.clone(), utils::VERIFYING_KEY), ErrorCode::InvalidProof);
+
+    Ok(())
+}
+
+/// Checks that the proof commits to `ext_data`: the ext data hash and the public amount.
+fn verify_ext_data(
+    proof: &Proof,
+    ext_data: &ExtData,
+    encrypted_output1: &[u8],
+    encrypted_output2: &[u8],
+) -> Result<()> {
+    // check if the ext_data hashes to the same ext_data in the proof
+    let calculated_ext_data_hash = utils::calculate_complete_ext_data_hash(
+        ext_data.recipient,
+        ext_data.ext_amount,
+        encrypted_output1,
+        encrypted_output2,
+        ext_data.fee,
+        ext_data.max_fee,
+        ext_data.fee_recipient,
+        ext_data.mint_address,
+    )?;
+
+    require!(
+        Fr::from_le_bytes_mod_order(&calculated_ext_data_hash) == Fr::from_be_bytes_mod_order(&proof.ext_data_hash),
+        ErrorCode::ExtDataHashMismatch
+    );
+
+    require!(
+        utils::check_public_amount(ext_data.ext_amount, ext_data.fee, proof.public_amount),
+        ErrorCode::InvalidPublicAmountData
+    );
+
+    Ok(())
+}
+
+/// Moves a deposit into the tree token account after checking the deposit limit.
+fn deposit_to_pool<'info>(
+    system_program: &Program<'info, System>,
+    from: AccountInfo<'info>,
+    tree_token_account: AccountInfo<'info>,
+    deposit_amount: u64,
+    max_deposit_amount: u64,
+) -> Result<()> {
+    require!(
+        deposit_amount <= max_deposit_amount,
+        ErrorCode::DepositLimitExceeded
+    );
+
+    anchor_lang::system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from,
+                to: tree_token_account,
+            },
+        ),
+        deposit_amount,
+    )
+}
+
+/// Checks that the pool can pay a withdrawal and its fee while staying rent exempt.
+fn check_withdrawal_funds(
+    tree_token_account_info: &AccountInfo,
+    withdrawal_amount: u64,
+    fee: u64,
+    rent_exempt_minimum: u64,
+) -> Result<()> {
+    let total_required = withdrawal_amount
+        .checked_add(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_add(rent_exempt_minimum)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    require!(
+        tree_token_account_info.lamports() >= total_required,
+        ErrorCode::InsufficientFundsForWithdrawal
+    );
+
+    Ok(())
+}
+
+/// Moves lamports from the tree token account to a recipient.
+/// PDA can't directly sign transactions, so we need to transfer SOL via try_borrow_mut_lamports
+fn withdraw_from_pool(
+    tree_token_account_info: &AccountInfo,
+    recipient_account_info: &AccountInfo,
+    amount: u64,
+) -> Result<()> {
+    let tree_token_balance = tree_token_account_info.lamports();
+    let recipient_balance = recipient_account_info.lamports();
+    
+    let new_tree_token_balance = tree_token_balance.checked_sub(amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let new_recipient_balance = recipient_balance.checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+    **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
+    **recipient_account_info.try_borrow_mut_lamports()? = new_recipient_balance;
+
+    Ok(())
+}
+
+/// Pays the relayer fee out of the tree token account.
+fn pay_fee(
+    tree_token_account_info: &AccountInfo,
+    fee_recipient_account_info: &AccountInfo,
+    fee: u64,
+    ext_amount: i64,
+    rent_exempt_minimum: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+
+    if ext_amount >= 0 {
+        let total_required = fee
+            .checked_add(rent_exempt_minimum)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        require!(
+            tree_token_account_info.lamports() >= total_required,
+            ErrorCode::InsufficientFundsForFee
+        );
+    }
+
+    let tree_token_balance = tree_token_account_info.lamports();
+    let fee_recipient_balance = fee_recipient_account_info.lamports();
+    
+    let new_tree_token_balance = tree_token_balance.checked_sub(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let new_fee_recipient_balance = fee_recipient_balance.checked_add(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+    **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
+    **fee_recipient_account_info.try_borrow_mut_lamports()? = new_fee_recipient_balance;
+
+    Ok(())
+}
+
+/// Appends a transaction's output commitments to the tree and emits their encrypted outputs.
+/// The commitments share a single root history slot.
+fn append_commitments(
+    tree_account: &mut MerkleTreeAccount,
+    commitments: &[[u8; 32]],
+    encrypted_outputs: Vec<Vec<u8>>,
+) -> Result<()> {
+    let start_index = tree_account.next_index;
+    MerkleTree::append_batch::<Poseidon>(commitments, tree_account, Clock::get()?.slot)?;
+
+    for ((index, commitment), encrypted_output) in (start_index..).zip(commitments).zip(encrypted_outputs) {
+        emit!(CommitmentData {
+            index,
+            commitment: *commitment,
+            encrypted_output,
+        });
     }
+
+    Ok(())
+}
+
+#[event]
+pub struct CommitmentData {
+    pub index: u64,
+    pub commitment: [u8; 32],
+    pub encrypted_output: Vec<u8>,
+}
+
+#[event]
+pub struct SolvencyReport {
+    pub vault_balance: u64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub total_fees: u64,
+    pub rent_exempt_minimum: u64,
+}
+
+#[event]
+pub struct TreeRolledOver {
+    pub generation: u64,
+    pub tree: Pubkey,
+}
+
+#[event]
+pub struct WithdrawalQueued {
+    pub nullifier: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub release_slot: u64,
+}
+
+#[event]
+pub struct WithdrawalClaimed {
+    pub nullifier: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct WithdrawalCancelled {
+    pub nullifier: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CancelledWithdrawalResolved {
+    pub nullifier: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct NoteRagequit {
+    pub commitment: [u8; 32],
+    pub input_nullifiers: [[u8; 32]; 2],
+    pub depositor: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DenyListUpdated {
+    pub address: Pubkey,
+    pub denied: bool,
+}
+
+#[event]
+pub struct CommitmentQueued {
+    pub shard: u8,
+    pub commitment: [u8; 32],
+    pub encrypted_output: Vec<u8>,
+}
+
+#[event]
+pub struct QueuedCommitmentsInserted {
+    pub shard: u8,
+    pub start_index: u64, // tree index of commitments[0], the others follow consecutively
+    pub commitments: Vec<[u8; 32]>,
+}
+
+#[event]
+pub struct ConfigChangeQueued {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub queued_slot: u64,
+    pub effective_slot: u64,
+}
+
+#[event]
+pub struct ConfigChangeExecuted {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub effective_slot: u64,
+    pub executed_slot: u64,
+}
+
+#[event]
+pub struct ConfigChangeCancelled {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub effective_slot: u64,
+}
+
+#[event]
+pub struct MultisigMembersUpdated {
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct MultisigProposalCreated {
+    pub id: u64,
+    pub action: MultisigAction,
+    pub accounts: Vec<Pubkey>,
+    pub proposer: Pubkey,
+}
+
+#[event]
+pub struct MultisigProposalApproved {
+    pub id: u64,
+    pub member: Pubkey,
+    pub approval_count: u8,
+}
+
+#[event]
+pub struct MultisigProposalExecuted {
+    pub id: u64,
+    pub action: MultisigAction,
+}
+
+#[event]
+pub struct MultisigProposalClosed {
+    pub id: u64,
+}
+
+// all public inputs needs to be in big endian format
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Proof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub root: [u8; 32],
+    pub public_amount: [u8; 32],
+    pub ext_data_hash: [u8; 32],
+    pub input_nullifiers: [[u8; 32]; 2],
+    pub output_commitments: [[u8; 32]; 2],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExtData {
+    pub recipient: Pubkey,
+    pub ext_amount: i64,
+    pub fee: u64,
+    pub max_fee: u64,
+    pub fee_recipient: Pubkey,
+    pub mint_address: Pubkey,
+}
+
+/// One recipient of a multi-recipient withdrawal.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Payout {
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExtDataMinified {
+    pub ext_amount: i64,
+    pub fee: u64,
+    pub max_fee: u64,
+}
+
+impl ExtData {
+    fn from_minified(recipient: Pubkey, fee_recipient: Pubkey, minified: ExtDataMinified) -> Self {
+        use crate::utils::SOL_ADDRESS;
+        Self {
+            recipient,
+            ext_amount: minified.ext_amount,
+            fee: minified.fee,
+            max_fee: minified.max_fee,
+            fee_recipient,
+            mint_address: SOL_ADDRESS,
+        }
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64)]
+pub struct Transact<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+    
+    /// Nullifier account to mark the first input as spent.
+    /// Using `init` without `init_if_needed` ensures that the transaction
+    /// will automatically fail with a system program error if this nullifier
+    /// has already been used (i.e., if the account already exists).
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+    
+    /// Nullifier account to mark the second input as spent.
+    /// Using `init` without `init_if_needed` ensures that the transaction
+    /// will automatically fail with a system program error if this nullifier
+    /// has already been used (i.e., if the account already exists).
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+    
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+    
+    /// Origin record of the outputs, see ragequit. Required for deposits and
+    /// not allowed otherwise.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<DepositOrigin>(),
+        seeds = [b"deposit_origin", proof.output_commitments[0].as_ref()],
+        bump
+    )]
+    pub deposit_origin: Option<Account<'info, DepositOrigin>>,
+
+    /// Holds a withdrawal above the large withdrawal threshold until claim_withdrawal.
+    /// Required for such withdrawals and not allowed otherwise.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<PendingWithdrawal>(),
+        seeds = [b"pending_withdrawal", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub pending_withdrawal: Option<Account<'info, PendingWithdrawal>>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Screened before accepting a deposit
+    #[account(
+        seeds = [b"deny_list"],
+        bump = deny_list.load()?.bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+
+    /// CHECK: only needed when global_config.price_feed is set; checked against it and read by price_feed::load_price
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Only needed when global_config.max_deposit_per_window is set
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + std::mem::size_of::<DepositorVelocity>(),
+        seeds = [b"depositor_velocity", signer.key().as_ref()],
+        bump
+    )]
+    pub depositor_velocity: Option<Account<'info, DepositorVelocity>>,
+
+    /// The tree of generation tree_index; only needed when that is not the newest tree
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_index).as_slice()],
+        bump = root_tree.load()?.bump
+    )]
+    pub root_tree: Option<AccountLoader<'info, MerkleTreeAccount>>,
+
+    /// Only needed when the proof's root has left the root history
+    pub root_archive: Option<AccountLoader<'info, RootArchive>>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send funds to any types of accounts
+    pub recipient: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send fees to any types of accounts
+    pub fee_recipient_account: UncheckedAccount<'info>,
+    
+    /// The account that is signing the transaction
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"pending_withdrawal", pending_withdrawal.nullifier.as_ref()],
+        bump = pending_withdrawal.bump,
+        close = payer
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        mut,
+        address = pending_withdrawal.recipient @ ErrorCode::InvalidPendingWithdrawalAccount
+    )]
+    /// CHECK: the recipient of the queued withdrawal
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = pending_withdrawal.payer @ ErrorCode::InvalidPendingWithdrawalAccount
+    )]
+    /// CHECK: receives the rent of the pending withdrawal
+    pub payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"pending_withdrawal", pending_withdrawal.nullifier.as_ref()],
+        bump = pending_withdrawal.bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = guardian @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(nullifier: [u8; 32], recipient: Pubkey)]
+pub struct ResolveCancelledWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"pending_withdrawal", nullifier.as_ref()],
+        bump = pending_withdrawal.bump,
+        close = payer
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut, address = recipient)]
+    /// CHECK: chosen by the authority, may be any account
+    pub recipient_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = pending_withdrawal.payer @ ErrorCode::InvalidPendingWithdrawalAccount
+    )]
+    /// CHECK: receives the rent of the pending withdrawal
+    pub payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, output_index: u8)]
+pub struct Ragequit<'info> {
+    /// The newest tree, which receives the outputs. The note itself may be in any generation.
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"deposit_origin", deposit_origin.commitments[0].as_ref()],
+        bump = deposit_origin.bump
+    )]
+    pub deposit_origin: Account<'info, DepositOrigin>,
+
+    /// Nullifier accounts, see Transact
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Holds a ragequit above the large withdrawal threshold until claim_withdrawal, see Transact
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<PendingWithdrawal>(),
+        seeds = [b"pending_withdrawal", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub pending_withdrawal: Option<Account<'info, PendingWithdrawal>>,
+
+    #[account(
+        mut,
+        address = deposit_origin.depositor @ ErrorCode::InvalidRagequitRecipient
+    )]
+    /// CHECK: the original depositor, the only possible recipient of a ragequit
+    pub depositor: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64)]
+pub struct DepositFor<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    /// The tree of generation tree_index; only needed when that is not the newest tree
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_index).as_slice()],
+        bump = root_tree.load()?.bump
+    )]
+    pub root_tree: Option<AccountLoader<'info, MerkleTreeAccount>>,
+
+    /// Nullifier accounts, see Transact. Deposits spend zero-value inputs,
+    /// whose nullifiers must still be marked as used.
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+    
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+    
+    /// Origin record of the outputs, see ragequit
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<DepositOrigin>(),
+        seeds = [b"deposit_origin", proof.output_commitments[0].as_ref()],
+        bump
+    )]
+    pub deposit_origin: Account<'info, DepositOrigin>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Screened before accepting a deposit
+    #[account(
+        seeds = [b"deny_list"],
+        bump = deny_list.load()?.bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+
+    /// CHECK: only needed when global_config.price_feed is set; checked against it and read by price_feed::load_price
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Only needed when global_config.max_deposit_per_window is set
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<DepositorVelocity>(),
+        seeds = [b"depositor_velocity", depositor.key().as_ref()],
+        bump
+    )]
+    pub depositor_velocity: Option<Account<'info, DepositorVelocity>>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send fees to any types of accounts
+    pub fee_recipient_account: UncheckedAccount<'info>,
+    
+    /// The account funding the deposit, e.g. a PDA of the calling program
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// Pays the rent of the nullifier accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard: u8)]
+pub struct InitializeCommitmentQueue<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<CommitmentQueue>(),
+        seeds = [b"commitment_queue", [shard].as_ref()],
+        bump
+    )]
+    pub commitment_queue: AccountLoader<'info, CommitmentQueue>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64)]
+pub struct TransactQueued<'info> {
+    /// Read-only: outputs go to the commitment queue
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    /// The tree of generation tree_index; only needed when that is not the newest tree
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_index).as_slice()],
+        bump = root_tree.load()?.bump
+    )]
+    pub root_tree: Option<AccountLoader<'info, MerkleTreeAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"commitment_queue", [commitment_queue.load()?.shard].as_ref()],
+        bump = commitment_queue.load()?.bump
+    )]
+    pub commitment_queue: AccountLoader<'info, CommitmentQueue>,
+    
+    /// Nullifier accounts, see Transact
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+    
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+    
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+    
+    /// Origin record of the outputs, see ragequit. Required for deposits and
+    /// not allowed otherwise.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<DepositOrigin>(),
+        seeds = [b"deposit_origin", proof.output_commitments[0].as_ref()],
+        bump
+    )]
+    pub deposit_origin: Option<Account<'info, DepositOrigin>>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Screened before accepting a deposit
+    #[account(
+        seeds = [b"deny_list"],
+        bump = deny_list.load()?.bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+
+    /// CHECK: only needed when global_config.price_feed is set; checked against it and read by price_feed::load_price
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Only needed when global_config.max_deposit_per_window is set
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + std::mem::size_of::<DepositorVelocity>(),
+        seeds = [b"depositor_velocity", signer.key().as_ref()],
+        bump
+    )]
+    pub depositor_velocity: Option<Account<'info, DepositorVelocity>>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send funds to any types of accounts
+    pub recipient: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send fees to any types of accounts
+    pub fee_recipient_account: UncheckedAccount<'info>,
+    
+    /// The account that is signing the transaction
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessCommitmentQueue<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"commitment_queue", [commitment_queue.load()?.shard].as_ref()],
+        bump = commitment_queue.load()?.bump
+    )]
+    pub commitment_queue: AccountLoader<'info, CommitmentQueue>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(page: u64)]
+pub struct InitializeRootArchive<'info> {
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_account.load()?.generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<RootArchive>(),
+        seeds = [b"root_archive", tree_account.key().as_ref(), page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub root_archive: AccountLoader<'info, RootArchive>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ArchiveRoots<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_account.load()?.generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"root_archive", tree_account.key().as_ref(), root_archive.load()?.page.to_le_bytes().as_ref()],
+        bump = root_archive.load()?.bump
+    )]
+    pub root_archive: AccountLoader<'info, RootArchive>,
+}
+
+#[derive(Accounts)]
+pub struct CheckInvariants<'info> {
+    #[account(
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RolloverTree<'info> {
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?).as_slice()],
+        bump
+    )]
+    pub new_tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, payouts: Vec<Payout>, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>, tree_index: u64)]
+pub struct WithdrawMulti<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    /// The tree of generation tree_index; only needed when that is not the newest tree
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_index).as_slice()],
+        bump = root_tree.load()?.bump
+    )]
+    pub root_tree: Option<AccountLoader<'info, MerkleTreeAccount>>,
+
+    /// Nullifier accounts, see Transact
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+    
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+    
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    
+    #[account(mut)]
+    /// CHECK: user should be able to send fees to any types of accounts
+    pub fee_recipient_account: UncheckedAccount<'info>,
+    
+    /// The account that is signing the transaction
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
+        seeds = [b"merkle_tree"],
+        bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<TreeTokenAccount>(),
+        seeds = [b"tree_token"],
+        bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<GlobalConfig>(),
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<DenyList>(),
+        seeds = [b"deny_list"],
+        bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        constraint = zkcash_program.programdata_address()? == Some(program_data.key()) @ ErrorCode::InvalidProgramData
+    )]
+    pub zkcash_program: Program<'info, program::Zkcash>,
+
+    /// The ProgramData account holding this program's upgrade authority
+    pub program_data: Account<'info, ProgramData>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLegacyAccounts<'info> {
+    /// CHECK: legacy MerkleTreeAccount, checked and rewritten by the handler
+    #[account(mut, owner = crate::ID, seeds = [b"merkle_tree"], bump)]
+    pub tree_account: UncheckedAccount<'info>,
+
+    /// CHECK: legacy TreeTokenAccount, checked and rewritten by the handler
+    #[account(mut, owner = crate::ID, seeds = [b"tree_token"], bump)]
+    pub tree_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: legacy GlobalConfig, checked and rewritten by the handler
+    #[account(mut, owner = crate::ID, seeds = [b"global_config"], bump)]
+    pub global_config: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<DenyList>(),
+        seeds = [b"deny_list"],
+        bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDepositLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    
+    /// The authority account that can update the deposit limit
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+    
+    /// The authority account that can update the global config
+    pub authority: Signer<'info>,
+}
+
+#[cfg(feature = "localnet")]
+#[derive(Accounts)]
+pub struct SetMockPrice<'info> {
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MockPriceFeed>(),
+        seeds = [b"mock_price_feed", authority.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, MockPriceFeed>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDenyList<'info> {
+    #[account(
+        mut,
+        seeds = [b"deny_list"],
+        bump = deny_list.load()?.bump
+    )]
+    pub deny_list: AccountLoader<'info, DenyList>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// The authority account that maintains the deny list
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QueueConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<PendingConfigChange>(),
+        seeds = [b"pending_config_change", global_config.next_config_change_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pending_config_change: Account<'info, PendingConfigChange>,
+
+    /// The authority account that can queue config changes
+    pub authority: Signer<'info>,
+
+    /// Pays the rent of the pending change (a multisig authority cannot pay rent itself)
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>)]
-pub struct Transact<'info> {
+pub struct ExecuteConfigChange<'info> {
     #[account(
         mut,
-        seeds = [b"merkle_tree"],
-        bump = tree_account.load()?.bump
+        seeds = [b"pending_config_change", pending_config_change.id.to_le_bytes().as_ref()],
+        bump = pending_config_change.bump,
+        close = authority
     )]
-    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
-    
-    /// Nullifier account to mark the first input as spent.
-    /// Using `init` without `init_if_needed` ensures that the transaction
-    /// will automatically fail with a system program error if this nullifier
-    /// has already been used (i.e., if the account already exists).
+    pub pending_config_change: Account<'info, PendingConfigChange>,
+
     #[account(
-        init,
-        payer = signer,
-        space = 8 + std::mem::size_of::<NullifierAccount>(),
-        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
-        bump
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
     )]
-    pub nullifier0: Account<'info, NullifierAccount>,
-    
-    /// Nullifier account to mark the second input as spent.
-    /// Using `init` without `init_if_needed` ensures that the transaction
-    /// will automatically fail with a system program error if this nullifier
-    /// has already been used (i.e., if the account already exists).
+    pub global_config: Account<'info, GlobalConfig>,
+
     #[account(
-        init,
-        payer = signer,
-        space = 8 + std::mem::size_of::<NullifierAccount>(),
-        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
-        bump
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
     )]
-    pub nullifier1: Account<'info, NullifierAccount>,
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
 
+    #[account(mut)]
+    /// CHECK: receives the rent of the pending change, checked against global_config.authority
+    pub authority: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CancelConfigChange<'info> {
     #[account(
-        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
-        bump
+        mut,
+        seeds = [b"pending_config_change", id.to_le_bytes().as_ref()],
+        bump = pending_config_change.bump,
+        close = authority
     )]
-    pub nullifier2: SystemAccount<'info>,
-    
+    pub pending_config_change: Account<'info, PendingConfigChange>,
+
     #[account(
-        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
-        bump
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
     )]
-    pub nullifier3: SystemAccount<'info>,
-    
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// The authority account that can cancel config changes
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteFee<'info> {
     #[account(
-        mut,
-        seeds = [b"tree_token"],
-        bump = tree_token_account.bump
+        seeds = [b"global_config"],
+        bump = global_config.bump
     )]
-    pub tree_token_account: Account<'info, TreeTokenAccount>,
-    
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+pub struct GetPoolState<'info> {
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
     #[account(
         seeds = [b"global_config"],
         bump = global_config.bump
     )]
     pub global_config: Account<'info, GlobalConfig>,
-    
-    #[account(mut)]
-    /// CHECK: user should be able to send funds to any types of accounts
-    pub recipient: UncheckedAccount<'info>,
-    
-    #[account(mut)]
-    /// CHECK: user should be able to send fees to any types of accounts
-    pub fee_recipient_account: UncheckedAccount<'info>,
-    
-    /// The account that is signing the transaction
-    #[account(mut)]
-    pub signer: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+pub struct IsRootKnown<'info> {
+    #[account(
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(tree_account.load()?.generation).as_slice()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMultisig<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
-        seeds = [b"merkle_tree"],
+        space = 8 + std::mem::size_of::<Multisig>(),
+        seeds = [b"multisig"],
         bump
     )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", merkle_tree::generation_seed(global_config.current_tree_generation).as_slice()],
+        bump = tree_account.load()?.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
     pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
-    
+
     #[account(
-        init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<TreeTokenAccount>(),
+        mut,
         seeds = [b"tree_token"],
-        bump
+        bump = tree_token_account.bump,
+        has_one = authority @ ErrorCode::Unauthorized
     )]
     pub tree_token_account: Account<'info, TreeTokenAccount>,
-    
+
+    /// The current single-key authority handing over to the multisig
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeMultisigAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    /// Sized for the largest action, SetMultisigMembers with a full member list, and a full account list
     #[account(
         init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<GlobalConfig>(),
-        seeds = [b"global_config"],
+        payer = proposer,
+        space = 8 + std::mem::size_of::<MultisigProposal>() + MAX_MULTISIG_MEMBERS * 32 + MAX_MULTISIG_ACTION_ACCOUNTS * 32,
+        seeds = [b"multisig_proposal", multisig.next_proposal_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub global_config: Account<'info, GlobalConfig>,
-    
+    pub proposal: Account<'info, MultisigProposal>,
+
     #[account(mut)]
-    pub authority: Signer<'info>,
-    
+    pub proposer: Signer<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateDepositLimit<'info> {
+pub struct ApproveMultisigProposal<'info> {
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
     #[account(
         mut,
-        seeds = [b"merkle_tree"],
-        bump = tree_account.load()?.bump,
-        has_one = authority @ ErrorCode::Unauthorized
+        seeds = [b"multisig_proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
     )]
-    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
-    
-    /// The authority account that can update the deposit limit
-    pub authority: Signer<'info>,
+    pub proposal: Account<'info, MultisigProposal>,
+
+    pub member: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateGlobalConfig<'info> {
+pub struct ExecuteMultisigProposal<'info> {
     #[account(
         mut,
-        seeds = [b"global_config"],
-        bump = global_config.bump,
-        has_one = authority @ ErrorCode::Unauthorized
+        seeds = [b"multisig"],
+        bump = multisig.bump
     )]
-    pub global_config: Account<'info, GlobalConfig>,
-    
-    /// The authority account that can update the global config
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig_proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        has_one = proposer,
+        close = proposer
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(mut)]
+    /// CHECK: receives the rent of the executed proposal, checked against proposal.proposer
+    pub proposer: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub member: Signer<'info>,
+
+    pub zkcash_program: Program<'info, program::Zkcash>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMultisigProposal<'info> {
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig_proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        has_one = proposer,
+        close = proposer
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(mut)]
+    /// CHECK: receives the rent of the closed proposal, checked against proposal.proposer
+    pub proposer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMultisigMembers<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    /// The multisig PDA itself, signing through execute_multisig_proposal
+    #[account(address = multisig.key() @ ErrorCode::Unauthorized)]
     pub authority: Signer<'info>,
 }
 
 #[account]
 pub struct TreeTokenAccount {
     pub authority: Pubkey,
+    pub total_deposited: u64, // lamports shielded, before fees
+    pub total_withdrawn: u64, // lamports unshielded, excluding fees
+    pub total_fees: u64,      // lamports paid out to fee recipients
+    pub denomination_deposits: [u64; MAX_DENOMINATIONS],    // deposits per denomination, in fixed-denomination pools
+    pub denomination_withdrawals: [u64; MAX_DENOMINATIONS], // withdrawals per denomination, in fixed-denomination pools
+    pub bump: u8,
+}
+
+/// Deposits of one depositor over the velocity window, see velocity::VELOCITY_BUCKETS.
+#[account]
+pub struct DepositorVelocity {
+    pub depositor: Pubkey,
+    pub buckets: [u64; VELOCITY_BUCKETS], // lamports deposited in each bucket of the window
+    pub last_bucket: u64,                 // current_slot / bucket_slots at the last deposit
+    pub bucket_slots: u64,                // bucket length the buckets were recorded with
+    pub bump: u8,
+}
+
+/// SOL/USD price feed for local testing, see price_feed::PriceFeed.
+#[cfg(feature = "localnet")]
+#[account]
+pub struct MockPriceFeed {
+    pub authority: Pubkey,
+    pub micro_usd_per_sol: u64,
+    pub publish_slot: u64,
     pub bump: u8,
 }
 
 #[account]
 pub struct GlobalConfig {
     pub authority: Pubkey,
-    pub deposit_fee_rate: u16,    // basis points (0-10000, where 10000 = 100%)
-    pub withdrawal_fee_rate: u16, // basis points (0-10000, where 10000 = 100%)
-    pub fee_error_margin: u16,    // basis points (0-10000, where 10000 = 100%)
+    pub deposit_fee: FeeSchedule,    // applies when ext_amount > 0
+    pub withdrawal_fee: FeeSchedule, // applies when ext_amount < 0
+    pub transfer_fee: FeeSchedule,   // applies when ext_amount == 0
+    pub fee_error_margin: u16,       // basis points (0-10000, where 10000 = 100%)
+    pub config_timelock_slots: u64,  // delay before a queued config change can be executed
+    pub next_config_change_id: u64,  // id of the next PendingConfigChange
+    pub max_archived_root_age_slots: u64, // how old an archived root transact still accepts
+    pub current_tree_generation: u64, // generation of the tree new commitments are appended to
+    pub guardian: Pubkey,             // may cancel queued withdrawals during their delay
+    pub large_withdrawal_threshold: u64, // withdrawals above this are queued, 0 = disabled
+    pub withdrawal_delay_slots: u64,  // delay before a queued withdrawal can be claimed
+    pub denominations: [u64; MAX_DENOMINATIONS], // allowed deposit and withdrawal amounts, sorted
+    pub denomination_count: u8,       // 0 = variable amounts, set once at initialize
+    pub price_feed: Pubkey,           // SOL/USD feed for the USD limits, default = limits off
+    pub max_price_age_slots: u64,     // how old a price deposits still accept
+    pub max_deposit_usd: u64,         // micro-USD, 0 = no USD deposit limit
+    pub max_tvl_usd: u64,             // micro-USD, 0 = no USD cap on the pool's value
+    pub velocity_window_slots: u64,   // rolling window of the per-depositor velocity limit
+    pub max_deposit_per_window: u64,  // lamports one funding account may deposit per window, 0 = disabled
+    pub bump: u8,
+}
+
+/// Fee charged for one kind of operation:
+/// clamp(flat_fee + amount * rate_bps / 10000, min_fee, max_fee).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct FeeSchedule {
+    pub flat_fee: u64, // lamports
+    pub rate_bps: u16, // basis points (0-10000, where 10000 = 100%)
+    pub min_fee: u64,  // lamports
+    pub max_fee: u64,  // lamports, also the absolute cap on any fee charged
+}
+
+impl FeeSchedule {
+    /// A purely proportional schedule without flat component, minimum or cap.
+    pub const fn from_rate(rate_bps: u16) -> Self {
+        Self {
+            flat_fee: 0,
+            rate_bps,
+            min_fee: 0,
+            max_fee: u64::MAX,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        require!(self.rate_bps <= 10000, ErrorCode::InvalidFeeRate);
+        require!(self.min_fee <= self.max_fee, ErrorCode::InvalidFeeSchedule);
+        Ok(())
+    }
+}
+
+/// Return data of quote_fee.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeQuote {
+    pub expected_fee: u64,       // lamports charged by the schedule
+    pub min_acceptable_fee: u64, // lowest fee transact accepts (after fee_error_margin)
+    pub max_fee: u64,            // highest fee transact accepts
+}
+
+/// Return data of get_pool_state.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PoolState {
+    pub authority: Pubkey,
+    pub next_index: u64,
+    pub root: [u8; 32],
+    pub root_index: u64,
+    pub height: u8,
+    pub max_deposit_amount: u64,
+    pub deposit_fee: FeeSchedule,
+    pub withdrawal_fee: FeeSchedule,
+    pub transfer_fee: FeeSchedule,
+    pub fee_error_margin: u16,
+    pub config_timelock_slots: u64,
+}
+
+#[account]
+pub struct PendingConfigChange {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub queued_slot: u64,
+    pub effective_slot: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct Multisig {
+    pub members: [Pubkey; MAX_MULTISIG_MEMBERS], // only the first member_count entries are set
+    pub member_count: u8,
+    pub threshold: u8,
+    pub nonce: u64, // bumped on every member change to invalidate open proposals
+    pub next_proposal_id: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct MultisigProposal {
+    pub id: u64,
+    pub action: MultisigAction,
+    pub accounts: Vec<Pubkey>, // accounts the action runs with, in instruction order
+    pub proposer: Pubkey,
+    pub multisig_nonce: u64,
+    pub approvals: u16, // bitmask over Multisig::members
+    pub bump: u8,
+}
+
+/// A page of roots that have been, or will be, evicted from the tree's root history.
+#[account(zero_copy)]
+pub struct RootArchive {
+    pub tree: Pubkey,
+    pub page: u64,
+    pub len: u64, // roots[..len] are filled, except for roots evicted before being archived (left zero)
+    pub start_slot: u64, // slot of the first archived root
+    pub end_slot: u64, // slot of the last archived root
+    pub roots: [[u8; 32]; ROOT_ARCHIVE_PAGE_SIZE],
+    pub slots: [u64; ROOT_ARCHIVE_PAGE_SIZE], // slot at which each root was recorded
+    pub bump: u8,
+    pub _padding: [u8; 7],
+}
+
+#[account(zero_copy)]
+pub struct CommitmentQueue {
+    pub commitments: [[u8; 32]; COMMITMENT_QUEUE_CAPACITY], // ring buffer
+    pub has_origin: [u8; COMMITMENT_QUEUE_CAPACITY], // 1 for deposit outputs, see ragequit
+    pub head: u64, // position of the oldest queued commitment
+    pub len: u64,
+    pub shard: u8,
+    pub bump: u8,
+    pub _padding: [u8; 6],
+}
+
+#[account(zero_copy)]
+pub struct DenyList {
+    pub entries: [[u8; 32]; DENY_LIST_CAPACITY], // sorted sha256 hashes of denied addresses
+    pub len: u64,
+    pub bump: u8,
+    pub _padding: [u8; 7],
+}
+
+/// A withdrawal above the large withdrawal threshold, claimable from release_slot on
+#[account]
+pub struct PendingWithdrawal {
+    pub nullifier: [u8; 32], // first input nullifier of the withdrawing transaction
+    pub recipient: Pubkey,
+    pub payer: Pubkey, // paid the rent, refunded on claim or resolution
+    pub amount: u64,
+    pub release_slot: u64,
+    pub bump: u8,
+    pub cancelled: bool, // set by the guardian; only resolve_cancelled_withdrawal can pay it out then
+}
+
+/// Depositor of a deposit's output notes, the only address they can be ragequit to
+#[account]
+pub struct DepositOrigin {
+    pub depositor: Pubkey,
+    pub commitments: [[u8; 32]; 2],
+    pub leaf_indices: [Option<u64>; 2], // set once each output is in the tree
+    pub amount: u64, // what the notes can still ragequit: the deposit after its fee, less past ragequits
     pub bump: u8,
 }
 
@@ -479,11 +2920,15 @@ pub struct NullifierAccount {
 #[account(zero_copy)]
 pub struct MerkleTreeAccount {
     pub authority: Pubkey,
+    pub generation: u64, // PDA seed: generation 0 is the first tree, each rollover adds one
     pub next_index: u64,
     pub subtrees: [[u8; 32]; MERKLE_TREE_HEIGHT as usize],
     pub root: [u8; 32],
     pub root_history: [[u8; 32]; 100],
+    pub root_history_slots: [u64; 100], // slot at which each root_history entry was recorded
     pub root_index: u64,
+    pub root_count: u64, // roots recorded so far, including the initial one
+    pub archived_root_count: u64, // roots copied to (or skipped by) the root archive
     pub max_deposit_amount: u64,
     pub height: u8,
     pub root_history_size: u8,
@@ -528,4 +2973,118 @@ pub enum ErrorCode {
     RecipientMismatch,
     #[msg("Merkle tree is full: cannot add more leaves")]
     MerkleTreeFull,
+    #[msg("Fee exceeds the maximum fee committed in the ext data")]
+    FeeExceedsUserMaximum,
+    #[msg("Fee exceeds the maximum fee allowed by the global configuration")]
+    FeeExceedsCap,
+    #[msg("Invalid fee schedule: min_fee must not exceed max_fee")]
+    InvalidFeeSchedule,
+    #[msg("A config timelock is set: changes must go through queue_config_change")]
+    ConfigTimelocked,
+    #[msg("Queued config change is not effective yet")]
+    ConfigChangeNotReady,
+    #[msg("Invalid config timelock: delay exceeds MAX_CONFIG_TIMELOCK_SLOTS")]
+    InvalidConfigTimelock,
+    #[msg("Invalid multisig members: must be 1 to MAX_MULTISIG_MEMBERS distinct keys")]
+    InvalidMultisigMembers,
+    #[msg("Invalid multisig threshold: must be between 1 and the number of members")]
+    InvalidMultisigThreshold,
+    #[msg("Signer is not a multisig member")]
+    NotMultisigMember,
+    #[msg("Member has already approved this proposal")]
+    MultisigProposalAlreadyApproved,
+    #[msg("Multisig proposal does not have enough approvals")]
+    MultisigThresholdNotMet,
+    #[msg("Multisig members changed since this proposal was created")]
+    StaleMultisigProposal,
+    #[msg("Program data account does not belong to this program")]
+    InvalidProgramData,
+    #[msg("Instruction only accepts deposits (ext_amount > 0)")]
+    DepositRequired,
+    #[msg("Invalid payouts: must be 1 to MAX_PAYOUTS non-zero withdrawals")]
+    InvalidPayouts,
+    #[msg("Payout amounts do not add up to the withdrawn amount")]
+    PayoutsAmountMismatch,
+    #[msg("Commitment queue shard is full (256 commitments): it must be processed first")]
+    CommitmentQueueFull,
+    #[msg("Commitment queue is empty")]
+    CommitmentQueueEmpty,
+    #[msg("Root archive account does not belong to this tree")]
+    InvalidRootArchive,
+    #[msg("Root archive page is not the page of the oldest unarchived root")]
+    WrongRootArchivePage,
+    #[msg("No new roots to archive")]
+    NoRootsToArchive,
+    #[msg("Tree still has room: it can only be rolled over when nearly full")]
+    TreeNotFull,
+    #[msg("Tree index is not the newest generation and no matching tree account was provided")]
+    InvalidTreeIndex,
+    #[msg("Vault balance does not cover outstanding notes plus rent")]
+    InvariantViolated,
+    #[msg("Depositor is on the deny list")]
+    DepositorDenied,
+    #[msg("Deny list is full (256 addresses): remove one first")]
+    DenyListFull,
+    #[msg("Address is already on the deny list")]
+    AddressAlreadyDenied,
+    #[msg("Address is not on the deny list")]
+    AddressNotDenied,
+    #[msg("Deposits must create a deposit origin record")]
+    DepositOriginRequired,
+    #[msg("Only deposits may create a deposit origin record")]
+    DepositOriginNotAllowed,
+    #[msg("Commitment is not an output of this deposit")]
+    CommitmentNotInDeposit,
+    #[msg("Ragequit funds can only go to the original depositor")]
+    InvalidRagequitRecipient,
+    #[msg("Withdrawals above the large withdrawal threshold must create a pending withdrawal")]
+    PendingWithdrawalRequired,
+    #[msg("Only withdrawals above the large withdrawal threshold may create a pending withdrawal")]
+    PendingWithdrawalNotAllowed,
+    #[msg("Withdrawal is above the large withdrawal threshold, use transact to queue it")]
+    WithdrawalAboveThreshold,
+    #[msg("Queued withdrawal is still in its delay")]
+    WithdrawalStillDelayed,
+    #[msg("Queued withdrawal can no longer be cancelled")]
+    WithdrawalDelayElapsed,
+    #[msg("Account does not match the pending withdrawal")]
+    InvalidPendingWithdrawalAccount,
+    #[msg("Denominations must be non-zero, strictly increasing and at most 8")]
+    InvalidDenominations,
+    #[msg("Amount is not one of the pool's denominations")]
+    InvalidDenomination,
+    #[msg("The configured price feed account is required")]
+    PriceFeedRequired,
+    #[msg("Price feed account does not match the configured feed or is not supported")]
+    InvalidPriceFeed,
+    #[msg("Price feed has not been updated recently enough")]
+    StalePrice,
+    #[msg("Deposit would exceed the pool's TVL cap")]
+    TvlCapExceeded,
+    #[msg("The depositor velocity account is required while velocity limits are enabled")]
+    DepositorVelocityRequired,
+    #[msg("Deposit would exceed the depositor's volume limit for the window")]
+    VelocityLimitExceeded,
+    #[msg("Velocity window must be between 1 slot and the maximum config timelock")]
+    InvalidVelocityWindow,
+    #[msg("Only proposals made stale by a member set change can be closed")]
+    MultisigProposalNotStale,
+    #[msg("Withdrawal was cancelled by the guardian")]
+    PendingWithdrawalCancelled,
+    #[msg("Only cancelled withdrawals can be resolved")]
+    PendingWithdrawalNotCancelled,
+    #[msg("Fixed-denomination withdrawals must go to a single recipient")]
+    SplitDenominationWithdrawal,
+    #[msg("Deposit output has not been inserted into the tree yet")]
+    DepositNotInserted,
+    #[msg("Ragequit amount exceeds what is left of the deposit")]
+    RagequitExceedsDeposit,
+    #[msg("Ragequit charges no fee")]
+    RagequitFeeNotAllowed,
+    #[msg("Deposit origin record does not match the queued commitment")]
+    InvalidDepositOrigin,
+    #[msg("Account does not have the layout of the first deployment")]
+    NotLegacyLayout,
+    #[msg("Accounts do not match the ones bound in the multisig proposal")]
+    MultisigAccountsMismatch,
 }
\ No newline at end of file
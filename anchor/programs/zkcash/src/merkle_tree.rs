@@ -3,6 +3,20 @@ use light_hasher::Hasher;
 use crate::{MerkleTreeAccount, ErrorCode};
 use anchor_lang::prelude::*;
 
+/// A tree can be rolled over once it has room for fewer leaves than this, which is more
+/// than any single instruction appends.
+pub const ROLLOVER_THRESHOLD: u64 = 256;
+
+/// Seed after `b"merkle_tree"` of the tree PDA of `generation`. It is empty for generation 0,
+/// which keeps the `[b"merkle_tree"]` address the pool's first tree was deployed at.
+pub fn generation_seed(generation: u64) -> Vec<u8> {
+    if generation == 0 {
+        Vec::new()
+    } else {
+        generation.to_le_bytes().to_vec()
+    }
+}
+
 pub struct MerkleTree;
 
 impl MerkleTree {
@@ -19,16 +33,63 @@ impl MerkleTree {
         let initial_root = H::zero_bytes()[height];
         tree_account.root = initial_root;
         tree_account.root_history[0] = initial_root;
+        tree_account.root_count = 1;
         
         Ok(())
     }
 
+    /// Sets up `successor` as the next generation of the nearly full `tree_account`,
+    /// with the same height, root history size, authority and deposit limit.
+    pub fn initialize_successor<H: Hasher>(
+        tree_account: &MerkleTreeAccount,
+        successor: &mut MerkleTreeAccount,
+    ) -> Result<()> {
+        let max_capacity = 1u64 << tree_account.height;
+        require!(
+            max_capacity - tree_account.next_index < ROLLOVER_THRESHOLD,
+            ErrorCode::TreeNotFull
+        );
+
+        successor.authority = tree_account.authority;
+        successor.generation = tree_account.generation
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        successor.max_deposit_amount = tree_account.max_deposit_amount;
+        successor.height = tree_account.height;
+        successor.root_history_size = tree_account.root_history_size;
+
+        Self::initialize::<H>(successor)
+    }
+
     pub fn append<H: Hasher>(
         leaf: [u8; 32],
         tree_account: &mut MerkleTreeAccount,
+        slot: u64,
+    ) -> Result<Vec<[u8; 32]>> {
+        let proof = Self::insert_leaf::<H>(leaf, tree_account)?;
+        Self::record_root(tree_account, slot)?;
+        
+        Ok(proof)
+    }
+
+    /// Appends all leaves and records only the final root in the root history,
+    /// so a batch takes a single root history slot.
+    pub fn append_batch<H: Hasher>(
+        leaves: &[[u8; 32]],
+        tree_account: &mut MerkleTreeAccount,
+        slot: u64,
+    ) -> Result<()> {
+        for leaf in leaves {
+            Self::insert_leaf::<H>(*leaf, tree_account)?;
+        }
+        Self::record_root(tree_account, slot)
+    }
+
+    fn insert_leaf<H: Hasher>(
+        leaf: [u8; 32],
+        tree_account: &mut MerkleTreeAccount,
     ) -> Result<Vec<[u8; 32]>> {
         let height = tree_account.height as usize;
-        let root_history_size = tree_account.root_history_size as usize;
         
         // Check if tree is full before appending
         // Maximum capacity is 2^height leaves
@@ -67,13 +128,24 @@ impl MerkleTree {
             .checked_add(1)
             .ok_or(ErrorCode::ArithmeticOverflow)?;
         
+        Ok(proof)
+    }
+
+    /// Overwrites the oldest root history entry whether or not the root archive has copied it.
+    fn record_root(tree_account: &mut MerkleTreeAccount, slot: u64) -> Result<()> {
+        let root_history_size = tree_account.root_history_size as usize;
+        
         let new_root_index = (tree_account.root_index as usize)
             .checked_add(1)
             .ok_or(ErrorCode::ArithmeticOverflow)? % root_history_size;
         tree_account.root_index = new_root_index as u64;
-        tree_account.root_history[new_root_index] = current_level_hash;
+        tree_account.root_history[new_root_index] = tree_account.root;
+        tree_account.root_history_slots[new_root_index] = slot;
+        tree_account.root_count = tree_account.root_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
         
-        Ok(proof)
+        Ok(())
     }
 
     pub fn is_known_root(tree_account: &MerkleTreeAccount, root: [u8; 32]) -> bool {
@@ -103,4 +175,20 @@ impl MerkleTree {
         
         false
     }
-} 
\ No newline at end of file
+
+    /// Root of a tree of `height` holding only `leaf`, at `leaf_index`. A proof against it shows
+    /// its input is that leaf at that index, so the input's nullifier is the one a spend against
+    /// the real tree would reveal.
+    pub fn singleton_root<H: Hasher>(leaf: [u8; 32], leaf_index: u64, height: u8) -> [u8; 32] {
+        let zero_bytes = H::zero_bytes();
+        let mut node = leaf;
+        for (level, zero_byte) in zero_bytes.iter().take(height as usize).enumerate() {
+            node = if (leaf_index >> level) & 1 == 0 {
+                H::hashv(&[&node, zero_byte]).unwrap()
+            } else {
+                H::hashv(&[zero_byte, &node]).unwrap()
+            };
+        }
+        node
+    }
+}
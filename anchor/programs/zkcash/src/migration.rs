@@ -0,0 +1,161 @@
+use crate::{ErrorCode, FeeSchedule, GlobalConfig, MerkleTreeAccount, TreeTokenAccount, MERKLE_TREE_HEIGHT};
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_lang::Discriminator;
+
+/// MerkleTreeAccount as first deployed, before root slots, root counts and tree generations.
+#[zero_copy]
+pub struct LegacyMerkleTreeAccount {
+    pub authority: Pubkey,
+    pub next_index: u64,
+    pub subtrees: [[u8; 32]; MERKLE_TREE_HEIGHT as usize],
+    pub root: [u8; 32],
+    pub root_history: [[u8; 32]; 100],
+    pub root_index: u64,
+    pub max_deposit_amount: u64,
+    pub height: u8,
+    pub root_history_size: u8,
+    pub bump: u8,
+    pub _padding: [u8; 5],
+}
+
+/// GlobalConfig as first deployed, with a single rate per fee.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LegacyGlobalConfig {
+    pub authority: Pubkey,
+    pub deposit_fee_rate: u16,
+    pub withdrawal_fee_rate: u16,
+    pub fee_error_margin: u16,
+    pub bump: u8,
+}
+
+/// TreeTokenAccount as first deployed, before the lamport counters.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LegacyTreeTokenAccount {
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+/// Account sizes of the first deployment, discriminator included.
+pub const LEGACY_MERKLE_TREE_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<LegacyMerkleTreeAccount>();
+pub const LEGACY_GLOBAL_CONFIG_SIZE: usize = 8 + std::mem::size_of::<LegacyGlobalConfig>();
+pub const LEGACY_TREE_TOKEN_ACCOUNT_SIZE: usize = 8 + std::mem::size_of::<LegacyTreeTokenAccount>();
+
+impl LegacyMerkleTreeAccount {
+    /// The tree in the current layout, as generation 0. The legacy tree kept no slots or root
+    /// count, so every root in its history counts as recorded at `slot` and none as archived.
+    pub fn migrate(&self, slot: u64) -> MerkleTreeAccount {
+        let root_history_size = self.root_history_size as u64;
+        // The history has wrapped around once the entry after the current root is in use
+        let next_entry = ((self.root_index + 1) % root_history_size) as usize;
+        let root_count = if self.root_history[next_entry] != [0u8; 32] {
+            root_history_size + self.root_index + 1
+        } else {
+            self.root_index + 1
+        };
+
+        MerkleTreeAccount {
+            authority: self.authority,
+            generation: 0,
+            next_index: self.next_index,
+            subtrees: self.subtrees,
+            root: self.root,
+            root_history: self.root_history,
+            root_history_slots: [slot; 100],
+            root_index: self.root_index,
+            root_count,
+            archived_root_count: 0,
+            max_deposit_amount: self.max_deposit_amount,
+            height: self.height,
+            root_history_size: self.root_history_size,
+            bump: self.bump,
+            _padding: [0u8; 5],
+        }
+    }
+}
+
+impl LegacyGlobalConfig {
+    /// The config in the current layout. The legacy rates become proportional fee schedules,
+    /// transfers stay free and every later setting starts out as initialize sets it.
+    pub fn migrate(&self) -> GlobalConfig {
+        GlobalConfig {
+            authority: self.authority,
+            deposit_fee: FeeSchedule::from_rate(self.deposit_fee_rate),
+            withdrawal_fee: FeeSchedule::from_rate(self.withdrawal_fee_rate),
+            transfer_fee: FeeSchedule::from_rate(0),
+            fee_error_margin: self.fee_error_margin,
+            config_timelock_slots: 0,
+            next_config_change_id: 0,
+            max_archived_root_age_slots: 1_512_000, // ~7 days at 400ms slots
+            current_tree_generation: 0,
+            guardian: self.authority,
+            large_withdrawal_threshold: 0,
+            withdrawal_delay_slots: 216_000, // ~1 day at 400ms slots
+            denominations: Default::default(),
+            denomination_count: 0,
+            price_feed: Pubkey::default(),
+            max_price_age_slots: 150, // ~1 minute at 400ms slots
+            max_deposit_usd: 0,
+            max_tvl_usd: 0,
+            velocity_window_slots: 216_000, // ~1 day at 400ms slots
+            max_deposit_per_window: 0,
+            bump: self.bump,
+        }
+    }
+}
+
+impl LegacyTreeTokenAccount {
+    /// The vault account in the current layout. The legacy vault kept no counters, so the
+    /// `shielded` lamports it holds for outstanding notes count as deposited and the rest as
+    /// never having moved, which keeps net_shielded equal to what the notes are owed.
+    pub fn migrate(&self, shielded: u64) -> TreeTokenAccount {
+        TreeTokenAccount {
+            authority: self.authority,
+            total_deposited: shielded,
+            total_withdrawn: 0,
+            total_fees: 0,
+            denomination_deposits: Default::default(),
+            denomination_withdrawals: Default::default(),
+            bump: self.bump,
+        }
+    }
+}
+
+/// Checks that `account` holds `legacy_size` bytes starting with the discriminator of `T`.
+pub fn check_legacy_layout<T: Discriminator>(account: &AccountInfo, legacy_size: usize) -> Result<()> {
+    let data = account.try_borrow_data()?;
+    require!(
+        data.len() == legacy_size && data.starts_with(T::DISCRIMINATOR),
+        ErrorCode::NotLegacyLayout
+    );
+
+    Ok(())
+}
+
+/// Grows `account` to `new_len` bytes, with `payer` topping it up to the new rent exempt minimum.
+pub fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    new_len: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let top_up = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+
+    account.realloc(new_len, true)?;
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use crate::governance::ConfigChange;
+use crate::{ErrorCode, FeeSchedule, Multisig, MultisigProposal};
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+
+/// Maximum number of multisig members. Approvals are tracked as a u16 bitmask.
+pub const MAX_MULTISIG_MEMBERS: usize = 10;
+
+/// Maximum number of accounts a proposal binds its action to, more than any admin instruction takes.
+pub const MAX_MULTISIG_ACTION_ACCOUNTS: usize = 10;
+
+/// An admin instruction a multisig proposal executes once enough members approved it.
+/// Each variant carries the arguments of the instruction of the same name. Every instruction
+/// gated on the authority or the guardian has one, so handing either role to the multisig never
+/// strands an instruction; permissionless ones (rollover_tree, initialize_root_archive, ...) are
+/// called directly.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub enum MultisigAction {
+    UpdateDepositLimit {
+        new_limit: u64,
+    },
+    UpdateGlobalConfig {
+        deposit_fee: Option<FeeSchedule>,
+        withdrawal_fee: Option<FeeSchedule>,
+        transfer_fee: Option<FeeSchedule>,
+        fee_error_margin: Option<u16>,
+    },
+    QueueConfigChange {
+        change: ConfigChange,
+    },
+    CancelConfigChange {
+        id: u64,
+    },
+    SetMultisigMembers {
+        members: Vec<Pubkey>,
+        threshold: u8,
+    },
+    AddToDenylist {
+        address: Pubkey,
+    },
+    RemoveFromDenylist {
+        address: Pubkey,
+    },
+    ResolveCancelledWithdrawal {
+        nullifier: [u8; 32],
+        recipient: Pubkey,
+    },
+    InitializeCommitmentQueue {
+        shard: u8,
+    },
+    CancelWithdrawal,
+}
+
+impl MultisigAction {
+    /// Serialized instruction data for the admin instruction this action runs.
+    pub fn instruction_data(&self) -> Vec<u8> {
+        match self.clone() {
+            MultisigAction::UpdateDepositLimit { new_limit } => {
+                crate::instruction::UpdateDepositLimit { new_limit }.data()
+            }
+            MultisigAction::UpdateGlobalConfig {
+                deposit_fee,
+                withdrawal_fee,
+                transfer_fee,
+                fee_error_margin,
+            } => crate::instruction::UpdateGlobalConfig {
+                deposit_fee,
+                withdrawal_fee,
+                transfer_fee,
+                fee_error_margin,
+            }
+            .data(),
+            MultisigAction::QueueConfigChange { change } => {
+                crate::instruction::QueueConfigChange { change }.data()
+            }
+            MultisigAction::CancelConfigChange { id } => {
+                crate::instruction::CancelConfigChange { id }.data()
+            }
+            MultisigAction::SetMultisigMembers { members, threshold } => {
+                crate::instruction::SetMultisigMembers { members, threshold }.data()
+            }
+            MultisigAction::AddToDenylist { address } => {
+                crate::instruction::AddToDenylist { address }.data()
+            }
+            MultisigAction::RemoveFromDenylist { address } => {
+                crate::instruction::RemoveFromDenylist { address }.data()
+            }
+            MultisigAction::ResolveCancelledWithdrawal { nullifier, recipient } => {
+                crate::instruction::ResolveCancelledWithdrawal { nullifier, recipient }.data()
+            }
+            MultisigAction::InitializeCommitmentQueue { shard } => {
+                crate::instruction::InitializeCommitmentQueue { shard }.data()
+            }
+            MultisigAction::CancelWithdrawal => crate::instruction::CancelWithdrawal {}.data(),
+        }
+    }
+}
+
+/// Checks that a proposal binds its action to at most MAX_MULTISIG_ACTION_ACCOUNTS accounts.
+pub fn validate_action_accounts(accounts: &[Pubkey]) -> Result<()> {
+    require!(
+        accounts.len() <= MAX_MULTISIG_ACTION_ACCOUNTS,
+        ErrorCode::MultisigAccountsMismatch
+    );
+
+    Ok(())
+}
+
+/// Checks that the member set is non-empty, fits in the account, has no duplicates
+/// and that the threshold is reachable.
+pub fn validate_members(members: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(
+        !members.is_empty() && members.len() <= MAX_MULTISIG_MEMBERS,
+        ErrorCode::InvalidMultisigMembers
+    );
+
+    for (i, member) in members.iter().enumerate() {
+        require!(
+            !members[..i].contains(member),
+            ErrorCode::InvalidMultisigMembers
+        );
+    }
+
+    require!(
+        threshold > 0 && threshold as usize <= members.len(),
+        ErrorCode::InvalidMultisigThreshold
+    );
+
+    Ok(())
+}
+
+impl Multisig {
+    /// Replaces the member set. Bumping the nonce invalidates all open proposals,
+    /// whose approval bitmasks refer to the old member indices.
+    pub fn set_members(&mut self, members: &[Pubkey], threshold: u8) -> Result<()> {
+        validate_members(members, threshold)?;
+
+        self.members = [Pubkey::default(); MAX_MULTISIG_MEMBERS];
+        self.members[..members.len()].copy_from_slice(members);
+        self.member_count = members.len() as u8;
+        self.threshold = threshold;
+        self.nonce = self.nonce
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    pub fn member_index(&self, key: &Pubkey) -> Option<usize> {
+        self.members[..self.member_count as usize]
+            .iter()
+            .position(|member| member == key)
+    }
+
+    pub fn is_approved(&self, approvals: u16) -> bool {
+        approvals.count_ones() >= self.threshold as u32
+    }
+
+    /// Whether the member set changed since the proposal was created, so it can no longer run.
+    pub fn is_stale(&self, proposal: &MultisigProposal) -> bool {
+        proposal.multisig_nonce != self.nonce
+    }
+}
+
+impl MultisigProposal {
+    /// Checks that execute_multisig_proposal was given exactly the accounts the members approved,
+    /// in order. Arguments alone do not pin down an action: cancel_withdrawal, for one, takes
+    /// none and cancels whichever pending withdrawal it is passed.
+    pub fn check_accounts(&self, accounts: &[AccountInfo]) -> Result<()> {
+        require!(
+            accounts.len() == self.accounts.len()
+                && accounts.iter().zip(&self.accounts).all(|(account, key)| account.key == key),
+            ErrorCode::MultisigAccountsMismatch
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{ErrorCode, GlobalConfig, PendingWithdrawal};
+use anchor_lang::prelude::*;
+
+/// A withdrawal that is held back in a `PendingWithdrawal` account until `release_slot`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DelayedWithdrawal {
+    pub amount: u64,
+    pub release_slot: u64,
+}
+
+impl GlobalConfig {
+    /// Returns the delay to apply to a withdrawal of `amount` lamports, if it is above the
+    /// large withdrawal threshold. A threshold of 0 disables delayed withdrawals.
+    pub fn delay_withdrawal(&self, amount: u64, current_slot: u64) -> Result<Option<DelayedWithdrawal>> {
+        if self.large_withdrawal_threshold == 0 || amount <= self.large_withdrawal_threshold {
+            return Ok(None);
+        }
+
+        let release_slot = current_slot
+            .checked_add(self.withdrawal_delay_slots)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(Some(DelayedWithdrawal { amount, release_slot }))
+    }
+
+    /// For instructions that cannot queue a withdrawal: rejects amounts above the threshold.
+    pub fn check_immediate_withdrawal(&self, amount: u64) -> Result<()> {
+        require!(
+            self.large_withdrawal_threshold == 0 || amount <= self.large_withdrawal_threshold,
+            ErrorCode::WithdrawalAboveThreshold
+        );
+        Ok(())
+    }
+}
+
+impl PendingWithdrawal {
+    pub fn is_claimable(&self, current_slot: u64) -> bool {
+        current_slot >= self.release_slot
+    }
+}
+
+/// Fills in the pending withdrawal account of a delayed withdrawal. The account must be
+/// provided exactly when the withdrawal is delayed.
+pub fn record_pending_withdrawal(
+    pending_withdrawal: Option<&mut PendingWithdrawal>,
+    bump: Option<u8>,
+    delayed: Option<DelayedWithdrawal>,
+    recipient: Pubkey,
+    payer: Pubkey,
+    nullifier: [u8; 32],
+) -> Result<()> {
+    let Some(delayed) = delayed else {
+        require!(pending_withdrawal.is_none(), ErrorCode::PendingWithdrawalNotAllowed);
+        return Ok(());
+    };
+
+    let pending_withdrawal = pending_withdrawal.ok_or(ErrorCode::PendingWithdrawalRequired)?;
+    pending_withdrawal.nullifier = nullifier;
+    pending_withdrawal.recipient = recipient;
+    pending_withdrawal.payer = payer;
+    pending_withdrawal.amount = delayed.amount;
+    pending_withdrawal.release_slot = delayed.release_slot;
+    pending_withdrawal.bump = bump.ok_or(ErrorCode::PendingWithdrawalRequired)?;
+    pending_withdrawal.cancelled = false;
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use crate::{ErrorCode, GlobalConfig};
+#[cfg(feature = "localnet")]
+use crate::MockPriceFeed;
+use anchor_lang::prelude::*;
+
+/// USD amounts (prices and limits) are in micro-USD: 1_000_000 = $1.
+pub const MICRO_USD_PER_USD: u64 = 1_000_000;
+
+const LAMPORTS_PER_SOL: u128 = 1_000_000_000;
+
+/// Pyth Solana receiver program, the owner of `PriceUpdateV2` accounts.
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Pyth feed id of SOL/USD.
+pub const PYTH_SOL_USD_FEED_ID: [u8; 32] = [
+    0xef, 0x0d, 0x8b, 0x6f, 0xda, 0x2c, 0xeb, 0xa4, 0x1d, 0xa1, 0x5d, 0x40, 0x95, 0xd1, 0xda, 0x39,
+    0x2a, 0x0d, 0x2f, 0x8e, 0xd0, 0xc6, 0xc7, 0xbc, 0x0f, 0x4c, 0xfa, 0xc8, 0xc2, 0x80, 0xb5, 0x6d,
+];
+
+/// A SOL/USD price and the slot it was published at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Price {
+    pub micro_usd_per_sol: u64,
+    pub publish_slot: u64,
+}
+
+impl Price {
+    pub fn usd_value(&self, lamports: u64) -> Result<u64> {
+        let value = (lamports as u128) * (self.micro_usd_per_sol as u128) / LAMPORTS_PER_SOL;
+        Ok(u64::try_from(value).map_err(|_| ErrorCode::ArithmeticOverflow)?)
+    }
+}
+
+/// Adapter over a price feed account. Oracle feeds get an implementation here and a branch in
+/// `load_price`; the mock feed is for local testing and only built with the `localnet` feature.
+pub trait PriceFeed {
+    fn latest_price(&self) -> Result<Price>;
+}
+
+#[cfg(feature = "localnet")]
+impl PriceFeed for MockPriceFeed {
+    fn latest_price(&self) -> Result<Price> {
+        Ok(Price {
+            micro_usd_per_sol: self.micro_usd_per_sol,
+            publish_slot: self.publish_slot,
+        })
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PythVerificationLevel {
+    Partial { num_signatures: u8 },
+    Full,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PythPriceMessage {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub prev_publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+}
+
+/// A Pyth `PriceUpdateV2` account, as posted by the Pyth receiver program. Read by hand so the
+/// program does not depend on the Pyth SDK; the layout is the SDK's borsh layout.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PythPriceUpdate {
+    pub write_authority: Pubkey,
+    pub verification_level: PythVerificationLevel,
+    pub price_message: PythPriceMessage,
+    pub posted_slot: u64,
+}
+
+impl PythPriceUpdate {
+    pub const DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+    pub fn try_from_account_data(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= Self::DISCRIMINATOR.len() && data[..8] == Self::DISCRIMINATOR,
+            ErrorCode::InvalidPriceFeed
+        );
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(ErrorCode::InvalidPriceFeed))
+    }
+}
+
+impl PriceFeed for PythPriceUpdate {
+    /// Only fully verified SOL/USD updates are accepted. The price is dated by the slot it was
+    /// posted at, so max_price_age_slots bounds how long ago it reached the chain.
+    fn latest_price(&self) -> Result<Price> {
+        require!(self.verification_level == PythVerificationLevel::Full, ErrorCode::InvalidPriceFeed);
+        require!(self.price_message.feed_id == PYTH_SOL_USD_FEED_ID, ErrorCode::InvalidPriceFeed);
+        require!(self.price_message.price > 0, ErrorCode::InvalidPriceFeed);
+
+        // price * 10^exponent USD = price * 10^(exponent + 6) micro-USD
+        let price = self.price_message.price as u128;
+        let scale = self.price_message.exponent
+            .checked_add(6)
+            .ok_or(ErrorCode::InvalidPriceFeed)?;
+        let factor = 10u128
+            .checked_pow(scale.unsigned_abs())
+            .ok_or(ErrorCode::InvalidPriceFeed)?;
+        let micro_usd_per_sol = if scale >= 0 {
+            price.checked_mul(factor).ok_or(ErrorCode::ArithmeticOverflow)?
+        } else {
+            price / factor
+        };
+        require!(micro_usd_per_sol > 0, ErrorCode::InvalidPriceFeed);
+
+        Ok(Price {
+            micro_usd_per_sol: u64::try_from(micro_usd_per_sol).map_err(|_| ErrorCode::ArithmeticOverflow)?,
+            publish_slot: self.posted_slot,
+        })
+    }
+}
+
+/// Reads the configured price feed. Returns None when no feed is configured, in which case the
+/// USD limits are off.
+pub fn load_price(global_config: &GlobalConfig, price_feed: Option<&AccountInfo>) -> Result<Option<Price>> {
+    if global_config.price_feed == Pubkey::default() {
+        return Ok(None);
+    }
+
+    let price_feed = price_feed.ok_or(ErrorCode::PriceFeedRequired)?;
+    require_keys_eq!(price_feed.key(), global_config.price_feed, ErrorCode::InvalidPriceFeed);
+
+    let data = price_feed.try_borrow_data()?;
+    let feed: Box<dyn PriceFeed> = match price_feed.owner {
+        owner if owner == &PYTH_RECEIVER_PROGRAM_ID => Box::new(PythPriceUpdate::try_from_account_data(&data)?),
+        #[cfg(feature = "localnet")]
+        owner if owner == &crate::ID => Box::new(MockPriceFeed::try_deserialize(&mut &data[..])?),
+        _ => return err!(ErrorCode::InvalidPriceFeed),
+    };
+
+    Ok(Some(feed.latest_price()?))
+}
+
+impl GlobalConfig {
+    /// Checks a deposit of `deposit_amount` lamports against the USD deposit limit and TVL cap,
+    /// with `net_shielded` lamports already in the pool. A limit of 0 is disabled.
+    pub fn check_usd_limits(&self, price: Option<Price>, deposit_amount: u64, net_shielded: u64, current_slot: u64) -> Result<()> {
+        let Some(price) = price else {
+            return Ok(());
+        };
+
+        require!(
+            current_slot.saturating_sub(price.publish_slot) <= self.max_price_age_slots,
+            ErrorCode::StalePrice
+        );
+
+        if self.max_deposit_usd > 0 {
+            require!(price.usd_value(deposit_amount)? <= self.max_deposit_usd, ErrorCode::DepositLimitExceeded);
+        }
+
+        if self.max_tvl_usd > 0 {
+            let tvl = net_shielded.checked_add(deposit_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+            require!(price.usd_value(tvl)? <= self.max_tvl_usd, ErrorCode::TvlCapExceeded);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{DepositOrigin, ErrorCode};
+use anchor_lang::prelude::*;
+
+impl DepositOrigin {
+    /// Commitment and leaf index of the deposit's output `output_index`, once it is in the tree.
+    pub fn note(&self, output_index: u8) -> Result<([u8; 32], u64)> {
+        let output_index = output_index as usize;
+        require!(output_index < self.commitments.len(), ErrorCode::CommitmentNotInDeposit);
+        let leaf_index = self.leaf_indices[output_index].ok_or(ErrorCode::DepositNotInserted)?;
+
+        Ok((self.commitments[output_index], leaf_index))
+    }
+
+    /// Takes a ragequit of `amount` from what is left of the deposit. Together a deposit's notes
+    /// never return more than the deposit put in, whatever value they hold.
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        self.amount = self.amount
+            .checked_sub(amount)
+            .ok_or(ErrorCode::RagequitExceedsDeposit)?;
+
+        Ok(())
+    }
+}
+
+/// Fills in the origin record of a transaction's outputs. Every deposit must create one, so its
+/// notes can be ragequit to the depositor, and no other transaction may, since anyone holding a
+/// record could ragequit its notes to themselves.
+///
+/// `first_leaf_index` is the leaf of the first output when the outputs go straight into the
+/// tree; queued outputs get theirs from record_queued_leaf_indices.
+pub fn record_deposit_origin(
+    deposit_origin: Option<&mut DepositOrigin>,
+    bump: Option<u8>,
+    ext_amount: i64,
+    fee: u64,
+    depositor: Pubkey,
+    commitments: [[u8; 32]; 2],
+    first_leaf_index: Option<u64>,
+) -> Result<()> {
+    if ext_amount <= 0 {
+        require!(deposit_origin.is_none(), ErrorCode::DepositOriginNotAllowed);
+        return Ok(());
+    }
+
+    let deposit_origin = deposit_origin.ok_or(ErrorCode::DepositOriginRequired)?;
+    deposit_origin.depositor = depositor;
+    deposit_origin.commitments = commitments;
+    deposit_origin.leaf_indices = match first_leaf_index {
+        Some(leaf_index) => [Some(leaf_index), Some(leaf_index + 1)],
+        None => [None, None],
+    };
+    deposit_origin.amount = (ext_amount as u64)
+        .checked_sub(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    deposit_origin.bump = bump.ok_or(ErrorCode::DepositOriginRequired)?;
+
+    Ok(())
+}
+
+/// Records the leaf index of every queued deposit output that process_commitment_queue inserts
+/// from `start_index` on. `origins` are the deposit origin records of the queued commitments
+/// flagged as deposit outputs, in queue order; a record appears twice if both of its outputs
+/// are inserted.
+pub fn record_queued_leaf_indices<'info>(
+    queued: &[([u8; 32], bool)],
+    start_index: u64,
+    origins: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    let mut origins = origins.iter();
+    for (offset, (commitment, has_origin)) in queued.iter().enumerate() {
+        if !has_origin {
+            continue;
+        }
+
+        let origin_info = origins.next().ok_or(ErrorCode::DepositOriginRequired)?;
+        let mut origin = Account::<DepositOrigin>::try_from(origin_info)?;
+        let output_index = (0..origin.commitments.len())
+            .find(|&i| origin.commitments[i] == *commitment && origin.leaf_indices[i].is_none())
+            .ok_or(ErrorCode::InvalidDepositOrigin)?;
+        origin.leaf_indices[output_index] = Some(start_index + offset as u64);
+        origin.exit(&crate::ID)?;
+    }
+
+    Ok(())
+}
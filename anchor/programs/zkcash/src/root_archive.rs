@@ -0,0 +1,64 @@
+use crate::{ErrorCode, MerkleTreeAccount, RootArchive};
+use anchor_lang::prelude::*;
+
+/// Number of roots an archive page holds. Page `p` holds roots `p * size .. (p + 1) * size`,
+/// numbered in the order the tree recorded them (see MerkleTreeAccount::root_count).
+pub const ROOT_ARCHIVE_PAGE_SIZE: usize = 128;
+
+impl RootArchive {
+    /// Whether `root` is archived in this page and was recorded at most `max_age_slots` before `current_slot`.
+    pub fn is_valid_root(&self, root: [u8; 32], current_slot: u64, max_age_slots: u64) -> bool {
+        if root == [0u8; 32] {
+            return false;
+        }
+
+        self.roots[..self.len as usize]
+            .iter()
+            .zip(self.slots.iter())
+            .any(|(archived_root, slot)| {
+                *archived_root == root && current_slot.saturating_sub(*slot) <= max_age_slots
+            })
+    }
+}
+
+/// Copies the roots the tree has recorded since the last call into `archive`, which must be
+/// the page of the oldest unarchived root. Stops when the page is full.
+///
+/// Roots that were evicted from the root history before anyone archived them are skipped.
+/// Returns the number of roots archived.
+pub fn archive_roots(tree_account: &mut MerkleTreeAccount, archive: &mut RootArchive) -> Result<u64> {
+    let root_history_size = tree_account.root_history_size as u64;
+    let oldest_available = tree_account.root_count.saturating_sub(root_history_size);
+    if tree_account.archived_root_count < oldest_available {
+        msg!("{} roots were evicted before being archived", oldest_available - tree_account.archived_root_count);
+        tree_account.archived_root_count = oldest_available;
+    }
+
+    require!(
+        archive.page == tree_account.archived_root_count / ROOT_ARCHIVE_PAGE_SIZE as u64,
+        ErrorCode::WrongRootArchivePage
+    );
+
+    let mut archived = 0;
+    while tree_account.archived_root_count < tree_account.root_count {
+        let position = (tree_account.archived_root_count % ROOT_ARCHIVE_PAGE_SIZE as u64) as usize;
+        if archived > 0 && position == 0 {
+            break; // page full
+        }
+
+        let history_index = (tree_account.archived_root_count % root_history_size) as usize;
+        let slot = tree_account.root_history_slots[history_index];
+        archive.roots[position] = tree_account.root_history[history_index];
+        archive.slots[position] = slot;
+        if archive.len == 0 {
+            archive.start_slot = slot;
+        }
+        archive.end_slot = slot;
+        archive.len = position as u64 + 1;
+
+        tree_account.archived_root_count += 1;
+        archived += 1;
+    }
+
+    Ok(archived)
+}
@@ -0,0 +1,45 @@
+use crate::{ErrorCode, TreeTokenAccount};
+use anchor_lang::prelude::*;
+
+impl TreeTokenAccount {
+    /// Adds a transaction's lamport flows to the cumulative counters. A positive `ext_amount`
+    /// is shielded, a negative one unshielded; the fee is paid out of the pool either way.
+    pub fn record_transaction(&mut self, ext_amount: i64, fee: u64) -> Result<()> {
+        if ext_amount > 0 {
+            self.total_deposited = self.total_deposited
+                .checked_add(ext_amount as u64)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        } else if ext_amount < 0 {
+            self.total_withdrawn = self.total_withdrawn
+                .checked_add(ext_amount.unsigned_abs())
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+
+        self.total_fees = self.total_fees
+            .checked_add(fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    /// Lamports owed to outstanding notes: everything deposited minus what left the pool.
+    pub fn net_shielded(&self) -> Result<u64> {
+        Ok(self.total_deposited
+            .checked_sub(self.total_withdrawn)
+            .and_then(|amount| amount.checked_sub(self.total_fees))
+            .ok_or(ErrorCode::InvariantViolated)?)
+    }
+}
+
+/// Checks that the vault holds enough lamports to pay out every outstanding note
+/// and stay rent exempt.
+pub fn check_solvency(tree_token_account: &TreeTokenAccount, vault_balance: u64, rent_exempt_minimum: u64) -> Result<()> {
+    let required = tree_token_account
+        .net_shielded()?
+        .checked_add(rent_exempt_minimum)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    require!(vault_balance >= required, ErrorCode::InvariantViolated);
+
+    Ok(())
+}
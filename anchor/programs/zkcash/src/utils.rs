@@ -1,4 +1,4 @@
-use crate::Proof;
+use crate::{FeeQuote, FeeSchedule, Payout, Proof};
 use crate::groth16::{Groth16Verifier, Groth16Verifyingkey};
 use crate::ErrorCode;
 use ark_bn254;
@@ -13,6 +13,9 @@ type G1 = ark_bn254::g1::G1Affine;
 
 pub const SOL_ADDRESS: Pubkey = anchor_lang::pubkey!("11111111111111111111111111111112");
 
+/// Maximum number of recipients of a multi-recipient withdrawal, bounded by the transaction size.
+pub const MAX_PAYOUTS: usize = 10;
+
 pub const VERIFYING_KEY: Groth16Verifyingkey =  Groth16Verifyingkey {
 	nr_pubinputs: 7,
 
@@ -128,86 +131,157 @@ pub fn check_public_amount(ext_amount: i64, fee: u64, public_amount_bytes: [u8;
 }
 
 /**
- * Validates that the provided fee meets the minimum required fee based on global configuration.
+ * Calculates the fee a fee schedule charges for a given amount:
+ * expected_fee = clamp(flat_fee + amount * rate_bps / 10000, min_fee, max_fee)
+ *
+ * @param amount The absolute amount moved in or out of the pool (0 for internal transfers)
+ * @param schedule The fee schedule of the operation
+ * @return The expected fee in lamports, Err(ErrorCode) on overflow
+ */
+pub fn calculate_expected_fee(amount: u64, schedule: &FeeSchedule) -> Result<u64> {
+    let proportional_fee = (amount as u128)
+        .checked_mul(schedule.rate_bps as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    let expected_fee: u64 = proportional_fee
+        .checked_add(schedule.flat_fee as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .try_into()
+        .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+    Ok(expected_fee.max(schedule.min_fee).min(schedule.max_fee))
+}
+
+/**
+ * Quotes the fee of an operation with the same schedule selection and tolerance as validate_fee.
  * 
- * For deposits (ext_amount > 0):
- * - expected_fee = (ext_amount * deposit_fee_rate) / 10000
- * - minimum_fee = expected_fee * (1 - fee_error_margin/10000)
+ * Deposits (ext_amount > 0) use deposit_fee, withdrawals (ext_amount < 0) use
+ * withdrawal_fee and internal transfers (ext_amount == 0) use transfer_fee:
+ * - expected_fee = clamp(flat_fee + amount * rate_bps / 10000, min_fee, max_fee)
+ * - min_acceptable_fee = expected_fee * (1 - fee_error_margin/10000)
+ * - max_fee is the cap of the selected schedule
  * 
- * For withdrawals (ext_amount < 0):
- * - expected_fee = (abs(ext_amount) * withdrawal_fee_rate) / 10000
- * - minimum_fee = expected_fee * (1 - fee_error_margin/10000)
+ * @param ext_amount The external amount (positive for deposits, negative for withdrawals)
+ * @param deposit_fee Fee schedule for deposits
+ * @param withdrawal_fee Fee schedule for withdrawals
+ * @param transfer_fee Fee schedule for internal transfers
+ * @param fee_error_margin Tolerance rate (in basis points, 0-10000)
+ * @return The fee quote, Err(ErrorCode) on overflow
+ */
+pub fn calculate_fee_quote(
+    ext_amount: i64,
+    deposit_fee: &FeeSchedule,
+    withdrawal_fee: &FeeSchedule,
+    transfer_fee: &FeeSchedule,
+    fee_error_margin: u16,
+) -> Result<FeeQuote> {
+    let (amount, schedule) = if ext_amount > 0 {
+        (ext_amount as u64, deposit_fee)
+    } else if ext_amount < 0 {
+        let withdrawal_amount = ext_amount.checked_neg()
+            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
+        (withdrawal_amount, withdrawal_fee)
+    } else {
+        (0, transfer_fee)
+    };
+
+    let expected_fee = calculate_expected_fee(amount, schedule)?;
+
+    // Calculate minimum acceptable fee: expected_fee * (1 - fee_error_margin/10000)
+    let min_acceptable_fee = if expected_fee > 0 {
+        let error_multiplier = 10000u128.checked_sub(fee_error_margin as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        (expected_fee as u128)
+            .checked_mul(error_multiplier)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::ArithmeticOverflow)? as u64
+    } else {
+        0 // If expected fee is 0, minimum is also 0
+    };
+
+    Ok(FeeQuote {
+        expected_fee,
+        min_acceptable_fee,
+        max_fee: schedule.max_fee,
+    })
+}
+
+/**
+ * Validates the provided fee against the fee schedule of the operation.
+ * The provided fee must be at least the quoted min_acceptable_fee and at most
+ * the schedule's max_fee (see calculate_fee_quote).
  * 
  * @param ext_amount The external amount (positive for deposits, negative for withdrawals)
  * @param provided_fee The fee provided by the user
- * @param deposit_fee_rate Fee rate for deposits (in basis points, 0-10000)
- * @param withdrawal_fee_rate Fee rate for withdrawals (in basis points, 0-10000)
+ * @param deposit_fee Fee schedule for deposits
+ * @param withdrawal_fee Fee schedule for withdrawals
+ * @param transfer_fee Fee schedule for internal transfers
  * @param fee_error_margin Tolerance rate (in basis points, 0-10000)
  * @return Ok(()) if fee is valid, Err(ErrorCode) if invalid
  */
 pub fn validate_fee(
     ext_amount: i64,
     provided_fee: u64,
-    deposit_fee_rate: u16,
-    withdrawal_fee_rate: u16,
+    deposit_fee: &FeeSchedule,
+    withdrawal_fee: &FeeSchedule,
+    transfer_fee: &FeeSchedule,
     fee_error_margin: u16,
 ) -> Result<()> {
-    if ext_amount > 0 {
-        // Deposit: check fee against deposit rate
-        let expected_fee = (ext_amount as u128)
-            .checked_mul(deposit_fee_rate as u128)
-            .ok_or(ErrorCode::ArithmeticOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
-        
-        // Calculate minimum acceptable fee: expected_fee * (1 - fee_error_margin/10000)
-        let min_acceptable_fee = if expected_fee > 0 {
-            let error_multiplier = 10000u128.checked_sub(fee_error_margin as u128)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            (expected_fee as u128)
-                .checked_mul(error_multiplier)
-                .ok_or(ErrorCode::ArithmeticOverflow)?
-                .checked_div(10000)
-                .ok_or(ErrorCode::ArithmeticOverflow)? as u64
-        } else {
-            0 // If expected fee is 0, minimum is also 0
-        };
-        
-        require!(
-            provided_fee >= min_acceptable_fee,
-            ErrorCode::InvalidFeeAmount
-        );
-    } else if ext_amount < 0 {
-        // Withdrawal: check fee against withdrawal rate
-        let withdrawal_amount = ext_amount.checked_neg()
-            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
-        
-        let expected_fee = (withdrawal_amount as u128)
-            .checked_mul(withdrawal_fee_rate as u128)
-            .ok_or(ErrorCode::ArithmeticOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
-        
-        // Calculate minimum acceptable fee: expected_fee * (1 - fee_error_margin/10000)
-        let min_acceptable_fee = if expected_fee > 0 {
-            let error_multiplier = 10000u128.checked_sub(fee_error_margin as u128)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            (expected_fee as u128)
-                .checked_mul(error_multiplier)
-                .ok_or(ErrorCode::ArithmeticOverflow)?
-                .checked_div(10000)
-                .ok_or(ErrorCode::ArithmeticOverflow)? as u64
-        } else {
-            0 // If expected fee is 0, minimum is also 0
-        };
-        
-        require!(
-            provided_fee >= min_acceptable_fee,
-            ErrorCode::InvalidFeeAmount
-        );
-    }
-    // For ext_amount == 0, no fee validation needed
-    
+    let quote = calculate_fee_quote(ext_amount, deposit_fee, withdrawal_fee, transfer_fee, fee_error_margin)?;
+
+    require!(
+        provided_fee >= quote.min_acceptable_fee,
+        ErrorCode::InvalidFeeAmount
+    );
+
+    require!(
+        provided_fee <= quote.max_fee,
+        ErrorCode::FeeExceedsCap
+    );
+
+    Ok(())
+}
+
+/**
+ * Validates that the provided fee does not exceed the user-specified maximum fee
+ * committed in the ext data.
+ * 
+ * @param provided_fee The fee provided by the user
+ * @param user_max_fee The maximum fee the user signed for in the ext data
+ * @return Ok(()) if fee is within the user's bound, Err(ErrorCode) otherwise
+ */
+pub fn validate_max_fee(provided_fee: u64, user_max_fee: u64) -> Result<()> {
+    require!(
+        provided_fee <= user_max_fee,
+        ErrorCode::FeeExceedsUserMaximum
+    );
+
+    Ok(())
+}
+
+/**
+ * Check that the caller is allowed to initialize the pool.
+ * 
+ * @param authority The account calling initialize
+ * @param admin_key Hardcoded admin key; overrides the upgrade authority when set
+ * @param upgrade_authority The program's upgrade authority from its ProgramData account
+ * @return Ok(()) if the caller may initialize, Err(ErrorCode::Unauthorized) otherwise
+ */
+pub fn validate_initializer(
+    authority: &Pubkey,
+    admin_key: Option<Pubkey>,
+    upgrade_authority: Option<Pubkey>,
+) -> Result<()> {
+    let allowed = match admin_key {
+        Some(admin_key) => admin_key,
+        // An immutable program (no upgrade authority) can only be initialized through the override
+        None => upgrade_authority.ok_or(ErrorCode::Unauthorized)?,
+    };
+    require!(authority.eq(&allowed), ErrorCode::Unauthorized);
+
     Ok(())
 }
 
@@ -271,12 +345,14 @@ pub fn verify_proof(proof: Proof, verifying_key: Groth16Verifyingkey) -> bool {
  * Calculate ExtData hash with encrypted outputs included
  * This matches the client-side calculation for hash verification
  */
+#[allow(clippy::too_many_arguments)]
 pub fn calculate_complete_ext_data_hash(
     recipient: Pubkey,
     ext_amount: i64,
     encrypted_output1: &[u8],
     encrypted_output2: &[u8],
     fee: u64,
+    max_fee: u64,
     fee_recipient: Pubkey,
     mint_address: Pubkey,
 ) -> Result<[u8; 32]> {
@@ -287,6 +363,7 @@ pub fn calculate_complete_ext_data_hash(
         pub encrypted_output1: Vec<u8>,
         pub encrypted_output2: Vec<u8>,
         pub fee: u64,
+        pub max_fee: u64,
         pub fee_recipient: Pubkey,
         pub mint_address: Pubkey,
     }
@@ -297,6 +374,7 @@ pub fn calculate_complete_ext_data_hash(
         encrypted_output1: encrypted_output1.to_vec(),
         encrypted_output2: encrypted_output2.to_vec(),
         fee,
+        max_fee,
         fee_recipient,
         mint_address,
     };
@@ -308,6 +386,45 @@ pub fn calculate_complete_ext_data_hash(
     Ok(calculated_ext_data_hash)
 }
 
+/**
+ * Commitment to the payout list of a multi-recipient withdrawal. It takes the place of the
+ * recipient in the ext data hash, so the proof commits to every (recipient, amount) pair.
+ */
+pub fn calculate_payouts_commitment(payouts: &[Payout]) -> Result<Pubkey> {
+    let mut serialized_payouts = b"multi_recipient".to_vec();
+    payouts.to_vec().serialize(&mut serialized_payouts)?;
+
+    Ok(Pubkey::new_from_array(hash(&serialized_payouts).to_bytes()))
+}
+
+/**
+ * Validates the payout list of a multi-recipient withdrawal against its ext_amount.
+ * 
+ * @param payouts The (recipient, amount) pairs to pay
+ * @param ext_amount The external amount, must be negative (a withdrawal)
+ * @return The total withdrawn amount if the payouts add up to -ext_amount, Err(ErrorCode) otherwise
+ */
+pub fn validate_payouts(payouts: &[Payout], ext_amount: i64) -> Result<u64> {
+    require!(
+        !payouts.is_empty() && payouts.len() <= MAX_PAYOUTS,
+        ErrorCode::InvalidPayouts
+    );
+    require!(ext_amount < 0, ErrorCode::InvalidPayouts);
+
+    let withdrawal_amount = ext_amount.checked_neg()
+        .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
+
+    let mut total: u64 = 0;
+    for payout in payouts {
+        require!(payout.amount > 0, ErrorCode::InvalidPayouts);
+        total = total.checked_add(payout.amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+    require!(total == withdrawal_amount, ErrorCode::PayoutsAmountMismatch);
+
+    Ok(total)
+}
+
 pub fn change_endianness(bytes: &[u8]) -> Vec<u8> {
     let mut vec = Vec::new();
     for b in bytes.chunks(32) {
@@ -328,11 +445,12 @@ mod tests {
         // 1000 * 25 / 10000 = 2.5 -> 2 (rounded down)
         // minimum = 2 * 95% = 1.9 -> 1 (rounded down)
         let result = validate_fee(
-            1000,  // ext_amount (deposit)
-            1,     // provided_fee (exact minimum)
-            0,     // deposit_fee_rate (0% - free deposits)
-            25,    // withdrawal_fee_rate (0.25%)
-            500,   // error_rate (5%)
+            1000,                        // ext_amount (deposit)
+            1,                           // provided_fee (exact minimum)
+            &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+            &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -341,11 +459,12 @@ mod tests {
     fn test_validate_fee_deposit_above_minimum() {
         // Test deposit with fee above minimum
         let result = validate_fee(
-            1000,  // ext_amount (deposit)
-            10,    // provided_fee (well above minimum)
-            0,     // deposit_fee_rate (0% - free deposits)
-            25,    // withdrawal_fee_rate (0.25%)
-            500,   // error_rate (5%)
+            1000,                        // ext_amount (deposit)
+            10,                          // provided_fee (well above minimum)
+            &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+            &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -357,11 +476,12 @@ mod tests {
         // 10000 * 0 / 10000 = 0 (expected fee)
         // minimum = 0 * 95% = 0 (minimum acceptable fee)
         let result = validate_fee(
-            10000, // ext_amount (deposit)
-            0,     // provided_fee (even 0 is acceptable for free deposits)
-            0,     // deposit_fee_rate (0% - free deposits)
-            25,    // withdrawal_fee_rate (0.25%)
-            500,   // error_rate (5%)
+            10000,                       // ext_amount (deposit)
+            0,                           // provided_fee (even 0 is acceptable for free deposits)
+            &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+            &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok()); // Should pass since deposits are free
     }
@@ -370,11 +490,12 @@ mod tests {
     fn test_validate_fee_withdrawal_zero_rate() {
         // Test withdrawal with 0% fee rate
         let result = validate_fee(
-            -1000, // ext_amount (withdrawal)
-            5,     // provided_fee (any amount is fine since expected is 0)
-            25,    // deposit_fee_rate
-            0,     // withdrawal_fee_rate (0%)
-            500,   // error_rate (5%)
+            -1000,                       // ext_amount (withdrawal)
+            5,                           // provided_fee (any amount is fine since expected is 0)
+            &FeeSchedule::from_rate(25), // deposit_fee
+            &FeeSchedule::from_rate(0),  // withdrawal_fee (0%)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -385,11 +506,12 @@ mod tests {
         // 1000 * 50 / 10000 = 5
         // minimum = 5 * 95% = 4.75 -> 4 (rounded down)
         let result = validate_fee(
-            -1000, // ext_amount (withdrawal)
-            4,     // provided_fee (exact minimum)
-            25,    // deposit_fee_rate
-            50,    // withdrawal_fee_rate (0.5%)
-            500,   // error_rate (5%)
+            -1000,                       // ext_amount (withdrawal)
+            4,                           // provided_fee (exact minimum)
+            &FeeSchedule::from_rate(25), // deposit_fee
+            &FeeSchedule::from_rate(50), // withdrawal_fee (0.5%)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -400,11 +522,12 @@ mod tests {
         // 1000 * 100 / 10000 = 10
         // minimum = 10 * 95% = 9.5 -> 9 (rounded down)
         let result = validate_fee(
-            -1000, // ext_amount (withdrawal)
-            8,     // provided_fee (below minimum of 9)
-            25,    // deposit_fee_rate
-            100,   // withdrawal_fee_rate (1%)
-            500,   // error_rate (5%)
+            -1000,                        // ext_amount (withdrawal)
+            8,                            // provided_fee (below minimum of 9)
+            &FeeSchedule::from_rate(25),  // deposit_fee
+            &FeeSchedule::from_rate(100), // withdrawal_fee (1%)
+            &FeeSchedule::from_rate(0),   // transfer_fee
+            500,                          // error_rate (5%)
         );
         assert!(result.is_err());
         // In anchor, the error is wrapped, so we need to check the error differently
@@ -421,11 +544,12 @@ mod tests {
     fn test_validate_fee_zero_amount() {
         // Test with zero ext_amount (should always pass)
         let result = validate_fee(
-            0,     // ext_amount (neither deposit nor withdrawal)
-            100,   // provided_fee
-            25,    // deposit_fee_rate
-            50,    // withdrawal_fee_rate
-            500,   // error_rate
+            0,                           // ext_amount (neither deposit nor withdrawal)
+            100,                         // provided_fee
+            &FeeSchedule::from_rate(25), // deposit_fee
+            &FeeSchedule::from_rate(50), // withdrawal_fee
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate
         );
         assert!(result.is_ok());
     }
@@ -435,11 +559,12 @@ mod tests {
         // Test very small deposit that results in 0 expected fee
         // 1 * 25 / 10000 = 0.0025 -> 0 (rounded down)
         let result = validate_fee(
-            1,     // ext_amount (very small deposit)
-            0,     // provided_fee (0 is acceptable when expected is 0)
-            25,    // deposit_fee_rate (0.25%)
-            0,     // withdrawal_fee_rate
-            500,   // error_rate (5%)
+            1,                           // ext_amount (very small deposit)
+            0,                           // provided_fee (0 is acceptable when expected is 0)
+            &FeeSchedule::from_rate(25), // deposit_fee (0.25%)
+            &FeeSchedule::from_rate(0),  // withdrawal_fee
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -450,11 +575,12 @@ mod tests {
         // 1000 * 25 / 10000 = 2.5 -> 2
         // minimum = 2 * 50% = 1
         let result = validate_fee(
-            1000,  // ext_amount (deposit)
-            1,     // provided_fee (minimum with 50% fee error margin)
-            25,    // deposit_fee_rate (0.25%)
-            0,     // withdrawal_fee_rate
-            5000,  // fee_error_margin (50%)
+            1000,                        // ext_amount (deposit)
+            1,                           // provided_fee (minimum with 50% fee error margin)
+            &FeeSchedule::from_rate(25), // deposit_fee (0.25%)
+            &FeeSchedule::from_rate(0),  // withdrawal_fee
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            5000,                        // fee_error_margin (50%)
         );
         assert!(result.is_ok());
     }
@@ -464,11 +590,12 @@ mod tests {
         // Test that we don't overflow with large amounts
         // Use a large but safe value that won't cause overflow during multiplication
         let result = validate_fee(
-            1_000_000_000, // ext_amount (1 billion, large but safe)
-            1000000,       // provided_fee
-            1,             // deposit_fee_rate (small rate to avoid overflow)
-            0,             // withdrawal_fee_rate
-            500,           // error_rate (5%)
+            1_000_000_000,              // ext_amount (1 billion, large but safe)
+            1000000,                    // provided_fee
+            &FeeSchedule::from_rate(1), // deposit_fee (small rate to avoid overflow)
+            &FeeSchedule::from_rate(0), // withdrawal_fee
+            &FeeSchedule::from_rate(0), // transfer_fee
+            500,                        // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -477,11 +604,12 @@ mod tests {
     fn test_validate_fee_edge_case_min_withdrawal() {
         // Test edge case with minimum negative value (but not i64::MIN)
         let result = validate_fee(
-            -1,    // ext_amount (smallest withdrawal)
-            0,     // provided_fee
-            25,    // deposit_fee_rate
-            0,     // withdrawal_fee_rate (0%, so any fee is fine)
-            500,   // error_rate (5%)
+            -1,                          // ext_amount (smallest withdrawal)
+            0,                           // provided_fee
+            &FeeSchedule::from_rate(25), // deposit_fee
+            &FeeSchedule::from_rate(0),  // withdrawal_fee (0%, so any fee is fine)
+            &FeeSchedule::from_rate(0),  // transfer_fee
+            500,                         // error_rate (5%)
         );
         assert!(result.is_ok());
     }
@@ -491,11 +619,12 @@ mod tests {
         // Test that arithmetic overflow is properly detected and handled
         // Using maximum values that would cause overflow in the multiplication
         let result = validate_fee(
-            i64::MAX,  // ext_amount (maximum positive value)
-            0,         // provided_fee
-            10000,     // deposit_fee_rate (100% - maximum rate)
-            0,         // withdrawal_fee_rate
-            0,         // fee_error_margin (0% to test exact calculation)
+            i64::MAX,                       // ext_amount (maximum positive value)
+            0,                              // provided_fee
+            &FeeSchedule::from_rate(10000), // deposit_fee (100% - maximum rate)
+            &FeeSchedule::from_rate(0),     // withdrawal_fee
+            &FeeSchedule::from_rate(0),     // transfer_fee
+            0,                              // fee_error_margin (0% to test exact calculation)
         );
         // This should return an error (either arithmetic overflow or invalid fee amount)
         assert!(result.is_err());
@@ -0,0 +1,71 @@
+use crate::{DepositorVelocity, ErrorCode, GlobalConfig};
+use anchor_lang::prelude::*;
+
+/// The velocity window is tracked in this many buckets, so it rolls forward a quarter window
+/// at a time.
+pub const VELOCITY_BUCKETS: usize = 4;
+
+impl DepositorVelocity {
+    /// Adds a deposit to the current bucket after dropping buckets that fell out of the window,
+    /// and fails if the depositor's volume over the window would exceed `max_deposit_per_window`.
+    pub fn record(&mut self, amount: u64, current_slot: u64, window_slots: u64, max_deposit_per_window: u64) -> Result<()> {
+        let bucket_slots = (window_slots / VELOCITY_BUCKETS as u64).max(1);
+        let bucket = current_slot / bucket_slots;
+
+        if bucket_slots != self.bucket_slots || bucket >= self.last_bucket + VELOCITY_BUCKETS as u64 {
+            // The window changed or every bucket is outside of it
+            self.buckets = [0u64; VELOCITY_BUCKETS];
+            self.bucket_slots = bucket_slots;
+        } else {
+            for expired in self.last_bucket + 1..=bucket {
+                self.buckets[(expired % VELOCITY_BUCKETS as u64) as usize] = 0;
+            }
+        }
+        self.last_bucket = self.last_bucket.max(bucket);
+
+        let window_total = self.buckets
+            .iter()
+            .try_fold(amount, |total, deposited| total.checked_add(*deposited))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(window_total <= max_deposit_per_window, ErrorCode::VelocityLimitExceeded);
+
+        let index = (bucket % VELOCITY_BUCKETS as u64) as usize;
+        self.buckets[index] += amount; // Bounded by window_total
+
+        Ok(())
+    }
+}
+
+/// Counts a deposit against the depositor's velocity limit, if one is configured. The
+/// depositor's velocity account must then be provided; it is created on first use.
+///
+/// The depositor is the account funding the deposit: the signer of transact and transact_queued,
+/// the depositor of deposit_for. Everyone depositing through the same funding account shares
+/// its limit, e.g. the users of a relayer that funds their deposits, or of a program calling
+/// deposit_for from a single PDA. Integrations that want their users limited one by one must
+/// fund each user's deposits from a separate account, such as a per-user PDA.
+pub fn record_deposit(
+    depositor_velocity: Option<&mut DepositorVelocity>,
+    bump: Option<u8>,
+    global_config: &GlobalConfig,
+    depositor: Pubkey,
+    amount: u64,
+    current_slot: u64,
+) -> Result<()> {
+    if global_config.max_deposit_per_window == 0 {
+        return Ok(());
+    }
+
+    let depositor_velocity = depositor_velocity.ok_or(ErrorCode::DepositorVelocityRequired)?;
+    if depositor_velocity.depositor == Pubkey::default() {
+        depositor_velocity.depositor = depositor;
+        depositor_velocity.bump = bump.ok_or(ErrorCode::DepositorVelocityRequired)?;
+    }
+
+    depositor_velocity.record(
+        amount,
+        current_slot,
+        global_config.velocity_window_slots,
+        global_config.max_deposit_per_window,
+    )
+}
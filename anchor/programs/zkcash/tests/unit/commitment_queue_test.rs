@@ -0,0 +1,78 @@
+use zkcash::{commitment_queue::COMMITMENT_QUEUE_CAPACITY, CommitmentQueue};
+
+fn create_test_queue() -> CommitmentQueue {
+    CommitmentQueue {
+        commitments: [[0u8; 32]; COMMITMENT_QUEUE_CAPACITY],
+        has_origin: [0u8; COMMITMENT_QUEUE_CAPACITY],
+        head: 0,
+        len: 0,
+        shard: 0,
+        bump: 255,
+        _padding: [0u8; 6],
+    }
+}
+
+#[test]
+fn test_push_and_pop_in_order() {
+    let mut queue = create_test_queue();
+    for i in 1..=3u8 {
+        queue.push([i; 32], false).unwrap();
+    }
+    assert_eq!(queue.len, 3);
+
+    assert_eq!(queue.pop_front(2), vec![([1u8; 32], false), ([2u8; 32], false)]);
+    assert_eq!(queue.pop_front(10), vec![([3u8; 32], false)]);
+    assert_eq!(queue.len, 0);
+    assert!(queue.pop_front(10).is_empty());
+}
+
+#[test]
+fn test_queue_full() {
+    let mut queue = create_test_queue();
+    for _ in 0..COMMITMENT_QUEUE_CAPACITY {
+        queue.push([1u8; 32], false).unwrap();
+    }
+
+    let err = queue.push([2u8; 32], false).unwrap_err();
+    assert!(format!("{:?}", err).contains("CommitmentQueueFull"));
+    assert_eq!(queue.len as usize, COMMITMENT_QUEUE_CAPACITY);
+
+    // Processing makes room again
+    queue.pop_front(2);
+    queue.push([2u8; 32], false).unwrap();
+    queue.push([3u8; 32], false).unwrap();
+}
+
+#[test]
+fn test_queue_wraparound() {
+    let mut queue = create_test_queue();
+    for _ in 0..COMMITMENT_QUEUE_CAPACITY - 1 {
+        queue.push([0u8; 32], false).unwrap();
+    }
+    queue.pop_front(COMMITMENT_QUEUE_CAPACITY - 1);
+
+    queue.push([1u8; 32], false).unwrap();
+    queue.push([2u8; 32], false).unwrap();
+    assert_eq!(queue.head as usize, COMMITMENT_QUEUE_CAPACITY - 1);
+    assert_eq!(queue.commitments[0], [2u8; 32]);
+
+    assert_eq!(queue.pop_front(2), vec![([1u8; 32], false), ([2u8; 32], false)]);
+    assert_eq!(queue.head, 1);
+}
+
+
+#[test]
+fn test_pop_returns_origin_flags() {
+    let mut queue = create_test_queue();
+    queue.push([1u8; 32], true).unwrap();
+    queue.push([2u8; 32], false).unwrap();
+
+    assert_eq!(queue.pop_front(2), vec![([1u8; 32], true), ([2u8; 32], false)]);
+
+    // A slot reused by a later commitment takes that commitment's flag
+    for _ in 0..COMMITMENT_QUEUE_CAPACITY - 2 {
+        queue.push([0u8; 32], false).unwrap();
+    }
+    queue.push([3u8; 32], false).unwrap();
+    assert_eq!(queue.has_origin[0], 0);
+}
\ No newline at end of file
@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+use zkcash::{cpi_helpers::deposit_for_addresses, Proof};
+
+fn create_test_proof(nullifier0: [u8; 32], nullifier1: [u8; 32]) -> Proof {
+    Proof {
+        proof_a: [0u8; 64],
+        proof_b: [0u8; 128],
+        proof_c: [0u8; 64],
+        root: [0u8; 32],
+        public_amount: [0u8; 32],
+        ext_data_hash: [0u8; 32],
+        input_nullifiers: [nullifier0, nullifier1],
+        output_commitments: [[0u8; 32]; 2],
+    }
+}
+
+#[test]
+fn test_deposit_for_addresses_match_program_seeds() {
+    let proof = create_test_proof([1u8; 32], [2u8; 32]);
+    let addresses = deposit_for_addresses(&proof, 3);
+
+    let (tree_account, _) = Pubkey::find_program_address(&[b"merkle_tree", &3u64.to_le_bytes()], &zkcash::ID);
+    let (nullifier0, _) = Pubkey::find_program_address(&[b"nullifier0", &[1u8; 32]], &zkcash::ID);
+    assert_eq!(addresses.tree_account, tree_account);
+    assert_eq!(addresses.nullifier0, nullifier0);
+}
+
+#[test]
+fn test_deposit_for_addresses_keep_legacy_first_tree() {
+    let proof = create_test_proof([1u8; 32], [2u8; 32]);
+    let addresses = deposit_for_addresses(&proof, 0);
+
+    let (tree_account, _) = Pubkey::find_program_address(&[b"merkle_tree"], &zkcash::ID);
+    assert_eq!(addresses.tree_account, tree_account);
+}
+
+#[test]
+fn test_deposit_for_cross_check_nullifiers_swap_inputs() {
+    let proof = create_test_proof([1u8; 32], [2u8; 32]);
+    let swapped = create_test_proof([2u8; 32], [1u8; 32]);
+
+    let addresses = deposit_for_addresses(&proof, 0);
+    let swapped_addresses = deposit_for_addresses(&swapped, 0);
+
+    // The cross-check accounts of one ordering are the nullifier accounts of the other
+    assert_eq!(addresses.nullifier2, swapped_addresses.nullifier0);
+    assert_eq!(addresses.nullifier3, swapped_addresses.nullifier1);
+}
@@ -0,0 +1,87 @@
+use super::{create_test_global_config, create_test_tree_token_account};
+
+#[test]
+fn test_variable_amount_pool_accepts_any_amount() {
+    let config = create_test_global_config();
+
+    assert!(!config.is_fixed_denomination());
+    assert_eq!(config.denomination_index(123_456).unwrap(), None);
+    assert_eq!(config.denomination_index(-7).unwrap(), None);
+}
+
+#[test]
+fn test_fixed_denomination_pool_only_accepts_denominations() {
+    let mut config = create_test_global_config();
+    config.set_denominations(&[100_000_000, 1_000_000_000, 10_000_000_000]).unwrap();
+
+    assert!(config.is_fixed_denomination());
+    assert_eq!(config.denomination_index(100_000_000).unwrap(), Some(0));
+    assert_eq!(config.denomination_index(-10_000_000_000).unwrap(), Some(2));
+    // Internal transfers move nothing in or out of the pool
+    assert_eq!(config.denomination_index(0).unwrap(), None);
+
+    let err = config.denomination_index(500_000_000).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidDenomination"));
+}
+
+#[test]
+fn test_set_denominations_rejects_invalid_sets() {
+    let mut config = create_test_global_config();
+
+    for denominations in [
+        vec![0, 100],
+        vec![200, 100],
+        vec![100, 100],
+        vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+    ] {
+        let err = config.set_denominations(&denominations).unwrap_err();
+        assert!(format!("{:?}", err).contains("InvalidDenominations"));
+    }
+    assert!(!config.is_fixed_denomination());
+
+    config.set_denominations(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+    assert_eq!(config.denomination_count, 8);
+}
+
+#[test]
+fn test_fixed_denomination_pool_pays_out_to_a_single_recipient() {
+    let mut config = create_test_global_config();
+    assert!(config.check_payout_count(3).is_ok());
+
+    config.set_denominations(&[100, 1_000]).unwrap();
+    assert!(config.check_payout_count(1).is_ok());
+    let err = config.check_payout_count(2).unwrap_err();
+    assert!(format!("{:?}", err).contains("SplitDenominationWithdrawal"));
+}
+
+#[test]
+fn test_record_denomination_counts_anonymity_sets() {
+    let mut config = create_test_global_config();
+    config.set_denominations(&[100, 1_000]).unwrap();
+    let mut account = create_test_tree_token_account();
+
+    account.record_denomination(&config, 100).unwrap();
+    account.record_denomination(&config, 100).unwrap();
+    account.record_denomination(&config, 1_000).unwrap();
+    account.record_denomination(&config, -100).unwrap();
+    account.record_denomination(&config, 0).unwrap();
+
+    assert_eq!(account.denomination_deposits[..2], [2, 1]);
+    assert_eq!(account.denomination_withdrawals[..2], [1, 0]);
+    assert_eq!(account.denomination_anonymity_set(0), 1);
+    assert_eq!(account.denomination_anonymity_set(1), 1);
+
+    assert!(account.record_denomination(&config, 250).is_err());
+}
+
+#[test]
+fn test_variable_amount_pool_records_no_denominations() {
+    let config = create_test_global_config();
+    let mut account = create_test_tree_token_account();
+
+    account.record_denomination(&config, 100).unwrap();
+    account.record_denomination(&config, -100).unwrap();
+
+    assert_eq!(account.denomination_deposits, [0; 8]);
+    assert_eq!(account.denomination_withdrawals, [0; 8]);
+}
@@ -0,0 +1,95 @@
+use anchor_lang::prelude::Pubkey;
+use zkcash::{
+    deny_list::{address_hash, check_depositor, DENY_LIST_CAPACITY},
+    DenyList,
+};
+
+fn create_test_deny_list() -> Box<DenyList> {
+    Box::new(DenyList {
+        entries: [[0u8; 32]; DENY_LIST_CAPACITY],
+        len: 0,
+        bump: 255,
+        _padding: [0u8; 7],
+    })
+}
+
+#[test]
+fn test_insert_keeps_entries_sorted() {
+    let mut deny_list = create_test_deny_list();
+    let addresses: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+    for address in &addresses {
+        deny_list.insert(address).unwrap();
+    }
+
+    assert_eq!(deny_list.len, 10);
+    let entries = &deny_list.entries[..10];
+    assert!(entries.windows(2).all(|pair| pair[0] < pair[1]));
+    for address in &addresses {
+        assert!(deny_list.contains(address));
+        assert!(entries.contains(&address_hash(address)));
+    }
+    assert!(!deny_list.contains(&Pubkey::new_unique()));
+}
+
+#[test]
+fn test_insert_duplicate_fails() {
+    let mut deny_list = create_test_deny_list();
+    let address = Pubkey::new_unique();
+    deny_list.insert(&address).unwrap();
+
+    let err = deny_list.insert(&address).unwrap_err();
+    assert!(format!("{:?}", err).contains("AddressAlreadyDenied"));
+    assert_eq!(deny_list.len, 1);
+}
+
+#[test]
+fn test_insert_into_full_list_fails() {
+    let mut deny_list = create_test_deny_list();
+    let addresses: Vec<Pubkey> = (0..DENY_LIST_CAPACITY).map(|_| Pubkey::new_unique()).collect();
+    for address in &addresses {
+        deny_list.insert(address).unwrap();
+    }
+
+    let address = Pubkey::new_unique();
+    let err = deny_list.insert(&address).unwrap_err();
+    assert!(format!("{:?}", err).contains("DenyListFull"));
+    assert!(!deny_list.contains(&address));
+
+    // Removing an address makes room again
+    deny_list.remove(&addresses[0]).unwrap();
+    deny_list.insert(&address).unwrap();
+    assert!(deny_list.contains(&address));
+}
+
+#[test]
+fn test_remove() {
+    let mut deny_list = create_test_deny_list();
+    let addresses: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+    for address in &addresses {
+        deny_list.insert(address).unwrap();
+    }
+
+    deny_list.remove(&addresses[2]).unwrap();
+
+    assert_eq!(deny_list.len, 4);
+    assert!(!deny_list.contains(&addresses[2]));
+    for address in addresses.iter().filter(|address| **address != addresses[2]) {
+        assert!(deny_list.contains(address));
+    }
+    assert!(deny_list.entries[..4].windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(deny_list.entries[4], [0u8; 32]);
+
+    let err = deny_list.remove(&addresses[2]).unwrap_err();
+    assert!(format!("{:?}", err).contains("AddressNotDenied"));
+}
+
+#[test]
+fn test_check_depositor() {
+    let mut deny_list = create_test_deny_list();
+    let denied = Pubkey::new_unique();
+    deny_list.insert(&denied).unwrap();
+
+    assert!(check_depositor(&deny_list, &Pubkey::new_unique()).is_ok());
+    let err = check_depositor(&deny_list, &denied).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositorDenied"));
+}
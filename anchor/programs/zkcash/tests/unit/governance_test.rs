@@ -0,0 +1,180 @@
+use anchor_lang::prelude::*;
+use zkcash::{
+    governance::{calculate_effective_slot, ConfigChange, MAX_CONFIG_TIMELOCK_SLOTS},
+    FeeSchedule,
+};
+
+use super::{create_test_global_config, create_test_tree_account};
+
+#[test]
+fn test_apply_fees_change_only_touches_provided_fields() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+    let new_withdrawal_fee = FeeSchedule { flat_fee: 5000, rate_bps: 30, min_fee: 0, max_fee: 1_000_000 };
+    
+    let change = ConfigChange::Fees {
+        deposit_fee: None,
+        withdrawal_fee: Some(new_withdrawal_fee),
+        transfer_fee: None,
+        fee_error_margin: Some(1000),
+    };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+    
+    assert_eq!(global_config.deposit_fee, FeeSchedule::from_rate(0));
+    assert_eq!(global_config.withdrawal_fee, new_withdrawal_fee);
+    assert_eq!(global_config.transfer_fee, FeeSchedule::from_rate(0));
+    assert_eq!(global_config.fee_error_margin, 1000);
+    assert_eq!(tree_account.max_deposit_amount, 1_000_000_000_000);
+}
+
+#[test]
+fn test_apply_deposit_limit_change() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+    
+    let change = ConfigChange::DepositLimit { max_deposit_amount: 5_000_000_000 };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+    
+    assert_eq!(tree_account.max_deposit_amount, 5_000_000_000);
+}
+
+#[test]
+fn test_apply_timelock_change() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+    
+    let change = ConfigChange::Timelock { delay_slots: 216_000 };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+    
+    assert_eq!(global_config.config_timelock_slots, 216_000);
+}
+
+#[test]
+fn test_apply_archived_root_max_age_change() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+
+    let change = ConfigChange::ArchivedRootMaxAge { max_age_slots: 216_000 };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+
+    assert_eq!(global_config.max_archived_root_age_slots, 216_000);
+}
+
+#[test]
+fn test_apply_withdrawal_delay_and_guardian_changes() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+
+    let change = ConfigChange::WithdrawalDelay { large_withdrawal_threshold: 100_000_000_000, delay_slots: 432_000 };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+    assert_eq!(global_config.large_withdrawal_threshold, 100_000_000_000);
+    assert_eq!(global_config.withdrawal_delay_slots, 432_000);
+
+    let guardian = Pubkey::new_unique();
+    let change = ConfigChange::Guardian { guardian };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+    assert_eq!(global_config.guardian, guardian);
+
+    let change = ConfigChange::WithdrawalDelay { large_withdrawal_threshold: 1, delay_slots: MAX_CONFIG_TIMELOCK_SLOTS + 1 };
+    let err = change.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidConfigTimelock"));
+}
+
+#[test]
+fn test_apply_usd_limits_change() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+
+    let price_feed = Pubkey::new_unique();
+    let change = ConfigChange::UsdLimits {
+        price_feed,
+        max_price_age_slots: 150,
+        max_deposit_usd: 10_000_000_000,
+        max_tvl_usd: 1_000_000_000_000,
+    };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+
+    assert_eq!(global_config.price_feed, price_feed);
+    assert_eq!(global_config.max_price_age_slots, 150);
+    assert_eq!(global_config.max_deposit_usd, 10_000_000_000);
+    assert_eq!(global_config.max_tvl_usd, 1_000_000_000_000);
+}
+
+#[test]
+fn test_apply_velocity_limit_change() {
+    let mut global_config = create_test_global_config();
+    let mut tree_account = create_test_tree_account();
+
+    let change = ConfigChange::VelocityLimit {
+        window_slots: 9_000,
+        max_deposit_per_window: 100_000_000_000,
+    };
+    assert!(change.validate().is_ok());
+    change.apply(&mut global_config, &mut tree_account);
+
+    assert_eq!(global_config.velocity_window_slots, 9_000);
+    assert_eq!(global_config.max_deposit_per_window, 100_000_000_000);
+
+    let change = ConfigChange::VelocityLimit {
+        window_slots: 0,
+        max_deposit_per_window: 100_000_000_000,
+    };
+    let err = change.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidVelocityWindow"));
+}
+
+#[test]
+fn test_validate_rejects_invalid_fee_schedule() {
+    let change = ConfigChange::Fees {
+        deposit_fee: Some(FeeSchedule::from_rate(10001)),
+        withdrawal_fee: None,
+        transfer_fee: None,
+        fee_error_margin: None,
+    };
+    let err = change.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidFeeRate"));
+
+    let change = ConfigChange::Fees {
+        deposit_fee: None,
+        withdrawal_fee: None,
+        transfer_fee: Some(FeeSchedule { flat_fee: 0, rate_bps: 0, min_fee: 10, max_fee: 5 }),
+        fee_error_margin: None,
+    };
+    let err = change.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidFeeSchedule"));
+}
+
+#[test]
+fn test_validate_rejects_invalid_fee_error_margin() {
+    let change = ConfigChange::Fees {
+        deposit_fee: None,
+        withdrawal_fee: None,
+        transfer_fee: None,
+        fee_error_margin: Some(10001),
+    };
+    assert!(change.validate().is_err());
+}
+
+#[test]
+fn test_validate_rejects_excessive_timelock() {
+    let change = ConfigChange::Timelock { delay_slots: MAX_CONFIG_TIMELOCK_SLOTS };
+    assert!(change.validate().is_ok());
+
+    let change = ConfigChange::Timelock { delay_slots: MAX_CONFIG_TIMELOCK_SLOTS + 1 };
+    let err = change.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidConfigTimelock"));
+}
+
+#[test]
+fn test_calculate_effective_slot() {
+    assert_eq!(calculate_effective_slot(1000, 0).unwrap(), 1000);
+    assert_eq!(calculate_effective_slot(1000, 216_000).unwrap(), 217_000);
+    assert!(calculate_effective_slot(u64::MAX, 1).is_err());
+}
@@ -1,6 +1,6 @@
 use light_hasher::{Poseidon, Hasher};
 use std::mem::MaybeUninit;
-use zkcash::{MerkleTreeAccount, merkle_tree::MerkleTree};
+use zkcash::{MerkleTreeAccount, merkle_tree::{MerkleTree, ROLLOVER_THRESHOLD}};
 
 fn create_test_account() -> MerkleTreeAccount {
     let mut uninit: MaybeUninit<MerkleTreeAccount> = MaybeUninit::uninit();
@@ -48,7 +48,7 @@ fn test_single_append() {
     let _ = MerkleTree::initialize::<Poseidon>(&mut account);
     
     let leaf = [1u8; 32];
-    let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     
     assert!(result.is_ok(), "Single append should succeed");
     assert_eq!(account.next_index, 1, "next_index should increment to 1");
@@ -68,7 +68,7 @@ fn test_multiple_appends() {
         let mut leaf = [0u8; 32];
         leaf[0] = i as u8;
         
-        let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+        let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
         assert!(result.is_ok(), "Append {} should succeed", i);
         assert_eq!(account.next_index, i + 1, "next_index should be {}", i + 1);
     }
@@ -91,7 +91,7 @@ fn test_multiple_appends_verify_index_increments() {
         let expected_index = start_index + i;
         assert_eq!(account.next_index, expected_index, "next_index should be {} before append", expected_index);
         
-        let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+        let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
         assert!(result.is_ok(), "Append {} should succeed", i);
         assert_eq!(account.next_index, expected_index + 1, "next_index should be {} after append", expected_index + 1);
     }
@@ -112,7 +112,7 @@ fn test_tree_full_capacity_check() {
     let leaf = [1u8; 32];
     
     // This append should succeed (we're at capacity-1, so one more is allowed)
-    let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     assert!(result.is_ok(), "Append should succeed when at capacity-1");
     assert_eq!(account.next_index, max_capacity, "next_index should equal max_capacity after append");
 }
@@ -132,7 +132,7 @@ fn test_tree_already_full() {
     let leaf = [1u8; 32];
     
     // This append should fail (tree is full)
-    let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     assert!(result.is_err(), "Append should fail when tree is full");
     
     // Verify the error is the expected one
@@ -162,17 +162,17 @@ fn test_append_near_max_next_index() {
     let leaf = [1u8; 32];
     
     // First append should succeed (we're at capacity-2)
-    let result1 = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result1 = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     assert!(result1.is_ok(), "First append should succeed");
     assert_eq!(account.next_index, max_capacity - 1, "next_index should be capacity-1");
     
     // Second append should succeed (we're at capacity-1)
-    let result2 = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result2 = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     assert!(result2.is_ok(), "Second append should succeed");
     assert_eq!(account.next_index, max_capacity, "next_index should be capacity");
     
     // Third append should fail (tree is now full)
-    let result3 = MerkleTree::append::<Poseidon>(leaf, &mut account);
+    let result3 = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
     assert!(result3.is_err(), "Third append should fail when tree is full");
 }
 
@@ -192,7 +192,7 @@ fn test_root_known_after_multiple_appends() {
         let mut leaf = [0u8; 32];
         leaf[0] = i as u8;
         
-        let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+        let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
         assert!(result.is_ok(), "Append {} should succeed", i);
         
         let current_root = account.root;
@@ -239,7 +239,7 @@ fn test_root_history_wraparound() {
         let mut leaf = [0u8; 32];
         leaf[0] = i as u8;
         
-        let result = MerkleTree::append::<Poseidon>(leaf, &mut account);
+        let result = MerkleTree::append::<Poseidon>(leaf, &mut account, 0);
         assert!(result.is_ok(), "Append {} should succeed", i);
     }
     
@@ -250,4 +250,82 @@ fn test_root_history_wraparound() {
     // But the current root should be known
     assert!(MerkleTree::is_known_root(&account, account.root), 
            "Current root should be known");
-}
\ No newline at end of file
+}
+#[test]
+fn test_append_batch_matches_sequential_appends() {
+    let leaves: Vec<[u8; 32]> = (1..=5u8).map(|i| [i; 32]).collect();
+
+    let mut sequential = create_test_account();
+    MerkleTree::initialize::<Poseidon>(&mut sequential).unwrap();
+    for leaf in &leaves {
+        MerkleTree::append::<Poseidon>(*leaf, &mut sequential, 0).unwrap();
+    }
+
+    let mut batched = create_test_account();
+    MerkleTree::initialize::<Poseidon>(&mut batched).unwrap();
+    MerkleTree::append_batch::<Poseidon>(&leaves, &mut batched, 7).unwrap();
+
+    assert_eq!(batched.next_index, 5);
+    assert_eq!(batched.root, sequential.root);
+    assert_eq!(batched.subtrees, sequential.subtrees);
+
+    // Only one root history slot is used for the whole batch
+    assert_eq!(batched.root_index, 1);
+    assert_eq!(batched.root_history[1], batched.root);
+    assert_eq!(batched.root_history_slots[1], 7);
+    assert!(MerkleTree::is_known_root(&batched, batched.root));
+}
+
+#[test]
+fn test_singleton_root_matches_a_tree_holding_only_the_leaf() {
+    let leaf = [7u8; 32];
+    for leaf_index in [0u64, 1, 6] {
+        // Empty leaves are zero, so the zero leaves before `leaf` leave the tree as if it held only `leaf`
+        let mut account = create_test_account();
+        MerkleTree::initialize::<Poseidon>(&mut account).unwrap();
+        for _ in 0..leaf_index {
+            MerkleTree::append::<Poseidon>([0u8; 32], &mut account, 0).unwrap();
+        }
+        MerkleTree::append::<Poseidon>(leaf, &mut account, 0).unwrap();
+
+        assert_eq!(MerkleTree::singleton_root::<Poseidon>(leaf, leaf_index, account.height), account.root);
+    }
+
+    // The leaf index is part of the root
+    assert_ne!(
+        MerkleTree::singleton_root::<Poseidon>(leaf, 0, 26),
+        MerkleTree::singleton_root::<Poseidon>(leaf, 1, 26)
+    );
+}
+
+#[test]
+fn test_append_batch_rejects_overflowing_batch() {
+    let mut account = create_test_account();
+    account.height = 2;
+    MerkleTree::initialize::<Poseidon>(&mut account).unwrap();
+
+    let leaves = vec![[1u8; 32]; 5];
+    assert!(MerkleTree::append_batch::<Poseidon>(&leaves, &mut account, 0).is_err());
+}
+
+#[test]
+fn test_initialize_successor_requires_nearly_full_tree() {
+    let mut account = create_test_account();
+    account.height = 10;
+    account.generation = 4;
+    account.max_deposit_amount = 5_000;
+    MerkleTree::initialize::<Poseidon>(&mut account).unwrap();
+
+    let mut successor = create_test_account();
+    account.next_index = (1u64 << account.height) - ROLLOVER_THRESHOLD;
+    let err = MerkleTree::initialize_successor::<Poseidon>(&account, &mut successor).unwrap_err();
+    assert!(format!("{:?}", err).contains("TreeNotFull"));
+
+    account.next_index += 1;
+    MerkleTree::initialize_successor::<Poseidon>(&account, &mut successor).unwrap();
+    assert_eq!(successor.generation, 5);
+    assert_eq!(successor.height, 10);
+    assert_eq!(successor.max_deposit_amount, 5_000);
+    assert_eq!(successor.next_index, 0);
+    assert_eq!(successor.root, Poseidon::zero_bytes()[10]);
+}
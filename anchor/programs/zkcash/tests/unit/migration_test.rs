@@ -0,0 +1,133 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use zkcash::{
+    migration::{
+        check_legacy_layout, LegacyGlobalConfig, LegacyMerkleTreeAccount, LegacyTreeTokenAccount,
+        LEGACY_GLOBAL_CONFIG_SIZE, LEGACY_MERKLE_TREE_ACCOUNT_SIZE, LEGACY_TREE_TOKEN_ACCOUNT_SIZE,
+    },
+    solvency::check_solvency,
+    FeeSchedule, GlobalConfig, MerkleTreeAccount, TreeTokenAccount,
+};
+
+fn create_legacy_tree(root_index: u64, roots_recorded: u64) -> LegacyMerkleTreeAccount {
+    let mut tree: LegacyMerkleTreeAccount = bytemuck::Zeroable::zeroed();
+    tree.authority = Pubkey::new_unique();
+    tree.next_index = 2 * (roots_recorded - 1);
+    tree.root = [7u8; 32];
+    tree.root_index = root_index;
+    tree.max_deposit_amount = 1_000_000_000_000;
+    tree.height = 26;
+    tree.root_history_size = 100;
+    tree.bump = 255;
+    for i in 0..roots_recorded.min(100) as usize {
+        tree.root_history[i] = [1u8; 32];
+    }
+    tree
+}
+
+#[test]
+fn test_legacy_sizes_match_the_first_deployment() {
+    assert_eq!(LEGACY_MERKLE_TREE_ACCOUNT_SIZE, 4136);
+    assert_eq!(LEGACY_TREE_TOKEN_ACCOUNT_SIZE, 41);
+    assert_eq!(LEGACY_GLOBAL_CONFIG_SIZE, 48);
+}
+
+#[test]
+fn test_migrated_tree_keeps_its_leaves_and_roots() {
+    let legacy = create_legacy_tree(4, 5);
+    let tree = legacy.migrate(1_234);
+
+    assert_eq!(tree.authority, legacy.authority);
+    assert_eq!(tree.generation, 0);
+    assert_eq!(tree.next_index, legacy.next_index);
+    assert_eq!(tree.subtrees, legacy.subtrees);
+    assert_eq!(tree.root, legacy.root);
+    assert_eq!(tree.root_history, legacy.root_history);
+    assert_eq!(tree.root_index, 4);
+    assert_eq!(tree.root_count, 5);
+    assert_eq!(tree.archived_root_count, 0);
+    assert_eq!(tree.max_deposit_amount, legacy.max_deposit_amount);
+    assert_eq!(tree.bump, 255);
+    assert!(tree.root_history_slots.iter().all(|&slot| slot == 1_234));
+}
+
+#[test]
+fn test_migrated_tree_counts_roots_of_a_wrapped_history() {
+    // 250 roots recorded: the history has wrapped twice and holds the last 100
+    let legacy = create_legacy_tree(49, 250);
+    let tree = legacy.migrate(0);
+
+    // How often it wrapped is lost, but the count still lines up with the history,
+    // so the archive copies all 100 roots it holds
+    assert_eq!(tree.root_count, 150);
+    assert_eq!(tree.root_count % 100, (tree.root_index + 1) % 100);
+}
+
+#[test]
+fn test_migrated_config_keeps_fees_and_authority() {
+    let legacy = LegacyGlobalConfig {
+        authority: Pubkey::new_unique(),
+        deposit_fee_rate: 10,
+        withdrawal_fee_rate: 25,
+        fee_error_margin: 500,
+        bump: 254,
+    };
+    let config = legacy.migrate();
+
+    assert_eq!(config.authority, legacy.authority);
+    assert_eq!(config.guardian, legacy.authority);
+    assert_eq!(config.deposit_fee, FeeSchedule::from_rate(10));
+    assert_eq!(config.withdrawal_fee, FeeSchedule::from_rate(25));
+    assert_eq!(config.transfer_fee, FeeSchedule::from_rate(0));
+    assert_eq!(config.fee_error_margin, 500);
+    assert_eq!(config.current_tree_generation, 0);
+    assert_eq!(config.denomination_count, 0);
+    assert_eq!(config.config_timelock_slots, 0);
+    assert_eq!(config.bump, 254);
+}
+
+#[test]
+fn test_migrated_tree_token_account_is_seeded_with_the_vault_balance() {
+    let legacy = LegacyTreeTokenAccount {
+        authority: Pubkey::new_unique(),
+        bump: 253,
+    };
+    let token_account = legacy.migrate(5_000_000_000);
+
+    assert_eq!(token_account.authority, legacy.authority);
+    assert_eq!(token_account.bump, 253);
+    assert_eq!(token_account.net_shielded().unwrap(), 5_000_000_000);
+
+    // so withdrawing notes deposited before the migration keeps the counters consistent
+    let mut token_account = token_account;
+    token_account.record_transaction(-2_000_000_000, 5_000_000).unwrap();
+    assert_eq!(token_account.net_shielded().unwrap(), 2_995_000_000);
+    check_solvency(&token_account, 3_000_000_000 + 1_000_000, 1_000_000).unwrap();
+}
+
+#[test]
+fn test_only_legacy_layouts_are_migrated() {
+    let key = Pubkey::new_unique();
+    let mut lamports = 1_000_000;
+
+    let legacy = LegacyTreeTokenAccount {
+        authority: Pubkey::new_unique(),
+        bump: 253,
+    };
+    let mut data = TreeTokenAccount::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &zkcash::ID, false, 0);
+    check_legacy_layout::<TreeTokenAccount>(&info, LEGACY_TREE_TOKEN_ACCOUNT_SIZE).unwrap();
+
+    // An account of another type
+    let err = check_legacy_layout::<GlobalConfig>(&info, LEGACY_TREE_TOKEN_ACCOUNT_SIZE).unwrap_err();
+    assert!(format!("{:?}", err).contains("NotLegacyLayout"));
+
+    // or one that was already migrated
+    let mut lamports = 1_000_000;
+    let mut data = vec![0u8; 8 + std::mem::size_of::<MerkleTreeAccount>()];
+    data[..8].copy_from_slice(MerkleTreeAccount::DISCRIMINATOR);
+    let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &zkcash::ID, false, 0);
+    let err = check_legacy_layout::<MerkleTreeAccount>(&info, LEGACY_MERKLE_TREE_ACCOUNT_SIZE).unwrap_err();
+    assert!(format!("{:?}", err).contains("NotLegacyLayout"));
+}
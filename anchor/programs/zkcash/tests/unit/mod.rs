@@ -1,3 +1,75 @@
 mod merkle_tree_test; 
 mod utils_test;
-mod groth16_test;
\ No newline at end of file
+mod groth16_test;
+mod governance_test;
+mod multisig_test;
+mod cpi_helpers_test;
+mod commitment_queue_test;
+mod root_archive_test;
+mod solvency_test;
+mod deny_list_test;
+mod ragequit_test;
+mod pending_withdrawal_test;
+mod denomination_test;
+mod price_feed_test;
+mod velocity_test;
+mod migration_test;
+
+use anchor_lang::prelude::Pubkey;
+use std::mem::MaybeUninit;
+use zkcash::{FeeSchedule, GlobalConfig, MerkleTreeAccount, TreeTokenAccount};
+
+/// Pool config with the default fees; tests override the fields they exercise.
+pub fn create_test_global_config() -> GlobalConfig {
+    GlobalConfig {
+        authority: Pubkey::new_unique(),
+        deposit_fee: FeeSchedule::from_rate(0),
+        withdrawal_fee: FeeSchedule::from_rate(25),
+        transfer_fee: FeeSchedule::from_rate(0),
+        fee_error_margin: 500,
+        config_timelock_slots: 0,
+        next_config_change_id: 0,
+        max_archived_root_age_slots: 1_512_000,
+        current_tree_generation: 0,
+        guardian: Pubkey::new_unique(),
+        large_withdrawal_threshold: 0,
+        withdrawal_delay_slots: 1_000,
+        denominations: [0; 8],
+        denomination_count: 0,
+        price_feed: Pubkey::default(),
+        max_price_age_slots: 0,
+        max_deposit_usd: 0,
+        max_tvl_usd: 0,
+        velocity_window_slots: 216_000,
+        max_deposit_per_window: 0,
+        bump: 255,
+    }
+}
+
+/// Zeroed tree account with the default root history size and deposit limit.
+pub fn create_test_tree_account() -> MerkleTreeAccount {
+    let mut uninit: MaybeUninit<MerkleTreeAccount> = MaybeUninit::uninit();
+    
+    unsafe {
+        let ptr = uninit.as_mut_ptr();
+        std::ptr::write_bytes(ptr, 0, 1); // Zero-initialize the entire struct
+        
+        (*ptr).root_history_size = 100;
+        (*ptr).max_deposit_amount = 1_000_000_000_000;
+        
+        uninit.assume_init()
+    }
+}
+
+/// Vault account with no recorded flows.
+pub fn create_test_tree_token_account() -> TreeTokenAccount {
+    TreeTokenAccount {
+        authority: Pubkey::new_unique(),
+        total_deposited: 0,
+        total_withdrawn: 0,
+        total_fees: 0,
+        denomination_deposits: [0; 8],
+        denomination_withdrawals: [0; 8],
+        bump: 255,
+    }
+}
\ No newline at end of file
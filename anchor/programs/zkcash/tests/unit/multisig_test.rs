@@ -0,0 +1,201 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use zkcash::{
+    multisig::{validate_action_accounts, validate_members, MultisigAction, MAX_MULTISIG_ACTION_ACCOUNTS, MAX_MULTISIG_MEMBERS},
+    Multisig, MultisigProposal,
+};
+
+fn create_test_multisig(members: &[Pubkey], threshold: u8) -> Multisig {
+    let mut multisig = Multisig {
+        members: [Pubkey::default(); MAX_MULTISIG_MEMBERS],
+        member_count: 0,
+        threshold: 0,
+        nonce: 0,
+        next_proposal_id: 0,
+        bump: 255,
+    };
+    multisig.set_members(members, threshold).unwrap();
+    multisig
+}
+
+#[test]
+fn test_validate_members() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    assert!(validate_members(&members, 1).is_ok());
+    assert!(validate_members(&members, 3).is_ok());
+
+    let too_many: Vec<Pubkey> = (0..MAX_MULTISIG_MEMBERS + 1).map(|_| Pubkey::new_unique()).collect();
+    let err = validate_members(&too_many, 2).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidMultisigMembers"));
+
+    let err = validate_members(&[], 0).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidMultisigMembers"));
+
+    let duplicated = vec![members[0], members[1], members[0]];
+    let err = validate_members(&duplicated, 2).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidMultisigMembers"));
+}
+
+#[test]
+fn test_validate_members_rejects_unreachable_threshold() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+
+    let err = validate_members(&members, 0).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidMultisigThreshold"));
+
+    let err = validate_members(&members, 4).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidMultisigThreshold"));
+}
+
+#[test]
+fn test_set_members_bumps_nonce_and_clears_old_members() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let mut multisig = create_test_multisig(&members, 2);
+    assert_eq!(multisig.nonce, 1);
+    assert_eq!(multisig.member_index(&members[2]), Some(2));
+
+    let new_members = vec![members[1], Pubkey::new_unique()];
+    multisig.set_members(&new_members, 2).unwrap();
+
+    assert_eq!(multisig.nonce, 2);
+    assert_eq!(multisig.member_count, 2);
+    assert_eq!(multisig.member_index(&members[0]), None);
+    assert_eq!(multisig.member_index(&members[1]), Some(0));
+    assert_eq!(multisig.member_index(&members[2]), None);
+    assert_eq!(multisig.members[2], Pubkey::default());
+}
+
+#[test]
+fn test_set_members_keeps_state_on_invalid_input() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let mut multisig = create_test_multisig(&members, 2);
+
+    assert!(multisig.set_members(&members, 5).is_err());
+    assert_eq!(multisig.nonce, 1);
+    assert_eq!(multisig.threshold, 2);
+    assert_eq!(multisig.member_count, 3);
+}
+
+#[test]
+fn test_member_index_ignores_unused_slots() {
+    let members = vec![Pubkey::new_unique()];
+    let multisig = create_test_multisig(&members, 1);
+
+    assert_eq!(multisig.member_index(&members[0]), Some(0));
+    // Unused slots hold the default key, which must not count as a member
+    assert_eq!(multisig.member_index(&Pubkey::default()), None);
+}
+
+#[test]
+fn test_is_approved() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let multisig = create_test_multisig(&members, 2);
+
+    assert!(!multisig.is_approved(0b000));
+    assert!(!multisig.is_approved(0b100));
+    assert!(multisig.is_approved(0b101));
+    assert!(multisig.is_approved(0b111));
+}
+
+#[test]
+fn test_proposal_goes_stale_when_members_change() {
+    let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let mut multisig = create_test_multisig(&members, 2);
+    let proposal = MultisigProposal {
+        id: 0,
+        action: MultisigAction::UpdateDepositLimit { new_limit: 5_000_000_000 },
+        accounts: vec![],
+        proposer: members[0],
+        multisig_nonce: multisig.nonce,
+        approvals: 0b001,
+        bump: 255,
+    };
+    assert!(!multisig.is_stale(&proposal));
+
+    multisig.set_members(&members, 3).unwrap();
+    assert!(multisig.is_stale(&proposal));
+}
+
+#[test]
+fn test_action_instruction_data_targets_admin_instruction() {
+    let action = MultisigAction::UpdateDepositLimit { new_limit: 5_000_000_000 };
+    let data = action.instruction_data();
+
+    assert_eq!(&data[..8], zkcash::instruction::UpdateDepositLimit::DISCRIMINATOR);
+    assert_eq!(&data[8..], &5_000_000_000u64.to_le_bytes());
+
+    let action = MultisigAction::CancelConfigChange { id: 7 };
+    let data = action.instruction_data();
+
+    assert_eq!(&data[..8], zkcash::instruction::CancelConfigChange::DISCRIMINATOR);
+    assert_eq!(&data[8..], &7u64.to_le_bytes());
+
+    let address = Pubkey::new_unique();
+    let action = MultisigAction::AddToDenylist { address };
+    let data = action.instruction_data();
+
+    assert_eq!(&data[..8], zkcash::instruction::AddToDenylist::DISCRIMINATOR);
+    assert_eq!(&data[8..], address.as_ref());
+
+    let action = MultisigAction::ResolveCancelledWithdrawal { nullifier: [9u8; 32], recipient: address };
+    let data = action.instruction_data();
+
+    assert_eq!(&data[..8], zkcash::instruction::ResolveCancelledWithdrawal::DISCRIMINATOR);
+    assert_eq!(&data[8..40], &[9u8; 32]);
+    assert_eq!(&data[40..], address.as_ref());
+
+    let action = MultisigAction::InitializeCommitmentQueue { shard: 3 };
+    let data = action.instruction_data();
+
+    assert_eq!(&data[..8], zkcash::instruction::InitializeCommitmentQueue::DISCRIMINATOR);
+    assert_eq!(&data[8..], &[3u8]);
+
+    let data = MultisigAction::CancelWithdrawal.instruction_data();
+    assert_eq!(data, zkcash::instruction::CancelWithdrawal::DISCRIMINATOR);
+}
+
+#[test]
+fn test_proposal_runs_only_with_its_accounts() {
+    let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let mut lamports = [0u64; 3];
+    let mut data = [[0u8; 0]; 3];
+    let [l0, l1, l2] = &mut lamports;
+    let [d0, d1, d2] = &mut data;
+    let infos = [
+        AccountInfo::new(&keys[0], false, true, l0, d0, &zkcash::ID, false, 0),
+        AccountInfo::new(&keys[1], false, false, l1, d1, &zkcash::ID, false, 0),
+        AccountInfo::new(&keys[2], false, false, l2, d2, &zkcash::ID, false, 0),
+    ];
+    let proposal = MultisigProposal {
+        id: 0,
+        action: MultisigAction::CancelWithdrawal,
+        accounts: keys[..2].to_vec(),
+        proposer: keys[2],
+        multisig_nonce: 0,
+        approvals: 0b011,
+        bump: 255,
+    };
+
+    proposal.check_accounts(&infos[..2]).unwrap();
+
+    // Another pending withdrawal than the one approved
+    let err = proposal.check_accounts(&[infos[2].clone(), infos[1].clone()]).unwrap_err();
+    assert!(format!("{:?}", err).contains("MultisigAccountsMismatch"));
+
+    // the approved ones in another order
+    let err = proposal.check_accounts(&[infos[1].clone(), infos[0].clone()]).unwrap_err();
+    assert!(format!("{:?}", err).contains("MultisigAccountsMismatch"));
+
+    // or extra ones
+    let err = proposal.check_accounts(&infos).unwrap_err();
+    assert!(format!("{:?}", err).contains("MultisigAccountsMismatch"));
+}
+
+#[test]
+fn test_validate_action_accounts() {
+    let accounts: Vec<Pubkey> = (0..MAX_MULTISIG_ACTION_ACCOUNTS + 1).map(|_| Pubkey::new_unique()).collect();
+
+    validate_action_accounts(&accounts[..MAX_MULTISIG_ACTION_ACCOUNTS]).unwrap();
+    let err = validate_action_accounts(&accounts).unwrap_err();
+    assert!(format!("{:?}", err).contains("MultisigAccountsMismatch"));
+}
@@ -0,0 +1,83 @@
+use anchor_lang::prelude::Pubkey;
+use zkcash::{
+    pending_withdrawal::{record_pending_withdrawal, DelayedWithdrawal},
+    GlobalConfig, PendingWithdrawal,
+};
+
+fn create_test_global_config(large_withdrawal_threshold: u64) -> GlobalConfig {
+    GlobalConfig { large_withdrawal_threshold, ..super::create_test_global_config() }
+}
+
+fn create_test_pending_withdrawal() -> PendingWithdrawal {
+    PendingWithdrawal {
+        nullifier: [0u8; 32],
+        recipient: Pubkey::default(),
+        payer: Pubkey::default(),
+        amount: 0,
+        release_slot: 0,
+        bump: 0,
+        cancelled: false,
+    }
+}
+
+#[test]
+fn test_only_withdrawals_above_threshold_are_delayed() {
+    let global_config = create_test_global_config(5_000);
+
+    assert_eq!(global_config.delay_withdrawal(5_000, 100).unwrap(), None);
+    assert_eq!(
+        global_config.delay_withdrawal(5_001, 100).unwrap(),
+        Some(DelayedWithdrawal { amount: 5_001, release_slot: 1_100 })
+    );
+
+    assert!(global_config.check_immediate_withdrawal(5_000).is_ok());
+    let err = global_config.check_immediate_withdrawal(5_001).unwrap_err();
+    assert!(format!("{:?}", err).contains("WithdrawalAboveThreshold"));
+}
+
+#[test]
+fn test_zero_threshold_disables_delay() {
+    let global_config = create_test_global_config(0);
+
+    assert_eq!(global_config.delay_withdrawal(u64::MAX, 100).unwrap(), None);
+    assert!(global_config.check_immediate_withdrawal(u64::MAX).is_ok());
+}
+
+#[test]
+fn test_release_slot_overflow() {
+    let global_config = create_test_global_config(1);
+    let err = global_config.delay_withdrawal(2, u64::MAX).unwrap_err();
+    assert!(format!("{:?}", err).contains("ArithmeticOverflow"));
+}
+
+#[test]
+fn test_record_pending_withdrawal() {
+    let mut pending_withdrawal = create_test_pending_withdrawal();
+    let recipient = Pubkey::new_unique();
+    let payer = Pubkey::new_unique();
+    let delayed = DelayedWithdrawal { amount: 7_000, release_slot: 1_100 };
+
+    record_pending_withdrawal(Some(&mut pending_withdrawal), Some(253), Some(delayed), recipient, payer, [9u8; 32]).unwrap();
+
+    assert_eq!(pending_withdrawal.nullifier, [9u8; 32]);
+    assert_eq!(pending_withdrawal.recipient, recipient);
+    assert_eq!(pending_withdrawal.payer, payer);
+    assert_eq!(pending_withdrawal.amount, 7_000);
+    assert_eq!(pending_withdrawal.bump, 253);
+    assert!(!pending_withdrawal.cancelled);
+    assert!(!pending_withdrawal.is_claimable(1_099));
+    assert!(pending_withdrawal.is_claimable(1_100));
+}
+
+#[test]
+fn test_pending_withdrawal_account_only_for_delayed_withdrawals() {
+    let delayed = DelayedWithdrawal { amount: 7_000, release_slot: 1_100 };
+    let err = record_pending_withdrawal(None, None, Some(delayed), Pubkey::new_unique(), Pubkey::new_unique(), [9u8; 32]).unwrap_err();
+    assert!(format!("{:?}", err).contains("PendingWithdrawalRequired"));
+
+    record_pending_withdrawal(None, None, None, Pubkey::new_unique(), Pubkey::new_unique(), [9u8; 32]).unwrap();
+
+    let mut pending_withdrawal = create_test_pending_withdrawal();
+    let err = record_pending_withdrawal(Some(&mut pending_withdrawal), Some(253), None, Pubkey::new_unique(), Pubkey::new_unique(), [9u8; 32]).unwrap_err();
+    assert!(format!("{:?}", err).contains("PendingWithdrawalNotAllowed"));
+}
@@ -0,0 +1,192 @@
+use anchor_lang::prelude::*;
+use zkcash::{
+    price_feed::{
+        load_price, Price, PriceFeed, PythPriceMessage, PythPriceUpdate, PythVerificationLevel,
+        PYTH_RECEIVER_PROGRAM_ID, PYTH_SOL_USD_FEED_ID,
+    },
+    GlobalConfig,
+};
+#[cfg(feature = "localnet")]
+use zkcash::MockPriceFeed;
+
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+/// Pool config with a price feed and USD limits configured.
+fn create_usd_limited_global_config() -> GlobalConfig {
+    GlobalConfig {
+        price_feed: Pubkey::new_unique(),
+        max_price_age_slots: 150,
+        max_deposit_usd: 10_000_000_000, // $10,000
+        max_tvl_usd: 1_000_000_000_000,  // $1,000,000
+        ..super::create_test_global_config()
+    }
+}
+
+// $150 per SOL, published at slot 1_000
+const PRICE: Price = Price {
+    micro_usd_per_sol: 150_000_000,
+    publish_slot: 1_000,
+};
+
+#[test]
+fn test_usd_value_of_lamports() {
+    assert_eq!(PRICE.usd_value(LAMPORTS_PER_SOL).unwrap(), 150_000_000);
+    assert_eq!(PRICE.usd_value(LAMPORTS_PER_SOL / 2).unwrap(), 75_000_000);
+    assert_eq!(PRICE.usd_value(0).unwrap(), 0);
+}
+
+// $150 per SOL with Pyth's usual exponent of -8, posted at slot 1_000
+fn create_pyth_price_update() -> PythPriceUpdate {
+    PythPriceUpdate {
+        write_authority: Pubkey::new_unique(),
+        verification_level: PythVerificationLevel::Full,
+        price_message: PythPriceMessage {
+            feed_id: PYTH_SOL_USD_FEED_ID,
+            price: 15_000_000_000,
+            conf: 5_000_000,
+            exponent: -8,
+            publish_time: 1_700_000_000,
+            prev_publish_time: 1_699_999_999,
+            ema_price: 14_900_000_000,
+            ema_conf: 5_000_000,
+        },
+        posted_slot: 1_000,
+    }
+}
+
+fn pyth_account_data(update: &PythPriceUpdate) -> Vec<u8> {
+    let mut data = PythPriceUpdate::DISCRIMINATOR.to_vec();
+    update.serialize(&mut data).unwrap();
+    data
+}
+
+#[cfg(feature = "localnet")]
+#[test]
+fn test_mock_feed_reports_its_price() {
+    let feed = MockPriceFeed {
+        authority: Pubkey::new_unique(),
+        micro_usd_per_sol: 150_000_000,
+        publish_slot: 1_000,
+        bump: 255,
+    };
+    assert_eq!(feed.latest_price().unwrap(), PRICE);
+}
+
+#[test]
+fn test_pyth_feed_reports_its_price_in_micro_usd() {
+    let mut update = create_pyth_price_update();
+    assert_eq!(update.latest_price().unwrap(), PRICE);
+
+    update.price_message.price = 150;
+    update.price_message.exponent = 0;
+    assert_eq!(update.latest_price().unwrap(), PRICE);
+}
+
+#[test]
+fn test_pyth_feed_rejects_unusable_updates() {
+    let mut partial = create_pyth_price_update();
+    partial.verification_level = PythVerificationLevel::Partial { num_signatures: 5 };
+
+    let mut other_feed = create_pyth_price_update();
+    other_feed.price_message.feed_id = [1u8; 32];
+
+    let mut negative = create_pyth_price_update();
+    negative.price_message.price = -15_000_000_000;
+
+    let mut below_a_micro_usd = create_pyth_price_update();
+    below_a_micro_usd.price_message.price = 99;
+
+    for update in [partial, other_feed, negative, below_a_micro_usd] {
+        let err = update.latest_price().unwrap_err();
+        assert!(format!("{:?}", err).contains("InvalidPriceFeed"));
+    }
+}
+
+#[test]
+fn test_load_price_reads_the_configured_pyth_account() {
+    let config = create_usd_limited_global_config();
+    let mut lamports = 1_000_000;
+    let mut data = pyth_account_data(&create_pyth_price_update());
+    let feed = AccountInfo::new(&config.price_feed, false, false, &mut lamports, &mut data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+
+    assert_eq!(load_price(&config, Some(&feed)).unwrap(), Some(PRICE));
+}
+
+#[test]
+fn test_load_price_rejects_other_owners_and_layouts() {
+    let config = create_usd_limited_global_config();
+
+    let mut lamports = 1_000_000;
+    let mut data = pyth_account_data(&create_pyth_price_update());
+    let owner = Pubkey::new_unique();
+    let foreign_feed = AccountInfo::new(&config.price_feed, false, false, &mut lamports, &mut data, &owner, false, 0);
+    let err = load_price(&config, Some(&foreign_feed)).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPriceFeed"));
+
+    let mut lamports = 1_000_000;
+    let mut data = vec![0u8; 134];
+    let not_a_price_update = AccountInfo::new(&config.price_feed, false, false, &mut lamports, &mut data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+    let err = load_price(&config, Some(&not_a_price_update)).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPriceFeed"));
+}
+
+#[test]
+fn test_deposits_within_usd_limits_are_accepted() {
+    let config = create_usd_limited_global_config();
+
+    // 60 SOL = $9,000
+    assert!(config.check_usd_limits(Some(PRICE), 60 * LAMPORTS_PER_SOL, 0, 1_100).is_ok());
+    // Oldest accepted price
+    assert!(config.check_usd_limits(Some(PRICE), LAMPORTS_PER_SOL, 0, 1_150).is_ok());
+}
+
+#[test]
+fn test_stale_price_is_rejected() {
+    let config = create_usd_limited_global_config();
+
+    let err = config.check_usd_limits(Some(PRICE), LAMPORTS_PER_SOL, 0, 1_151).unwrap_err();
+    assert!(format!("{:?}", err).contains("StalePrice"));
+}
+
+#[test]
+fn test_usd_deposit_limit_and_tvl_cap() {
+    let mut config = create_usd_limited_global_config();
+
+    // 70 SOL = $10,500
+    let err = config.check_usd_limits(Some(PRICE), 70 * LAMPORTS_PER_SOL, 0, 1_000).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositLimitExceeded"));
+
+    // 6,650 SOL shielded + 60 SOL = $1,006,500
+    let err = config.check_usd_limits(Some(PRICE), 60 * LAMPORTS_PER_SOL, 6_650 * LAMPORTS_PER_SOL, 1_000).unwrap_err();
+    assert!(format!("{:?}", err).contains("TvlCapExceeded"));
+
+    config.max_deposit_usd = 0;
+    config.max_tvl_usd = 0;
+    assert!(config.check_usd_limits(Some(PRICE), 10_000 * LAMPORTS_PER_SOL, 6_650 * LAMPORTS_PER_SOL, 1_000).is_ok());
+}
+
+#[test]
+fn test_limits_are_off_without_a_price_feed() {
+    let mut config = create_usd_limited_global_config();
+    config.price_feed = Pubkey::default();
+
+    let price = load_price(&config, None).unwrap();
+    assert_eq!(price, None);
+    assert!(config.check_usd_limits(price, u64::MAX, 0, 1_000).is_ok());
+}
+
+#[test]
+fn test_configured_price_feed_must_be_passed() {
+    let config = create_usd_limited_global_config();
+
+    let err = load_price(&config, None).unwrap_err();
+    assert!(format!("{:?}", err).contains("PriceFeedRequired"));
+
+    let key = Pubkey::new_unique();
+    let owner = zkcash::ID;
+    let mut lamports = 1_000_000;
+    let mut data = vec![0u8; 64];
+    let wrong_feed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+    let err = load_price(&config, Some(&wrong_feed)).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPriceFeed"));
+}
@@ -0,0 +1,126 @@
+use anchor_lang::prelude::*;
+use zkcash::{
+    ragequit::{record_deposit_origin, record_queued_leaf_indices},
+    DepositOrigin,
+};
+
+fn create_test_origin() -> DepositOrigin {
+    DepositOrigin {
+        depositor: Pubkey::default(),
+        commitments: [[0u8; 32]; 2],
+        leaf_indices: [None, None],
+        amount: 0,
+        bump: 0,
+    }
+}
+
+/// Account data of `origin` as the program stores it
+fn origin_account_data(origin: &DepositOrigin) -> Vec<u8> {
+    let mut data = Vec::new();
+    origin.try_serialize(&mut data).unwrap();
+    data.resize(8 + std::mem::size_of::<DepositOrigin>(), 0);
+    data
+}
+
+#[test]
+fn test_deposit_records_origin() {
+    let mut origin = create_test_origin();
+    let depositor = Pubkey::new_unique();
+
+    record_deposit_origin(Some(&mut origin), Some(254), 1_000, 25, depositor, [[1u8; 32], [2u8; 32]], Some(6)).unwrap();
+
+    assert_eq!(origin.depositor, depositor);
+    assert_eq!(origin.bump, 254);
+    assert_eq!(origin.amount, 975);
+    assert_eq!(origin.note(0).unwrap(), ([1u8; 32], 6));
+    assert_eq!(origin.note(1).unwrap(), ([2u8; 32], 7));
+
+    let err = origin.note(2).unwrap_err();
+    assert!(format!("{:?}", err).contains("CommitmentNotInDeposit"));
+}
+
+#[test]
+fn test_queued_deposit_has_no_leaf_index_yet() {
+    let mut origin = create_test_origin();
+    record_deposit_origin(Some(&mut origin), Some(254), 1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], None).unwrap();
+
+    let err = origin.note(0).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositNotInserted"));
+}
+
+#[test]
+fn test_deposit_without_origin_record_fails() {
+    let err = record_deposit_origin(None, None, 1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], Some(0)).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositOriginRequired"));
+}
+
+#[test]
+fn test_only_deposits_record_origin() {
+    // Withdrawals and transfers need no record
+    record_deposit_origin(None, None, -1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], Some(0)).unwrap();
+    record_deposit_origin(None, None, 0, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], Some(0)).unwrap();
+
+    // and must not create one, or their signer could ragequit the outputs to itself
+    for ext_amount in [-1_000, 0] {
+        let mut origin = create_test_origin();
+        let err = record_deposit_origin(Some(&mut origin), Some(254), ext_amount, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], Some(0)).unwrap_err();
+        assert!(format!("{:?}", err).contains("DepositOriginNotAllowed"));
+    }
+}
+
+#[test]
+fn test_ragequits_are_capped_at_the_deposit() {
+    let mut origin = create_test_origin();
+    record_deposit_origin(Some(&mut origin), Some(254), 1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], Some(0)).unwrap();
+
+    // A note holding more than the deposit, e.g. one that also took in private funds,
+    // still only returns what the deposit put in
+    let err = origin.withdraw(1_001).unwrap_err();
+    assert!(format!("{:?}", err).contains("RagequitExceedsDeposit"));
+
+    // and the cap is shared by both outputs
+    origin.withdraw(600).unwrap();
+    let err = origin.withdraw(401).unwrap_err();
+    assert!(format!("{:?}", err).contains("RagequitExceedsDeposit"));
+    origin.withdraw(400).unwrap();
+    assert_eq!(origin.amount, 0);
+}
+
+#[test]
+fn test_queued_leaf_indices_are_recorded_on_insertion() {
+    let mut origin = create_test_origin();
+    record_deposit_origin(Some(&mut origin), Some(254), 1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], None).unwrap();
+
+    let key = Pubkey::new_unique();
+    let mut lamports = 1_000_000;
+    let mut data = origin_account_data(&origin);
+    let origin_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &zkcash::ID, false, 0);
+    let origins = [origin_info.clone(), origin_info.clone()];
+
+    // Another shard's commitment sits between the two outputs
+    let queued = [([1u8; 32], true), ([9u8; 32], false), ([2u8; 32], true)];
+    record_queued_leaf_indices(&queued, 40, &origins).unwrap();
+
+    let origin = DepositOrigin::try_deserialize(&mut &origin_info.data.borrow()[..]).unwrap();
+    assert_eq!(origin.note(0).unwrap(), ([1u8; 32], 40));
+    assert_eq!(origin.note(1).unwrap(), ([2u8; 32], 42));
+}
+
+#[test]
+fn test_queued_leaf_indices_need_matching_origins() {
+    let mut origin = create_test_origin();
+    record_deposit_origin(Some(&mut origin), Some(254), 1_000, 0, Pubkey::new_unique(), [[1u8; 32], [2u8; 32]], None).unwrap();
+
+    let key = Pubkey::new_unique();
+    let mut lamports = 1_000_000;
+    let mut data = origin_account_data(&origin);
+    let origin_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &zkcash::ID, false, 0);
+
+    // Every flagged commitment needs its record
+    let err = record_queued_leaf_indices(&[([1u8; 32], true)], 0, &[]).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositOriginRequired"));
+
+    // and the record must be the one holding that commitment
+    let err = record_queued_leaf_indices(&[([3u8; 32], true)], 0, &[origin_info]).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidDepositOrigin"));
+}
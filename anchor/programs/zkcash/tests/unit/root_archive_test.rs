@@ -0,0 +1,126 @@
+use light_hasher::Poseidon;
+use std::mem::MaybeUninit;
+use zkcash::{
+    merkle_tree::MerkleTree,
+    root_archive::{archive_roots, ROOT_ARCHIVE_PAGE_SIZE},
+    MerkleTreeAccount, RootArchive,
+};
+
+use super::create_test_tree_account;
+
+const ROOT_HISTORY_SIZE: u64 = 100;
+
+fn create_test_archive(page: u64) -> RootArchive {
+    let mut uninit: MaybeUninit<RootArchive> = MaybeUninit::uninit();
+    unsafe {
+        let ptr = uninit.as_mut_ptr();
+        std::ptr::write_bytes(ptr, 0, 1);
+        (*ptr).page = page;
+        uninit.assume_init()
+    }
+}
+
+fn test_root(number: u64) -> [u8; 32] {
+    let mut root = [0u8; 32];
+    root[..8].copy_from_slice(&(number + 1).to_le_bytes());
+    root
+}
+
+/// Records roots the way MerkleTree does, with root number n recorded at slot 1000 + n.
+fn record_roots(tree_account: &mut MerkleTreeAccount, count: u64) {
+    for _ in 0..count {
+        let number = tree_account.root_count;
+        let index = (number % ROOT_HISTORY_SIZE) as usize;
+        tree_account.root_history[index] = test_root(number);
+        tree_account.root_history_slots[index] = 1000 + number;
+        tree_account.root_index = index as u64;
+        tree_account.root_count += 1;
+    }
+}
+
+#[test]
+fn test_archive_roots_copies_new_roots() {
+    let mut tree_account = create_test_tree_account();
+    let mut archive = create_test_archive(0);
+    record_roots(&mut tree_account, 10);
+
+    assert_eq!(archive_roots(&mut tree_account, &mut archive).unwrap(), 10);
+    assert_eq!(tree_account.archived_root_count, 10);
+    assert_eq!(archive.len, 10);
+    assert_eq!(archive.roots[9], test_root(9));
+    assert_eq!((archive.start_slot, archive.end_slot), (1000, 1009));
+
+    // Only roots recorded since the last call are copied
+    record_roots(&mut tree_account, 5);
+    assert_eq!(archive_roots(&mut tree_account, &mut archive).unwrap(), 5);
+    assert_eq!(archive.len, 15);
+    assert_eq!(archive_roots(&mut tree_account, &mut archive).unwrap(), 0);
+}
+
+#[test]
+fn test_archive_roots_stops_at_page_end() {
+    let mut tree_account = create_test_tree_account();
+    let mut first_page = create_test_archive(0);
+    let mut second_page = create_test_archive(1);
+
+    record_roots(&mut tree_account, 90);
+    archive_roots(&mut tree_account, &mut first_page).unwrap();
+    record_roots(&mut tree_account, 60);
+
+    assert_eq!(archive_roots(&mut tree_account, &mut first_page).unwrap(), ROOT_ARCHIVE_PAGE_SIZE as u64 - 90);
+    assert_eq!(first_page.len as usize, ROOT_ARCHIVE_PAGE_SIZE);
+
+    let err = archive_roots(&mut tree_account, &mut first_page).unwrap_err();
+    assert!(format!("{:?}", err).contains("WrongRootArchivePage"));
+
+    assert_eq!(archive_roots(&mut tree_account, &mut second_page).unwrap(), 150 - ROOT_ARCHIVE_PAGE_SIZE as u64);
+    assert_eq!(second_page.roots[0], test_root(ROOT_ARCHIVE_PAGE_SIZE as u64));
+}
+
+#[test]
+fn test_archive_roots_skips_evicted_roots() {
+    let mut tree_account = create_test_tree_account();
+    let mut archive = create_test_archive(0);
+    record_roots(&mut tree_account, 120);
+
+    // Roots 0..20 left the root history before anyone archived them
+    assert_eq!(archive_roots(&mut tree_account, &mut archive).unwrap(), 100);
+    assert_eq!(archive.roots[19], [0u8; 32]);
+    assert_eq!(archive.roots[20], test_root(20));
+    assert_eq!(archive.start_slot, 1020);
+}
+
+#[test]
+fn test_appends_do_not_wait_for_the_archive() {
+    let mut tree_account = create_test_tree_account();
+    tree_account.height = 26;
+    MerkleTree::initialize::<Poseidon>(&mut tree_account).unwrap();
+    let initial_root = tree_account.root;
+
+    // Nobody archives while the root history wraps around
+    for i in 0..ROOT_HISTORY_SIZE + 20 {
+        let mut leaf = [0u8; 32];
+        leaf[24..].copy_from_slice(&i.to_be_bytes());
+        MerkleTree::append::<Poseidon>(leaf, &mut tree_account, 1000 + i).unwrap();
+    }
+    assert!(!MerkleTree::is_known_root(&tree_account, initial_root));
+
+    let mut archive = create_test_archive(0);
+    assert_eq!(archive_roots(&mut tree_account, &mut archive).unwrap(), ROOT_HISTORY_SIZE);
+    assert!(!archive.is_valid_root(initial_root, 1200, 1000));
+    assert_eq!(tree_account.archived_root_count, tree_account.root_count);
+}
+
+#[test]
+fn test_is_valid_root_checks_age() {
+    let mut tree_account = create_test_tree_account();
+    let mut archive = create_test_archive(0);
+    record_roots(&mut tree_account, 10);
+    archive_roots(&mut tree_account, &mut archive).unwrap();
+
+    // Root 3 was recorded at slot 1003
+    assert!(archive.is_valid_root(test_root(3), 1103, 100));
+    assert!(!archive.is_valid_root(test_root(3), 1104, 100));
+    assert!(!archive.is_valid_root(test_root(10), 1010, 100));
+    assert!(!archive.is_valid_root([0u8; 32], 1010, 100));
+}
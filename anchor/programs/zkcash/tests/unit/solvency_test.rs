@@ -0,0 +1,51 @@
+use zkcash::solvency::check_solvency;
+
+use super::create_test_tree_token_account;
+
+const RENT_EXEMPT_MINIMUM: u64 = 1_000_000;
+
+#[test]
+fn test_record_transaction_updates_counters() {
+    let mut account = create_test_tree_token_account();
+
+    account.record_transaction(10_000, 100).unwrap(); // deposit
+    account.record_transaction(-4_000, 50).unwrap(); // withdrawal
+    account.record_transaction(0, 10).unwrap(); // transfer
+
+    assert_eq!(account.total_deposited, 10_000);
+    assert_eq!(account.total_withdrawn, 4_000);
+    assert_eq!(account.total_fees, 160);
+    assert_eq!(account.net_shielded().unwrap(), 5_840);
+}
+
+#[test]
+fn test_record_transaction_overflow() {
+    let mut account = create_test_tree_token_account();
+    account.total_deposited = u64::MAX;
+
+    let err = account.record_transaction(1, 0).unwrap_err();
+    assert!(format!("{:?}", err).contains("ArithmeticOverflow"));
+}
+
+#[test]
+fn test_check_solvency() {
+    let mut account = create_test_tree_token_account();
+    account.record_transaction(10_000, 100).unwrap();
+
+    assert!(check_solvency(&account, 9_900 + RENT_EXEMPT_MINIMUM, RENT_EXEMPT_MINIMUM).is_ok());
+    // Lamports sent to the vault directly are a surplus
+    assert!(check_solvency(&account, 50_000 + RENT_EXEMPT_MINIMUM, RENT_EXEMPT_MINIMUM).is_ok());
+
+    let err = check_solvency(&account, 9_899 + RENT_EXEMPT_MINIMUM, RENT_EXEMPT_MINIMUM).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvariantViolated"));
+}
+
+#[test]
+fn test_more_paid_out_than_deposited_violates_invariant() {
+    let mut account = create_test_tree_token_account();
+    account.record_transaction(1_000, 0).unwrap();
+    account.record_transaction(-1_000, 1).unwrap();
+
+    let err = check_solvency(&account, u64::MAX, RENT_EXEMPT_MINIMUM).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvariantViolated"));
+}
@@ -4,7 +4,7 @@ use ark_ff::{PrimeField, BigInteger};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress, Validate};
 use std::ops::Neg;
 use ark_bn254::Fr;
-use zkcash::{groth16::{is_less_than_bn254_field_size_be, Groth16Verifyingkey}, utils::{change_endianness, check_public_amount, verify_proof, validate_fee, calculate_complete_ext_data_hash, VERIFYING_KEY}, Proof};
+use zkcash::{groth16::{is_less_than_bn254_field_size_be, Groth16Verifyingkey}, utils::{change_endianness, check_public_amount, verify_proof, calculate_expected_fee, calculate_fee_quote, validate_fee, validate_max_fee, validate_initializer, validate_payouts, calculate_payouts_commitment, calculate_complete_ext_data_hash, VERIFYING_KEY, MAX_PAYOUTS}, FeeSchedule, Payout, Proof};
 use anchor_lang::prelude::*;
 
 type G1 = ark_bn254::g1::G1Affine;
@@ -686,11 +686,12 @@ fn test_validate_fee_deposit_exact_minimum() {
     // 1000 * 25 / 10000 = 2.5 -> 2 (rounded down)
     // minimum = 2 * 95% = 1.9 -> 1 (rounded down)
     let result = validate_fee(
-        1000,  // ext_amount (deposit)
-        1,     // provided_fee (exact minimum)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        1000,                        // ext_amount (deposit)
+        1,                           // provided_fee (exact minimum)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -699,11 +700,12 @@ fn test_validate_fee_deposit_exact_minimum() {
 fn test_validate_fee_deposit_above_minimum() {
     // Test deposit with fee above minimum
     let result = validate_fee(
-        1000,  // ext_amount (deposit)
-        10,    // provided_fee (well above minimum)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        1000,                        // ext_amount (deposit)
+        10,                          // provided_fee (well above minimum)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -715,11 +717,12 @@ fn test_validate_fee_deposit_below_minimum() {
     // 10000 * 0 / 10000 = 0 (expected fee)
     // minimum = 0 * 95% = 0 (minimum acceptable fee)
     let result = validate_fee(
-        10000, // ext_amount (deposit)
-        0,     // provided_fee (even 0 is acceptable for free deposits)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        10000,                       // ext_amount (deposit)
+        0,                           // provided_fee (even 0 is acceptable for free deposits)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok()); // Should pass since deposits are free
 }
@@ -728,11 +731,12 @@ fn test_validate_fee_deposit_below_minimum() {
 fn test_validate_fee_withdrawal_zero_rate() {
     // Test withdrawal with 0.25% fee rate - any fee should be accepted above minimum
     let result = validate_fee(
-        -1000, // ext_amount (withdrawal)
-        5,     // provided_fee (any amount is fine since expected is 0)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        -1000,                       // ext_amount (withdrawal)
+        5,                           // provided_fee (any amount is fine since expected is 0)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -743,11 +747,12 @@ fn test_validate_fee_withdrawal_with_rate() {
     // 1000 * 50 / 10000 = 5
     // minimum = 5 * 95% = 4.75 -> 4 (rounded down)
     let result = validate_fee(
-        -1000, // ext_amount (withdrawal)
-        4,     // provided_fee (exact minimum)
-        0,     // deposit_fee_rate (0% - free deposits)
-        50,    // withdrawal_fee_rate (0.5%)
-        500,   // error_rate (5%)
+        -1000,                       // ext_amount (withdrawal)
+        4,                           // provided_fee (exact minimum)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(50), // withdrawal_fee (0.5%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -758,11 +763,12 @@ fn test_validate_fee_withdrawal_below_minimum() {
     // 1000 * 100 / 10000 = 10
     // minimum = 10 * 95% = 9.5 -> 9 (rounded down)
     let result = validate_fee(
-        -1000, // ext_amount (withdrawal)
-        8,     // provided_fee (below minimum of 9)
-        0,     // deposit_fee_rate (0% - free deposits)
-        100,   // withdrawal_fee_rate (1%)
-        500,   // error_rate (5%)
+        -1000,                        // ext_amount (withdrawal)
+        8,                            // provided_fee (below minimum of 9)
+        &FeeSchedule::from_rate(0),   // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(100), // withdrawal_fee (1%)
+        &FeeSchedule::from_rate(0),   // transfer_fee
+        500,                          // error_rate (5%)
     );
     assert!(result.is_err());
 }
@@ -771,11 +777,12 @@ fn test_validate_fee_withdrawal_below_minimum() {
 fn test_validate_fee_zero_amount() {
     // Test with zero ext_amount (should always pass)
     let result = validate_fee(
-        0,     // ext_amount (neither deposit nor withdrawal)
-        100,   // provided_fee
-        0,     // deposit_fee_rate (0% - free deposits)
-        50,    // withdrawal_fee_rate
-        500,   // error_rate
+        0,                           // ext_amount (neither deposit nor withdrawal)
+        100,                         // provided_fee
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(50), // withdrawal_fee
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate
     );
     assert!(result.is_ok());
 }
@@ -785,11 +792,12 @@ fn test_validate_fee_small_deposit_zero_expected() {
     // Test very small deposit that results in 0 expected fee
     // 1 * 25 / 10000 = 0.0025 -> 0 (rounded down)
     let result = validate_fee(
-        1,     // ext_amount (very small deposit)
-        0,     // provided_fee (0 is acceptable when expected is 0)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        1,                           // ext_amount (very small deposit)
+        0,                           // provided_fee (0 is acceptable when expected is 0)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -800,11 +808,12 @@ fn test_validate_fee_high_error_rate() {
     // 1000 * 25 / 10000 = 2.5 -> 2
     // minimum = 2 * 50% = 1
     let result = validate_fee(
-        1000,  // ext_amount (deposit)
-        1,     // provided_fee (minimum with 50% error rate)
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        5000,  // error_rate (50%)
+        1000,                        // ext_amount (deposit)
+        1,                           // provided_fee (minimum with 50% error rate)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        5000,                        // error_rate (50%)
     );
     assert!(result.is_ok());
 }
@@ -814,11 +823,12 @@ fn test_validate_fee_overflow_protection() {
     // Test that we don't overflow with large amounts
     // Use a large but safe value that won't cause overflow during multiplication
     let result = validate_fee(
-        1_000_000_000, // ext_amount (1 billion, large but safe)
-        1000000,       // provided_fee
-        1,             // deposit_fee_rate (small rate to avoid overflow)
-        25,            // withdrawal_fee_rate (0.25%)
-        500,           // error_rate (5%)
+        1_000_000_000,               // ext_amount (1 billion, large but safe)
+        1000000,                     // provided_fee
+        &FeeSchedule::from_rate(1),  // deposit_fee (small rate to avoid overflow)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -827,11 +837,12 @@ fn test_validate_fee_overflow_protection() {
 fn test_validate_fee_edge_case_min_withdrawal() {
     // Test edge case with minimum negative value (but not i64::MIN)
     let result = validate_fee(
-        -1,    // ext_amount (smallest withdrawal)
-        0,     // provided_fee
-        0,     // deposit_fee_rate (0% - free deposits)
-        25,    // withdrawal_fee_rate (0.25%)
-        500,   // error_rate (5%)
+        -1,                          // ext_amount (smallest withdrawal)
+        0,                           // provided_fee
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -841,11 +852,12 @@ fn test_validate_fee_arithmetic_overflow_detection() {
     // Test that arithmetic overflow is properly detected and handled
     // Using maximum values that would cause overflow in the multiplication
     let result = validate_fee(
-        i64::MAX,  // ext_amount (maximum positive value)
-        0,         // provided_fee
-        10000,     // deposit_fee_rate (100% - maximum rate)
-        25,        // withdrawal_fee_rate (0.25%)
-        0,         // error_rate (0% to test exact calculation)
+        i64::MAX,                       // ext_amount (maximum positive value)
+        0,                              // provided_fee
+        &FeeSchedule::from_rate(10000), // deposit_fee (100% - maximum rate)
+        &FeeSchedule::from_rate(25),    // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),     // transfer_fee
+        0,                              // error_rate (0% to test exact calculation)
     );
     // This should return an error (either arithmetic overflow or invalid fee amount)
     assert!(result.is_err());
@@ -855,11 +867,12 @@ fn test_validate_fee_arithmetic_overflow_detection() {
 fn test_validate_fee_large_fee_rates() {
     // Test with maximum fee rates
     let result = validate_fee(
-        1000,  // ext_amount (deposit)
-        950,   // provided_fee (95% of 1000)
-        10000, // deposit_fee_rate (100%)
-        10000, // withdrawal_fee_rate (100%)
-        500,   // error_rate (5%)
+        1000,                           // ext_amount (deposit)
+        950,                            // provided_fee (95% of 1000)
+        &FeeSchedule::from_rate(10000), // deposit_fee (100%)
+        &FeeSchedule::from_rate(10000), // withdrawal_fee (100%)
+        &FeeSchedule::from_rate(0),     // transfer_fee
+        500,                            // error_rate (5%)
     );
     assert!(result.is_ok());
 }
@@ -870,11 +883,12 @@ fn test_validate_fee_exact_boundary_cases() {
     // 100 * 1000 / 10000 = 10
     // minimum = 10 * 90% = 9
     let result = validate_fee(
-        100,   // ext_amount (deposit)
-        9,     // provided_fee (exact minimum)
-        1000,  // deposit_fee_rate (10%)
-        0,     // withdrawal_fee_rate
-        1000,  // error_rate (10%)
+        100,                           // ext_amount (deposit)
+        9,                             // provided_fee (exact minimum)
+        &FeeSchedule::from_rate(1000), // deposit_fee (10%)
+        &FeeSchedule::from_rate(0),    // withdrawal_fee
+        &FeeSchedule::from_rate(0),    // transfer_fee
+        1000,                          // error_rate (10%)
     );
     assert!(result.is_ok());
 }
@@ -884,11 +898,12 @@ fn test_validate_fee_withdrawal_i64_min_protection() {
     // Test that i64::MIN is handled safely for withdrawals
     // This should not panic due to checked_neg() protection
     let result = validate_fee(
-        i64::MIN, // ext_amount (minimum possible withdrawal)
-        0,        // provided_fee
-        0,        // deposit_fee_rate (0% - free deposits)
-        25,       // withdrawal_fee_rate (0.25%)
-        500,      // error_rate (5%)
+        i64::MIN,                    // ext_amount (minimum possible withdrawal)
+        0,                           // provided_fee
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
     );
     // This should return an error due to arithmetic overflow protection
     assert!(result.is_err());
@@ -898,11 +913,12 @@ fn test_validate_fee_withdrawal_i64_min_protection() {
 fn test_validate_fee_max_basis_points() {
     // Test with maximum possible basis points (10000 = 100%)
     let result = validate_fee(
-        1000,  // ext_amount (deposit)
-        1000,  // provided_fee (100% of amount)
-        10000, // deposit_fee_rate (100%)
-        10000, // withdrawal_fee_rate (100%)
-        0,     // error_rate (0% - no tolerance)
+        1000,                           // ext_amount (deposit)
+        1000,                           // provided_fee (100% of amount)
+        &FeeSchedule::from_rate(10000), // deposit_fee (100%)
+        &FeeSchedule::from_rate(10000), // withdrawal_fee (100%)
+        &FeeSchedule::from_rate(0),     // transfer_fee
+        0,                              // error_rate (0% - no tolerance)
     );
     assert!(result.is_ok());
 }
@@ -912,11 +928,12 @@ fn test_validate_fee_precision_edge_cases() {
     // Test cases where rounding matters
     // 999 * 1 / 10000 = 0.0999 -> 0 (rounded down)
     let result = validate_fee(
-        999,   // ext_amount (deposit)
-        0,     // provided_fee (expected is 0 due to rounding)
-        1,     // deposit_fee_rate (0.01%)
-        25,    // withdrawal_fee_rate (0.25%)
-        0,     // error_rate (0% - exact calculation)
+        999,                         // ext_amount (deposit)
+        0,                           // provided_fee (expected is 0 due to rounding)
+        &FeeSchedule::from_rate(1),  // deposit_fee (0.01%)
+        &FeeSchedule::from_rate(25), // withdrawal_fee (0.25%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        0,                           // error_rate (0% - exact calculation)
     );
     assert!(result.is_ok());
 }
@@ -927,15 +944,152 @@ fn test_validate_fee_withdrawal_large_amount() {
     // 1_000_000 * 50 / 10000 = 5000
     // minimum = 5000 * 90% = 4500 (with 10% error rate)
     let result = validate_fee(
-        -1_000_000, // ext_amount (large withdrawal)
-        4500,       // provided_fee (sufficient for 0.5% rate with 10% tolerance)
-        0,          // deposit_fee_rate (0% - free deposits)
-        50,         // withdrawal_fee_rate (0.5%)
-        1000,       // error_rate (10%)
+        -1_000_000,                  // ext_amount (large withdrawal)
+        4500,                        // provided_fee (sufficient for 0.5% rate with 10% tolerance)
+        &FeeSchedule::from_rate(0),  // deposit_fee (0% - free deposits)
+        &FeeSchedule::from_rate(50), // withdrawal_fee (0.5%)
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        1000,                        // error_rate (10%)
     );
     assert!(result.is_ok());
 }
 
+// Tests for fee schedules with flat, minimum and maximum components
+#[test]
+fn test_calculate_expected_fee_flat_plus_rate() {
+    // 200 + 1_000_000 * 25 / 10000 = 200 + 2500
+    let schedule = FeeSchedule { flat_fee: 200, rate_bps: 25, min_fee: 0, max_fee: u64::MAX };
+    assert_eq!(calculate_expected_fee(1_000_000, &schedule).unwrap(), 2700);
+}
+
+#[test]
+fn test_calculate_expected_fee_clamped_to_minimum() {
+    // 1000 * 25 / 10000 = 2, raised to the 5000 lamport minimum
+    let schedule = FeeSchedule { flat_fee: 0, rate_bps: 25, min_fee: 5000, max_fee: u64::MAX };
+    assert_eq!(calculate_expected_fee(1000, &schedule).unwrap(), 5000);
+}
+
+#[test]
+fn test_calculate_expected_fee_clamped_to_maximum() {
+    // 1_000_000_000_000 * 25 / 10000 = 2_500_000_000, lowered to the 1 SOL maximum
+    let schedule = FeeSchedule { flat_fee: 0, rate_bps: 25, min_fee: 0, max_fee: 1_000_000_000 };
+    assert_eq!(calculate_expected_fee(1_000_000_000_000, &schedule).unwrap(), 1_000_000_000);
+}
+
+#[test]
+fn test_calculate_expected_fee_flat_overflow() {
+    let schedule = FeeSchedule { flat_fee: u64::MAX, rate_bps: 10000, min_fee: 0, max_fee: u64::MAX };
+    assert!(calculate_expected_fee(1, &schedule).is_err());
+}
+
+#[test]
+fn test_validate_fee_small_withdrawal_pays_flat_fee() {
+    // A tiny withdrawal must still cover the flat relayer fee
+    let withdrawal_fee = FeeSchedule { flat_fee: 5000, rate_bps: 25, min_fee: 0, max_fee: u64::MAX };
+    let result = validate_fee(
+        -100,                        // ext_amount (tiny withdrawal)
+        4000,                        // provided_fee (below 95% of 5000)
+        &FeeSchedule::from_rate(0),  // deposit_fee
+        &withdrawal_fee,             // withdrawal_fee
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
+    );
+    let err = result.unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidFeeAmount"));
+
+    let result = validate_fee(
+        -100,                        // ext_amount (tiny withdrawal)
+        4750,                        // provided_fee (exactly 95% of 5000)
+        &FeeSchedule::from_rate(0),  // deposit_fee
+        &withdrawal_fee,             // withdrawal_fee
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_validate_fee_transfer_uses_transfer_schedule() {
+    let transfer_fee = FeeSchedule { flat_fee: 1000, rate_bps: 0, min_fee: 0, max_fee: u64::MAX };
+    let result = validate_fee(
+        0,                           // ext_amount (internal transfer)
+        0,                           // provided_fee
+        &FeeSchedule::from_rate(0),  // deposit_fee
+        &FeeSchedule::from_rate(0),  // withdrawal_fee
+        &transfer_fee,               // transfer_fee
+        0,                           // error_rate (0%)
+    );
+    assert!(result.is_err());
+
+    let result = validate_fee(
+        0,                           // ext_amount (internal transfer)
+        1000,                        // provided_fee
+        &FeeSchedule::from_rate(0),  // deposit_fee
+        &FeeSchedule::from_rate(0),  // withdrawal_fee
+        &transfer_fee,               // transfer_fee
+        0,                           // error_rate (0%)
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_validate_fee_above_schedule_maximum() {
+    let withdrawal_fee = FeeSchedule { flat_fee: 0, rate_bps: 25, min_fee: 0, max_fee: 2000 };
+    let result = validate_fee(
+        -1_000_000,                  // ext_amount (withdrawal)
+        2500,                        // provided_fee (above the 2000 cap)
+        &FeeSchedule::from_rate(0),  // deposit_fee
+        &withdrawal_fee,             // withdrawal_fee
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
+    );
+    let err = result.unwrap_err();
+    assert!(format!("{:?}", err).contains("FeeExceedsCap"));
+}
+
+#[test]
+fn test_validate_fee_deposit_uses_deposit_schedule_maximum() {
+    // Deposit fee is above the withdrawal cap but below the deposit cap
+    let deposit_fee = FeeSchedule { flat_fee: 0, rate_bps: 0, min_fee: 0, max_fee: 3000 };
+    let withdrawal_fee = FeeSchedule { flat_fee: 0, rate_bps: 0, min_fee: 0, max_fee: 1000 };
+    let result = validate_fee(
+        1_000_000,                   // ext_amount (deposit)
+        2500,                        // provided_fee
+        &deposit_fee,                // deposit_fee
+        &withdrawal_fee,             // withdrawal_fee
+        &FeeSchedule::from_rate(0),  // transfer_fee
+        500,                         // error_rate (5%)
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_fee_schedule_validate() {
+    assert!(FeeSchedule::from_rate(10000).validate().is_ok());
+    assert!(FeeSchedule::from_rate(10001).validate().is_err());
+
+    let inverted = FeeSchedule { flat_fee: 0, rate_bps: 25, min_fee: 2000, max_fee: 1000 };
+    let err = inverted.validate().unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidFeeSchedule"));
+}
+
+// Tests for validate_max_fee function
+#[test]
+fn test_validate_max_fee_within_bound() {
+    assert!(validate_max_fee(2500, 3000).is_ok());
+}
+
+#[test]
+fn test_validate_max_fee_equal_to_user_maximum() {
+    assert!(validate_max_fee(2500, 2500).is_ok());
+}
+
+#[test]
+fn test_validate_max_fee_above_user_maximum() {
+    let err = validate_max_fee(2501, 2500).unwrap_err();
+    assert!(format!("{:?}", err).contains("FeeExceedsUserMaximum"));
+}
+
 #[test]
 fn test_calculate_complete_ext_data_hash_basic() {
     let recipient = Pubkey::new_unique();
@@ -943,6 +1097,7 @@ fn test_calculate_complete_ext_data_hash_basic() {
     let encrypted_output1 = b"encrypted_output_1_data";
     let encrypted_output2 = b"encrypted_output_2_data";
     let fee = 10;
+    let max_fee = 20;
     let fee_recipient = Pubkey::new_unique();  // Use the same fee_recipient for both calls
     let mint_address = Pubkey::new_unique();
     
@@ -952,6 +1107,7 @@ fn test_calculate_complete_ext_data_hash_basic() {
         encrypted_output1,
         encrypted_output2,
         fee,
+        max_fee,
         fee_recipient,
         mint_address,
     );
@@ -966,6 +1122,7 @@ fn test_calculate_complete_ext_data_hash_basic() {
         encrypted_output1,
         encrypted_output2,
         fee,
+        max_fee,
         fee_recipient,  // Use the same fee_recipient
         mint_address,
     ).unwrap();
@@ -986,6 +1143,7 @@ fn test_calculate_complete_ext_data_hash_different_inputs() {
         encrypted_output1,
         encrypted_output2,
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         recipient1, // Using recipient1 as mint_address for uniqueness
     ).unwrap();
@@ -996,6 +1154,7 @@ fn test_calculate_complete_ext_data_hash_different_inputs() {
         encrypted_output1,
         encrypted_output2,
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         recipient1, // Same mint_address
     ).unwrap();
@@ -1016,6 +1175,7 @@ fn test_calculate_complete_ext_data_hash_different_amounts() {
         encrypted_output1,
         encrypted_output2,
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1026,6 +1186,7 @@ fn test_calculate_complete_ext_data_hash_different_amounts() {
         encrypted_output1,
         encrypted_output2,
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1044,6 +1205,7 @@ fn test_calculate_complete_ext_data_hash_different_encrypted_outputs() {
         b"encrypted_output_1_data",
         b"encrypted_output_2_data",
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1054,6 +1216,7 @@ fn test_calculate_complete_ext_data_hash_different_encrypted_outputs() {
         b"different_encrypted_output_1",  // Different encrypted output
         b"encrypted_output_2_data",
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1072,6 +1235,7 @@ fn test_calculate_complete_ext_data_hash_empty_encrypted_outputs() {
         &[],  // Empty encrypted output 1
         &[],  // Empty encrypted output 2
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     );
@@ -1094,6 +1258,7 @@ fn test_calculate_complete_ext_data_hash_large_encrypted_outputs() {
         &large_encrypted_output1,
         &large_encrypted_output2,
         10,
+        10,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     );
@@ -1112,6 +1277,7 @@ fn test_calculate_complete_ext_data_hash_zero_values() {
         b"encrypted_output_1",
         b"encrypted_output_2",
         0,    // Zero fee
+        0,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     );
@@ -1130,6 +1296,7 @@ fn test_calculate_complete_ext_data_hash_negative_amount() {
         b"encrypted_output_1",
         b"encrypted_output_2",
         50,    // Fee for withdrawal
+        50,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     );
@@ -1150,6 +1317,7 @@ fn test_calculate_complete_ext_data_hash_different_fees() {
         encrypted_output1,
         encrypted_output2,
         10,   // Different fee
+        20,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1160,6 +1328,7 @@ fn test_calculate_complete_ext_data_hash_different_fees() {
         encrypted_output1,
         encrypted_output2,
         20,   // Different fee
+        20,  // max_fee
         Pubkey::new_unique(),  // fee_recipient
         mint_address,
     ).unwrap();
@@ -1167,6 +1336,37 @@ fn test_calculate_complete_ext_data_hash_different_fees() {
     assert_ne!(hash1, hash2, "Different fees should produce different hashes");
 }
 
+#[test]
+fn test_calculate_complete_ext_data_hash_different_max_fees() {
+    let recipient = Pubkey::new_unique();
+    let fee_recipient = Pubkey::new_unique();
+    let mint_address = Pubkey::new_unique();
+    
+    let hash1 = calculate_complete_ext_data_hash(
+        recipient,
+        -100,
+        b"encrypted_output_1_data",
+        b"encrypted_output_2_data",
+        10,
+        10,   // Different max_fee
+        fee_recipient,
+        mint_address,
+    ).unwrap();
+    
+    let hash2 = calculate_complete_ext_data_hash(
+        recipient,
+        -100,
+        b"encrypted_output_1_data",
+        b"encrypted_output_2_data",
+        10,
+        50,   // Different max_fee
+        fee_recipient,
+        mint_address,
+    ).unwrap();
+    
+    assert_ne!(hash1, hash2, "Different max fees should produce different hashes");
+}
+
 #[test]
 fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
     // This test ensures our hash calculation is consistent with Borsh serialization
@@ -1178,6 +1378,7 @@ fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
     let encrypted_output1 = b"test_encrypted_1";
     let encrypted_output2 = b"test_encrypted_2";
     let fee = 25u64;
+    let max_fee = 30u64;
     let fee_recipient = Pubkey::new_unique();  // Use the same fee_recipient for both calculations
     let mint_address = Pubkey::new_unique();
     
@@ -1188,6 +1389,7 @@ fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
         encrypted_output1,
         encrypted_output2,
         fee,
+        max_fee,
         fee_recipient,
         mint_address,
     ).unwrap();
@@ -1200,6 +1402,7 @@ fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
         pub encrypted_output1: Vec<u8>,
         pub encrypted_output2: Vec<u8>,
         pub fee: u64,
+        pub max_fee: u64,
         pub fee_recipient: Pubkey,
         pub mint_address: Pubkey,
     }
@@ -1210,6 +1413,7 @@ fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
         encrypted_output1: encrypted_output1.to_vec(),
         encrypted_output2: encrypted_output2.to_vec(),
         fee,
+        max_fee,
         fee_recipient,  // Use the same fee_recipient
         mint_address,
     };
@@ -1219,4 +1423,134 @@ fn test_calculate_complete_ext_data_hash_consistency_with_borsh() {
     let manual_hash = hash(&serialized).to_bytes();
     
     assert_eq!(our_hash, manual_hash, "Our function should match manual Borsh serialization");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_validate_initializer_uses_upgrade_authority() {
+    let upgrade_authority = Pubkey::new_unique();
+    let attacker = Pubkey::new_unique();
+
+    assert!(validate_initializer(&upgrade_authority, None, Some(upgrade_authority)).is_ok());
+
+    let err = validate_initializer(&attacker, None, Some(upgrade_authority)).unwrap_err();
+    assert!(format!("{:?}", err).contains("Unauthorized"));
+
+    // Immutable program without an override cannot be initialized
+    let err = validate_initializer(&upgrade_authority, None, None).unwrap_err();
+    assert!(format!("{:?}", err).contains("Unauthorized"));
+}
+
+#[test]
+fn test_validate_initializer_admin_key_overrides_upgrade_authority() {
+    let admin_key = Pubkey::new_unique();
+    let upgrade_authority = Pubkey::new_unique();
+
+    assert!(validate_initializer(&admin_key, Some(admin_key), Some(upgrade_authority)).is_ok());
+    assert!(validate_initializer(&admin_key, Some(admin_key), None).is_ok());
+
+    let err = validate_initializer(&upgrade_authority, Some(admin_key), Some(upgrade_authority)).unwrap_err();
+    assert!(format!("{:?}", err).contains("Unauthorized"));
+}
+
+#[test]
+fn test_calculate_fee_quote_selects_schedule_and_applies_margin() {
+    let deposit_fee = FeeSchedule::from_rate(0);
+    let withdrawal_fee = FeeSchedule { flat_fee: 5000, rate_bps: 25, min_fee: 0, max_fee: 1_000_000 };
+    let transfer_fee = FeeSchedule { flat_fee: 2000, rate_bps: 0, min_fee: 0, max_fee: 10_000 };
+
+    let quote = calculate_fee_quote(1_000_000, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).unwrap();
+    assert_eq!(quote.expected_fee, 0);
+    assert_eq!(quote.min_acceptable_fee, 0);
+    assert_eq!(quote.max_fee, u64::MAX);
+
+    // 5000 + 1_000_000 * 25 / 10000 = 7500, minus 5% margin = 7125
+    let quote = calculate_fee_quote(-1_000_000, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).unwrap();
+    assert_eq!(quote.expected_fee, 7500);
+    assert_eq!(quote.min_acceptable_fee, 7125);
+    assert_eq!(quote.max_fee, 1_000_000);
+
+    let quote = calculate_fee_quote(0, &deposit_fee, &withdrawal_fee, &transfer_fee, 0).unwrap();
+    assert_eq!(quote.expected_fee, 2000);
+    assert_eq!(quote.min_acceptable_fee, 2000);
+    assert_eq!(quote.max_fee, 10_000);
+}
+
+#[test]
+fn test_calculate_fee_quote_bounds_match_validate_fee() {
+    let deposit_fee = FeeSchedule::from_rate(0);
+    let withdrawal_fee = FeeSchedule { flat_fee: 5000, rate_bps: 25, min_fee: 0, max_fee: 1_000_000 };
+    let transfer_fee = FeeSchedule::from_rate(0);
+    let quote = calculate_fee_quote(-1_000_000, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).unwrap();
+
+    assert!(validate_fee(-1_000_000, quote.min_acceptable_fee, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).is_ok());
+    assert!(validate_fee(-1_000_000, quote.min_acceptable_fee - 1, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).is_err());
+    assert!(validate_fee(-1_000_000, quote.max_fee, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).is_ok());
+    assert!(validate_fee(-1_000_000, quote.max_fee + 1, &deposit_fee, &withdrawal_fee, &transfer_fee, 500).is_err());
+}
+
+#[test]
+fn test_validate_payouts() {
+    let payouts = vec![
+        Payout { recipient: Pubkey::new_unique(), amount: 300 },
+        Payout { recipient: Pubkey::new_unique(), amount: 700 },
+    ];
+    assert_eq!(validate_payouts(&payouts, -1000).unwrap(), 1000);
+
+    let err = validate_payouts(&payouts, -999).unwrap_err();
+    assert!(format!("{:?}", err).contains("PayoutsAmountMismatch"));
+
+    // Only withdrawals can be split
+    let err = validate_payouts(&payouts, 1000).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPayouts"));
+}
+
+#[test]
+fn test_validate_payouts_rejects_empty_zero_and_oversized_lists() {
+    let err = validate_payouts(&[], -1000).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPayouts"));
+
+    let payouts = vec![
+        Payout { recipient: Pubkey::new_unique(), amount: 1000 },
+        Payout { recipient: Pubkey::new_unique(), amount: 0 },
+    ];
+    let err = validate_payouts(&payouts, -1000).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPayouts"));
+
+    let payouts: Vec<Payout> = (0..MAX_PAYOUTS + 1)
+        .map(|_| Payout { recipient: Pubkey::new_unique(), amount: 1 })
+        .collect();
+    let err = validate_payouts(&payouts, -(MAX_PAYOUTS as i64 + 1)).unwrap_err();
+    assert!(format!("{:?}", err).contains("InvalidPayouts"));
+}
+
+#[test]
+fn test_validate_payouts_overflow() {
+    let payouts = vec![
+        Payout { recipient: Pubkey::new_unique(), amount: u64::MAX },
+        Payout { recipient: Pubkey::new_unique(), amount: 1 },
+    ];
+    let err = validate_payouts(&payouts, -1000).unwrap_err();
+    assert!(format!("{:?}", err).contains("ArithmeticOverflow"));
+}
+
+#[test]
+fn test_payouts_commitment_binds_recipients_amounts_and_order() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let payouts = vec![
+        Payout { recipient: alice, amount: 300 },
+        Payout { recipient: bob, amount: 700 },
+    ];
+    let commitment = calculate_payouts_commitment(&payouts).unwrap();
+
+    let different_amounts = vec![
+        Payout { recipient: alice, amount: 700 },
+        Payout { recipient: bob, amount: 300 },
+    ];
+    let reordered = vec![payouts[1].clone(), payouts[0].clone()];
+
+    assert_ne!(commitment, calculate_payouts_commitment(&different_amounts).unwrap());
+    assert_ne!(commitment, calculate_payouts_commitment(&reordered).unwrap());
+    // A single payout must not collide with withdrawing straight to that recipient
+    assert_ne!(alice, calculate_payouts_commitment(&payouts[..1]).unwrap());
+}
@@ -0,0 +1,107 @@
+use anchor_lang::prelude::*;
+use zkcash::{velocity::{record_deposit, VELOCITY_BUCKETS}, DepositorVelocity, GlobalConfig};
+
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+const WINDOW_SLOTS: u64 = 1_000;
+const MAX_PER_WINDOW: u64 = 10 * LAMPORTS_PER_SOL;
+
+fn create_test_global_config(max_deposit_per_window: u64) -> GlobalConfig {
+    GlobalConfig {
+        velocity_window_slots: WINDOW_SLOTS,
+        max_deposit_per_window,
+        ..super::create_test_global_config()
+    }
+}
+
+fn create_test_velocity() -> DepositorVelocity {
+    DepositorVelocity {
+        depositor: Pubkey::default(),
+        buckets: [0; VELOCITY_BUCKETS],
+        last_bucket: 0,
+        bucket_slots: 0,
+        bump: 0,
+    }
+}
+
+#[test]
+fn test_disabled_limit_needs_no_account() {
+    let global_config = create_test_global_config(0);
+    assert!(record_deposit(None, None, &global_config, Pubkey::new_unique(), u64::MAX, 1).is_ok());
+}
+
+#[test]
+fn test_enabled_limit_requires_account() {
+    let global_config = create_test_global_config(MAX_PER_WINDOW);
+    let err = record_deposit(None, None, &global_config, Pubkey::new_unique(), 1, 1).unwrap_err();
+    assert!(format!("{:?}", err).contains("DepositorVelocityRequired"));
+}
+
+#[test]
+fn test_first_deposit_initializes_account() {
+    let global_config = create_test_global_config(MAX_PER_WINDOW);
+    let depositor = Pubkey::new_unique();
+    let mut velocity = create_test_velocity();
+
+    record_deposit(Some(&mut velocity), Some(254), &global_config, depositor, LAMPORTS_PER_SOL, 5_000).unwrap();
+
+    assert_eq!(velocity.depositor, depositor);
+    assert_eq!(velocity.bump, 254);
+    assert_eq!(velocity.bucket_slots, WINDOW_SLOTS / VELOCITY_BUCKETS as u64);
+    assert_eq!(velocity.buckets.iter().sum::<u64>(), LAMPORTS_PER_SOL);
+}
+
+#[test]
+fn test_cap_applies_across_deposits_in_window() {
+    let mut velocity = create_test_velocity();
+
+    velocity.record(6 * LAMPORTS_PER_SOL, 10_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+    velocity.record(4 * LAMPORTS_PER_SOL, 10_500, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+
+    let err = velocity.record(1, 10_900, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap_err();
+    assert!(format!("{:?}", err).contains("VelocityLimitExceeded"));
+}
+
+#[test]
+fn test_single_deposit_over_cap_fails() {
+    let mut velocity = create_test_velocity();
+    let err = velocity.record(MAX_PER_WINDOW + 1, 10_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap_err();
+    assert!(format!("{:?}", err).contains("VelocityLimitExceeded"));
+}
+
+#[test]
+fn test_window_rolls_forward_bucket_by_bucket() {
+    let mut velocity = create_test_velocity();
+
+    // Buckets are 250 slots: 10_000 and 10_250 fall in consecutive buckets
+    velocity.record(6 * LAMPORTS_PER_SOL, 10_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+    velocity.record(4 * LAMPORTS_PER_SOL, 10_250, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+
+    // The first bucket has left the window, the second has not
+    velocity.record(6 * LAMPORTS_PER_SOL, 11_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+    let err = velocity.record(1, 11_100, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap_err();
+    assert!(format!("{:?}", err).contains("VelocityLimitExceeded"));
+
+    // Once the second bucket leaves too, its volume is available again
+    velocity.record(4 * LAMPORTS_PER_SOL, 11_250, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+}
+
+#[test]
+fn test_idle_depositor_starts_with_empty_window() {
+    let mut velocity = create_test_velocity();
+
+    velocity.record(MAX_PER_WINDOW, 10_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+    velocity.record(MAX_PER_WINDOW, 50_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+
+    assert_eq!(velocity.buckets.iter().sum::<u64>(), MAX_PER_WINDOW);
+}
+
+#[test]
+fn test_changed_window_resets_buckets() {
+    let mut velocity = create_test_velocity();
+
+    velocity.record(MAX_PER_WINDOW, 10_000, WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+    velocity.record(MAX_PER_WINDOW, 10_000, 2 * WINDOW_SLOTS, MAX_PER_WINDOW).unwrap();
+
+    assert_eq!(velocity.bucket_slots, 2 * WINDOW_SLOTS / VELOCITY_BUCKETS as u64);
+    assert_eq!(velocity.buckets.iter().sum::<u64>(), MAX_PER_WINDOW);
+}
@@ -205,4 +205,120 @@ export class MerkleTree {
     instance.zeroElement = instance._zeros[0];
     return instance;
   }
-}
\ No newline at end of file
+}
+
+/**
+ * Keeps the Merkle paths of a wallet's own notes up to date as commitments are appended, without
+ * storing the whole tree. Feed it every appended commitment in index order (e.g. from the
+ * program's CommitmentData events).
+ *
+ * Like the program, it only keeps the filled subtrees, which are enough to compute the path of
+ * each new leaf. A leaf appended after a tracked one changes exactly one sibling of the tracked
+ * path: the one at the highest level where the two leaf indices differ, which becomes the new
+ * leaf's ancestor at that level.
+ */
+export class MerklePathTracker {
+  levels: number;
+  nextIndex: number;
+  _zeros: string[];
+  _filledSubtrees: string[];
+  _root: string;
+  _paths: Map<number, string[]>;
+  _lightWasm: LightWasm;
+
+  /**
+   * @param levels Number of levels in the tree
+   * @param lightWasm Poseidon hasher
+   * @param nextIndex Number of leaves already in the tree
+   * @param filledSubtrees The tree account's subtrees as decimal strings, required if nextIndex > 0
+   * @param root The tree account's root as a decimal string, required if nextIndex > 0
+   */
+  constructor(
+    levels: number,
+    lightWasm: LightWasm,
+    nextIndex = 0,
+    filledSubtrees: string[] = [],
+    root?: string,
+  ) {
+    this.levels = levels;
+    this.nextIndex = nextIndex;
+    this._lightWasm = lightWasm;
+    this._paths = new Map();
+    this._zeros = [DEFAULT_ZERO.toString()];
+    for (let i = 1; i <= levels; i++) {
+      this._zeros[i] = lightWasm.poseidonHashString([this._zeros[i - 1], this._zeros[i - 1]]);
+    }
+    if (nextIndex > 0 && (filledSubtrees.length !== levels || root === undefined)) {
+      throw new Error("The subtrees and root of a non-empty tree are required");
+    }
+    this._filledSubtrees = nextIndex > 0 ? filledSubtrees.slice() : this._zeros.slice(0, levels);
+    this._root = root ?? this._zeros[levels];
+  }
+
+  root() {
+    return this._root;
+  }
+
+  /**
+   * Append the next leaf of the tree and fast-forward the tracked paths past it
+   * @param leaf The appended commitment
+   * @param track Whether to keep the path of this leaf up to date from now on
+   * @returns The leaf's index
+   */
+  append(leaf: string, track = false) {
+    if (this.nextIndex >= 2 ** this.levels) {
+      throw new Error("Tree is full");
+    }
+    const leafIndex = this.nextIndex;
+    const nodes: string[] = [];
+    const pathElements: string[] = [];
+    let node = leaf;
+    let index = leafIndex;
+    for (let level = 0; level < this.levels; level++) {
+      nodes[level] = node;
+      if (index % 2 === 0) {
+        this._filledSubtrees[level] = node;
+        pathElements[level] = this._zeros[level];
+        node = this._lightWasm.poseidonHashString([node, this._zeros[level]]);
+      } else {
+        pathElements[level] = this._filledSubtrees[level];
+        node = this._lightWasm.poseidonHashString([this._filledSubtrees[level], node]);
+      }
+      index >>= 1;
+    }
+    this._root = node;
+
+    for (const [trackedIndex, trackedPath] of this._paths) {
+      const criticalLevel = 31 - Math.clz32(trackedIndex ^ leafIndex);
+      trackedPath[criticalLevel] = nodes[criticalLevel];
+    }
+    if (track) {
+      this._paths.set(leafIndex, pathElements);
+    }
+    this.nextIndex++;
+    return leafIndex;
+  }
+
+  /**
+   * Current merkle path of a tracked leaf, in the format of MerkleTree.path
+   * @param index Leaf index returned by append
+   */
+  path(index: number) {
+    const pathElements = this._paths.get(index);
+    if (!pathElements) {
+      throw new Error("Leaf is not tracked: " + index);
+    }
+    return {
+      pathElements: pathElements.slice(),
+      pathIndices: pathElements.map((_, level) => (index >> level) % 2),
+    };
+  }
+
+  /**
+   * Stop tracking a leaf, e.g. once its note is spent
+   * @param index Leaf index returned by append
+   */
+  untrack(index: number) {
+    this._paths.delete(index);
+  }
+}
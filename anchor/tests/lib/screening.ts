@@ -0,0 +1,25 @@
+import { PublicKey } from '@solana/web3.js';
+
+/**
+ * Local stand-in for the sanctions screening oracle that feeds the on-chain deny list.
+ * Tests flag addresses here and push them on-chain with add_to_denylist.
+ */
+export class LocalScreeningOracle {
+  private readonly flagged = new Map<string, PublicKey>();
+
+  flag(address: PublicKey) {
+    this.flagged.set(address.toBase58(), address);
+  }
+
+  clear(address: PublicKey) {
+    this.flagged.delete(address.toBase58());
+  }
+
+  isFlagged(address: PublicKey): boolean {
+    return this.flagged.has(address.toBase58());
+  }
+
+  flaggedAddresses(): PublicKey[] {
+    return [...this.flagged.values()];
+  }
+}
@@ -138,12 +138,18 @@ export function getTestProtocolAddresses(
     programId
   );
 
+  const [denyList] = PublicKey.findProgramAddressSync(
+    [Buffer.from('deny_list')],
+    programId
+  );
+
   return [
     // Core program accounts (constant)
     programId,
     treeAccount,
     treeTokenAccount,
     globalConfigAccount,
+    denyList,
     authority,
     feeRecipient,
     
@@ -30,7 +30,8 @@ export function mockEncrypt(value: Utxo): string {
 
 /**
  * Calculates the hash of ext data using Borsh serialization
- * @param extData External data object containing recipient, amount, encrypted outputs, fee, fee recipient, and mint address
+ * @param extData External data object containing recipient, amount, encrypted outputs, fee, max fee, fee recipient, and mint address
+ *                (maxFee defaults to fee when omitted)
  * @returns The hash as a Uint8Array (32 bytes)
  */
 export function getExtDataHash(extData: {
@@ -39,6 +40,7 @@ export function getExtDataHash(extData: {
   encryptedOutput1?: string | Uint8Array;  // Optional for Account Data Separation
   encryptedOutput2?: string | Uint8Array;  // Optional for Account Data Separation
   fee: string | number | BN;
+  maxFee?: string | number | BN;
   feeRecipient: string | PublicKey;
   mintAddress: string | PublicKey;
 }): Uint8Array {
@@ -58,6 +60,7 @@ export function getExtDataHash(extData: {
   // Convert to BN for proper i64/u64 handling
   const extAmount = new BN(extData.extAmount.toString());
   const fee = new BN(extData.fee.toString());
+  const maxFee = new BN((extData.maxFee ?? extData.fee).toString());
   
   // Handle encrypted outputs - they might not be present in Account Data Separation approach
   const encryptedOutput1 = extData.encryptedOutput1 
@@ -75,6 +78,7 @@ export function getExtDataHash(extData: {
       encryptedOutput1: { array: { type: 'u8' } },
       encryptedOutput2: { array: { type: 'u8' } },
       fee: 'u64',
+      maxFee: 'u64',
       feeRecipient: { array: { type: 'u8', len: 32 } },
       mintAddress: { array: { type: 'u8', len: 32 } },
     }
@@ -86,6 +90,7 @@ export function getExtDataHash(extData: {
     encryptedOutput1: encryptedOutput1,
     encryptedOutput2: encryptedOutput2,
     fee: fee,  // BN instance - Borsh handles it correctly with u64 type
+    maxFee: maxFee,
     feeRecipient: feeRecipient.toBytes(),
     mintAddress: mintAddress.toBytes(),
   };
@@ -98,3 +103,33 @@ export function getExtDataHash(extData: {
   // Convert from hex string to Uint8Array
   return Buffer.from(hashHex.slice(2), 'hex');
 } 
+
+/**
+ * Calculates the payouts commitment of a multi-recipient withdrawal, used as the ext data recipient
+ * @param payouts List of recipients and the lamports each of them receives
+ * @returns The commitment as a PublicKey
+ */
+export function getPayoutsCommitment(payouts: {
+  recipient: PublicKey;
+  amount: string | number | BN;
+}[]): PublicKey {
+  const schema = {
+    array: {
+      type: {
+        struct: {
+          recipient: { array: { type: 'u8', len: 32 } },
+          amount: 'u64',
+        }
+      }
+    }
+  };
+
+  const serializedPayouts = borsh.serialize(schema, payouts.map((payout) => ({
+    recipient: payout.recipient.toBytes(),
+    amount: new BN(payout.amount.toString()),
+  })));
+
+  // Domain separated from a plain recipient, matching utils::calculate_payouts_commitment
+  const hashHex = sha256(Buffer.concat([Buffer.from("multi_recipient"), Buffer.from(serializedPayouts)]));
+  return new PublicKey(Buffer.from(hashHex.slice(2), 'hex'));
+}
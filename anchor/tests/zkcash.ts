@@ -3,7 +3,7 @@ import { Program, EventParser, BorshCoder } from "@coral-xyz/anchor";
 import { Zkcash } from "../target/types/zkcash";
 import { LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
 import { expect } from "chai";
-import { getExtDataHash } from "./lib/utils";
+import { getExtDataHash, getPayoutsCommitment } from "./lib/utils";
 import { DEFAULT_HEIGHT, FIELD_SIZE, ROOT_HISTORY_SIZE, ZERO_BYTES, DEPOSIT_FEE_RATE, WITHDRAW_FEE_RATE, FEE_RECIPIENT_ACCOUNT } from "./lib/constants";
 
 import * as crypto from "crypto";
@@ -24,6 +24,16 @@ function calculateFee(amount: number, feeRate: number): number {
   return Math.floor((amount * feeRate) / 10000);
 }
 
+// Helper function to build a purely proportional fee schedule (no flat fee, minimum or cap)
+function feeScheduleFromRate(rateBps: number) {
+  return {
+    flatFee: new anchor.BN(0),
+    rateBps,
+    minFee: new anchor.BN(0),
+    maxFee: new anchor.BN("18446744073709551615"), // u64::MAX
+  };
+}
+
 // Helper function to calculate deposit fee
 function calculateDepositFee(amount: number): number {
   return calculateFee(amount, DEPOSIT_FEE_RATE);
@@ -41,8 +51,9 @@ export function bnToBytes(bn: anchor.BN): number[] {
   ).reverse() as number[];
 }
 
-import { MerkleTree } from "./lib/merkle_tree";
+import { MerkleTree, MerklePathTracker } from "./lib/merkle_tree";
 import { createGlobalTestALT, getTestProtocolAddresses, createVersionedTransactionWithALT, sendAndConfirmVersionedTransaction } from "./lib/test_alt";
+import { LocalScreeningOracle } from "./lib/screening";
 
 // Find nullifier PDAs for the given proof
 function findNullifierPDAs(program: anchor.Program<any>, proof: any) {
@@ -74,6 +85,16 @@ function findCommitmentPDAs(program: anchor.Program<any>, proof: any) {
   return { commitment0PDA, commitment1PDA };
 }
 
+// Find the deposit origin PDA recording the depositor of the proof's outputs
+function findDepositOriginPDA(program: anchor.Program<any>, proof: any) {
+  const [depositOriginPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("deposit_origin"), Buffer.from(proof.outputCommitments[0])],
+    program.programId
+  );
+
+  return depositOriginPDA;
+}
+
 // Find cross-check nullifier PDAs for the given proof
 function findCrossCheckNullifierPDAs(program: anchor.Program<any>, proof: any) {
   const [nullifier2PDA] = PublicKey.findProgramAddressSync(
@@ -89,11 +110,22 @@ function findCrossCheckNullifierPDAs(program: anchor.Program<any>, proof: any) {
   return { nullifier2PDA, nullifier3PDA };
 }
 
+// Find the PendingConfigChange PDA for the given change id
+function findPendingConfigChangePDA(program: anchor.Program<any>, id: anchor.BN) {
+  const [pendingConfigChangePDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("pending_config_change"), id.toArrayLike(Buffer, "le", 8)],
+    program.programId
+  );
+
+  return pendingConfigChangePDA;
+}
+
 // Helper function to create ExtDataMinified from ExtData
 function createExtDataMinified(extData: any) {
   return {
     extAmount: extData.extAmount,
-    fee: extData.fee
+    fee: extData.fee,
+    maxFee: extData.maxFee ?? extData.fee
   };
 }
 
@@ -119,11 +151,13 @@ describe("zkcash", () => {
   let treeTokenAccountPDA: PublicKey;
   let treeTokenBump: number;
   let globalConfigPDA: PublicKey;
+  let denyListPDA: PublicKey;
   let globalMerkleTree: MerkleTree;
 
   // --- Funding a wallet to use for paying transaction fees ---
   before(async () => {
-    authority = anchor.web3.Keypair.generate();
+    // initialize is restricted to the program's upgrade authority, which is the provider wallet
+    authority = (provider.wallet as anchor.Wallet).payer;
     // Generate a funding account to pay for transactions
     fundingAccount = anchor.web3.Keypair.generate();
     lightWasm = await WasmFactory.getInstance();
@@ -166,7 +200,7 @@ describe("zkcash", () => {
 
     // Calculate the PDA for the tree account with the new authority
     const [treePda, pdaBump] = await PublicKey.findProgramAddressSync(
-      [Buffer.from("merkle_tree")],
+      [Buffer.from("merkle_tree")], // the first tree generation keeps the original address
       program.programId
     );
     treeAccountPDA = treePda;
@@ -186,14 +220,48 @@ describe("zkcash", () => {
       program.programId
     );
     globalConfigPDA = globalConfigPda;
+
+    [denyListPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("deny_list")],
+      program.programId
+    );
+
+    const [programDataPDA] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+
+    // A key other than the upgrade authority cannot front-run initialization
+    try {
+      await program.methods
+        .initialize([])
+        .accounts({
+          treeAccount: treeAccountPDA,
+          treeTokenAccount: treeTokenAccountPDA,
+          globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          authority: fundingAccount.publicKey,
+          zkcashProgram: program.programId,
+          programData: programDataPDA,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .signers([fundingAccount])
+        .rpc();
+      expect.fail("Initialization by a non-upgrade-authority should have failed");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
         
     await program.methods
-      .initialize()
+      .initialize([])
       .accounts({
         treeAccount: treeAccountPDA,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
         authority: authority.publicKey,
+        zkcashProgram: program.programId,
+        programData: programDataPDA,
         systemProgram: anchor.web3.SystemProgram.programId
       })
       .signers([authority]) // Only authority is a signer
@@ -215,6 +283,10 @@ describe("zkcash", () => {
     expect(merkleTreeAccount.rootIndex.toString()).to.equal("0");
     expect(merkleTreeAccount.rootHistory.length).to.equal(ROOT_HISTORY_SIZE);
     expect(merkleTreeAccount.root).to.deep.equal(ZERO_BYTES[DEFAULT_HEIGHT]);
+
+    // Initialized without denominations, so the pool takes variable amounts
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(globalConfig.denominationCount).to.equal(0);
   });
 
   // Reset program state before each test
@@ -366,7 +438,7 @@ describe("zkcash", () => {
 
     // Execute deposit transaction
     const depositTx = await program.methods
-      .transact(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2)
+      .transact(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: depositNullifiers.nullifier0PDA,
@@ -379,6 +451,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, depositProofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -505,7 +582,7 @@ describe("zkcash", () => {
 
     // Execute first withdrawal
     const firstTx = await program.methods
-      .transact(firstProofToSubmit, createExtDataMinified(firstExtData), firstExtData.encryptedOutput1, firstExtData.encryptedOutput2)
+      .transact(firstProofToSubmit, createExtDataMinified(firstExtData), firstExtData.encryptedOutput1, firstExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: firstNullifiers.nullifier0PDA,
@@ -518,6 +595,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -655,7 +737,7 @@ describe("zkcash", () => {
     try {
       // Execute second withdrawal - this SHOULD fail due to cross-check accounts!
       const secondTx = await program.methods
-        .transact(secondProofToSubmit, createExtDataMinified(secondExtData), secondExtData.encryptedOutput1, secondExtData.encryptedOutput2)
+        .transact(secondProofToSubmit, createExtDataMinified(secondExtData), secondExtData.encryptedOutput1, secondExtData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: secondNullifiers.nullifier0PDA,
@@ -668,6 +750,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: null,
+          pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -842,7 +929,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -855,6 +942,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -1065,7 +1157,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -1078,6 +1170,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -1304,7 +1401,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -1317,6 +1414,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -1530,7 +1632,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -1543,6 +1645,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -1772,7 +1879,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -1785,6 +1892,11 @@ describe("zkcash", () => {
         feeRecipientAccount: pdaFeeRecipient, // Use PDA fee recipient to match ExtData
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -1998,7 +2110,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -2011,6 +2123,11 @@ describe("zkcash", () => {
         feeRecipientAccount: pdaFeeRecipient, // Use PDA as fee recipient in transaction accounts
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -2219,7 +2336,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -2232,6 +2349,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -2441,7 +2563,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -2454,6 +2576,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -2656,7 +2783,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -2669,6 +2796,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -2880,7 +3012,7 @@ describe("zkcash", () => {
     // Execute the withdrawal transaction - this should fail due to recipient mismatch
     try {
       const withdrawTx = await program.methods
-        .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+        .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -2893,6 +3025,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: null,
+          pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -3071,7 +3208,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -3084,6 +3221,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -3293,7 +3435,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -3306,6 +3448,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -3509,7 +3656,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -3522,6 +3669,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -3731,7 +3883,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -3744,6 +3896,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -3947,7 +4104,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -3960,6 +4117,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -4169,7 +4331,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -4182,6 +4344,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -4387,7 +4554,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -4400,6 +4567,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey, // Use random user as signer
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -4609,7 +4781,7 @@ describe("zkcash", () => {
 
     // Execute the withdrawal transaction
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -4622,6 +4794,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -4744,7 +4921,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because the hash doesn't match
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -4757,6 +4934,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: null,
+          pendingWithdrawal: null,
           signer: randomUser.publicKey, // Use random user as signer
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -4844,7 +5026,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because the root is unknown
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -4857,6 +5039,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: null,
+          pendingWithdrawal: null,
           signer: randomUser.publicKey, // Use random user as signer
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -4948,7 +5135,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because the root is unknown
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -4961,6 +5148,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: null,
+          pendingWithdrawal: null,
           signer: randomUser.publicKey, // Use random user as signer
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -5050,7 +5242,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because of invalid mint address
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -5063,6 +5255,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: findDepositOriginPDA(program, proof),
+          pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -5153,7 +5350,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because of exceeding deposit limit
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -5166,6 +5363,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
           globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: findDepositOriginPDA(program, proof),
+          pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -5226,6 +5428,7 @@ describe("zkcash", () => {
       .updateDepositLimit(newLimit)
       .accounts({
         treeAccount: treeAccountPDA,
+        globalConfig: globalConfigPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -5288,6 +5491,7 @@ describe("zkcash", () => {
         .updateDepositLimit(newLimit)
         .accounts({
           treeAccount: treeAccountPDA,
+          globalConfig: globalConfigPDA,
           authority: nonAuthority.publicKey,
         })
         .signers([nonAuthority])
@@ -5329,6 +5533,7 @@ describe("zkcash", () => {
       .updateDepositLimit(newLimit)
       .accounts({
         treeAccount: treeAccountPDA,
+        globalConfig: globalConfigPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -5443,7 +5648,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -5456,6 +5661,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -5591,7 +5801,7 @@ describe("zkcash", () => {
 
     // Execute deposit
     const depositTx = await program.methods
-      .transact(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2)
+      .transact(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: depositNullifiers.nullifier0PDA,
@@ -5604,6 +5814,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, depositProofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -5730,7 +5945,7 @@ describe("zkcash", () => {
 
     // Execute withdrawal - should succeed regardless of deposit limit
     const withdrawTx = await program.methods
-      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2)
+      .transact(withdrawProofToSubmit, createExtDataMinified(withdrawExtData), withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -5743,6 +5958,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -5884,7 +6104,7 @@ describe("zkcash", () => {
     const depositLookupTableAddress = await createGlobalTestALT(provider.connection, authority, depositTestProtocolAddresses);
     
     const depositTx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -5897,6 +6117,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -6050,7 +6275,7 @@ describe("zkcash", () => {
       const withdrawLookupTableAddress = await createGlobalTestALT(provider.connection, authority, withdrawTestProtocolAddresses);
       
       const withdrawTx = await program.methods
-        .transact(withdrawProofToSubmit, createExtDataMinified(validWithdrawExtData), validWithdrawExtData.encryptedOutput1, validWithdrawExtData.encryptedOutput2)
+        .transact(withdrawProofToSubmit, createExtDataMinified(validWithdrawExtData), validWithdrawExtData.encryptedOutput1, validWithdrawExtData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: withdrawNullifiers.nullifier0PDA,
@@ -6063,6 +6288,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: null,
+        pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -6147,7 +6377,7 @@ describe("zkcash", () => {
       
       // Execute the transaction - this should fail because of invalid mint address
       const tx = await program.methods
-        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
         .accounts({
           treeAccount: treeAccountPDA,
           nullifier0: nullifier0PDA,
@@ -6160,6 +6390,11 @@ describe("zkcash", () => {
           feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
           treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proof),
+        pendingWithdrawal: null,
           signer: randomUser.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId
         })
@@ -6218,12 +6453,14 @@ describe("zkcash", () => {
     
     const tx = await program.methods
       .updateGlobalConfig(
-        null, // deposit_fee_rate
-        null, // withdrawal_fee_rate  
+        null, // deposit_fee
+        null, // withdrawal_fee
+        null, // transfer_fee
         null  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6257,12 +6494,14 @@ describe("zkcash", () => {
     
     await program.methods
       .updateGlobalConfig(
-        newDepositFeeRate, // deposit_fee_rate
-        null, // withdrawal_fee_rate
+        feeScheduleFromRate(newDepositFeeRate), // deposit_fee
+        null, // withdrawal_fee
+        null, // transfer_fee
         null  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6271,7 +6510,7 @@ describe("zkcash", () => {
 
     // Verify the deposit fee rate was updated
     const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
-    expect(globalConfig.depositFeeRate).to.equal(newDepositFeeRate);
+    expect(globalConfig.depositFee.rateBps).to.equal(newDepositFeeRate);
   });
 
   it("Authority can update global config - withdrawal fee rate", async () => {
@@ -6283,12 +6522,14 @@ describe("zkcash", () => {
     
     await program.methods
       .updateGlobalConfig(
-        null, // deposit_fee_rate
-        newWithdrawalFeeRate, // withdrawal_fee_rate
+        null, // deposit_fee
+        feeScheduleFromRate(newWithdrawalFeeRate), // withdrawal_fee
+        null, // transfer_fee
         null  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6297,7 +6538,7 @@ describe("zkcash", () => {
 
     // Verify the withdrawal fee rate was updated
     const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
-    expect(globalConfig.withdrawalFeeRate).to.equal(newWithdrawalFeeRate);
+    expect(globalConfig.withdrawalFee.rateBps).to.equal(newWithdrawalFeeRate);
   });
 
   it("Authority can update global config - fee error margin", async () => {
@@ -6309,12 +6550,14 @@ describe("zkcash", () => {
     
     await program.methods
       .updateGlobalConfig(
-        null, // deposit_fee_rate
-        null, // withdrawal_fee_rate
+        null, // deposit_fee
+        null, // withdrawal_fee
+        null, // transfer_fee
         newFeeErrorMargin  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6326,6 +6569,98 @@ describe("zkcash", () => {
     expect(globalConfig.feeErrorMargin).to.equal(newFeeErrorMargin);
   });
 
+  it("Authority can update fee schedules with flat, minimum and maximum components", async () => {
+    const newWithdrawalFee = {
+      flatFee: new anchor.BN(5000),
+      rateBps: 25,
+      minFee: new anchor.BN(10_000),
+      maxFee: new anchor.BN(5 * LAMPORTS_PER_SOL),
+    };
+    const newTransferFee = {
+      flatFee: new anchor.BN(5000),
+      rateBps: 0,
+      minFee: new anchor.BN(0),
+      maxFee: new anchor.BN(5000),
+    };
+    const initialConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    
+    await program.methods
+      .updateGlobalConfig(
+        null, // deposit_fee
+        newWithdrawalFee, // withdrawal_fee
+        newTransferFee, // transfer_fee
+        null  // fee_error_margin
+      )
+      .accounts({
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        authority: authority.publicKey,
+      })
+      .signers([authority])
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(globalConfig.withdrawalFee.flatFee.toString()).to.equal("5000");
+    expect(globalConfig.withdrawalFee.rateBps).to.equal(25);
+    expect(globalConfig.withdrawalFee.minFee.toString()).to.equal("10000");
+    expect(globalConfig.withdrawalFee.maxFee.toString()).to.equal((5 * LAMPORTS_PER_SOL).toString());
+    expect(globalConfig.transferFee.maxFee.toString()).to.equal("5000");
+
+    // Restore the previous schedules so later tests are unaffected
+    await program.methods
+      .updateGlobalConfig(null, initialConfig.withdrawalFee, initialConfig.transferFee, null)
+      .accounts({
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        authority: authority.publicKey,
+      })
+      .signers([authority])
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+  });
+
+  it("Fails to set a fee schedule whose minimum exceeds its maximum", async () => {
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+
+    try {
+      await program.methods
+        .updateGlobalConfig(
+          null, // deposit_fee
+          {
+            flatFee: new anchor.BN(0),
+            rateBps: 25,
+            minFee: new anchor.BN(2000),
+            maxFee: new anchor.BN(1000),
+          }, // withdrawal_fee (min > max - invalid)
+          null, // transfer_fee
+          null  // fee_error_margin
+        )
+        .accounts({
+          globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .preInstructions([modifyComputeUnits])
+        .rpc();
+
+      expect.fail("Transaction should have failed due to invalid fee schedule");
+    } catch (error) {
+      const errorString = error.toString();
+      expect(
+        errorString.includes("InvalidFeeSchedule") ||
+        errorString.includes("custom program error")
+      ).to.be.true;
+    }
+  });
+
   it("Authority can update multiple global config parameters at once", async () => {
     const newFeeRecipient = new PublicKey(FEE_RECIPIENT_ACCOUNT);
     const newDepositFeeRate = 75; // 0.75%
@@ -6338,12 +6673,14 @@ describe("zkcash", () => {
     
     await program.methods
       .updateGlobalConfig(
-        newDepositFeeRate, // deposit_fee_rate
-        newWithdrawalFeeRate, // withdrawal_fee_rate
+        feeScheduleFromRate(newDepositFeeRate), // deposit_fee
+        feeScheduleFromRate(newWithdrawalFeeRate), // withdrawal_fee
+        null, // transfer_fee
         newFeeErrorMargin  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6352,8 +6689,8 @@ describe("zkcash", () => {
 
     // Verify all parameters were updated
     const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
-    expect(globalConfig.depositFeeRate).to.equal(newDepositFeeRate);
-    expect(globalConfig.withdrawalFeeRate).to.equal(newWithdrawalFeeRate);
+    expect(globalConfig.depositFee.rateBps).to.equal(newDepositFeeRate);
+    expect(globalConfig.withdrawalFee.rateBps).to.equal(newWithdrawalFeeRate);
     expect(globalConfig.feeErrorMargin).to.equal(newFeeErrorMargin);
   });
 
@@ -6379,12 +6716,14 @@ describe("zkcash", () => {
       
       await program.methods
         .updateGlobalConfig(
-          100,  // deposit_fee_rate
-          null, // withdrawal_fee_rate
+          feeScheduleFromRate(100), // deposit_fee
+          null, // withdrawal_fee
+          null, // transfer_fee
           null  // fee_error_margin
         )
         .accounts({
           globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
           authority: nonAuthority.publicKey,
         })
         .signers([nonAuthority])
@@ -6415,12 +6754,14 @@ describe("zkcash", () => {
       
       await program.methods
         .updateGlobalConfig(
-          15000, // deposit_fee_rate (150% - invalid)
-          null, // withdrawal_fee_rate
+          feeScheduleFromRate(15000), // deposit_fee (150% - invalid)
+          null, // withdrawal_fee
+          null, // transfer_fee
           null  // fee_error_margin
         )
         .accounts({
           globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
           authority: authority.publicKey,
         })
         .signers([authority])
@@ -6446,12 +6787,14 @@ describe("zkcash", () => {
       
       await program.methods
         .updateGlobalConfig(
-          null, // deposit_fee_rate
-          12000, // withdrawal_fee_rate (120% - invalid)
+          null, // deposit_fee
+          feeScheduleFromRate(12000), // withdrawal_fee (120% - invalid)
+          null, // transfer_fee
           null  // fee_error_margin
         )
         .accounts({
           globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
           authority: authority.publicKey,
         })
         .signers([authority])
@@ -6477,12 +6820,14 @@ describe("zkcash", () => {
       
       await program.methods
         .updateGlobalConfig(
-          null, // deposit_fee_rate
-          null, // withdrawal_fee_rate
-          20000 // fee_error_margin (200% - invalid)
+          null, // deposit_fee
+          null, // withdrawal_fee
+          null, // transfer_fee
+          20000  // fee_error_margin (200% - invalid)
         )
         .accounts({
           globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
           authority: authority.publicKey,
         })
         .signers([authority])
@@ -6511,12 +6856,14 @@ describe("zkcash", () => {
     // Update with all null values
     await program.methods
       .updateGlobalConfig(
-        null, // deposit_fee_rate
-        null, // withdrawal_fee_rate
+        null, // deposit_fee
+        null, // withdrawal_fee
+        null, // transfer_fee
         null  // fee_error_margin
       )
       .accounts({
         globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
         authority: authority.publicKey,
       })
       .signers([authority])
@@ -6525,8 +6872,9 @@ describe("zkcash", () => {
 
     // Verify all values remain unchanged
     const updatedConfig = await program.account.globalConfig.fetch(globalConfigPDA);
-    expect(updatedConfig.depositFeeRate).to.equal(initialConfig.depositFeeRate);
-    expect(updatedConfig.withdrawalFeeRate).to.equal(initialConfig.withdrawalFeeRate);
+    expect(updatedConfig.depositFee).to.deep.equal(initialConfig.depositFee);
+    expect(updatedConfig.withdrawalFee).to.deep.equal(initialConfig.withdrawalFee);
+    expect(updatedConfig.transferFee).to.deep.equal(initialConfig.transferFee);
     expect(updatedConfig.feeErrorMargin).to.equal(initialConfig.feeErrorMargin);
   });
 
@@ -6627,7 +6975,7 @@ describe("zkcash", () => {
     });
     
     const tx = await program.methods
-      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2)
+      .transact(proofToSubmit, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
       .accounts({
         treeAccount: treeAccountPDA,
         nullifier0: nullifier0PDA,
@@ -6640,6 +6988,11 @@ describe("zkcash", () => {
         feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
         treeTokenAccount: treeTokenAccountPDA,
         globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proofToSubmit),
+        pendingWithdrawal: null,
         signer: randomUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId
       })
@@ -6664,4 +7017,2019 @@ describe("zkcash", () => {
 
     expect(txSig).to.be.a('string');
   });
+
+  it("Queued config change with no timelock can be executed immediately", async () => {
+    const newLimit = new anchor.BN(2_000_000_000_000); // 2000 SOL
+    const initialConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    const pendingConfigChangePDA = findPendingConfigChangePDA(program, initialConfig.nextConfigChangeId);
+
+    await program.methods
+      .queueConfigChange({ depositLimit: { maxDepositAmount: newLimit } })
+      .accounts({
+        globalConfig: globalConfigPDA,
+        pendingConfigChange: pendingConfigChangePDA,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+
+    const pendingChange = await program.account.pendingConfigChange.fetch(pendingConfigChangePDA);
+    expect(pendingChange.effectiveSlot.toString()).to.equal(pendingChange.queuedSlot.toString());
+
+    await program.methods
+      .executeConfigChange()
+      .accounts({
+        pendingConfigChange: pendingConfigChangePDA,
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        authority: authority.publicKey,
+      })
+      .rpc();
+
+    const merkleTreeAccount = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(merkleTreeAccount.maxDepositAmount.toString()).to.equal(newLimit.toString());
+    expect(await provider.connection.getAccountInfo(pendingConfigChangePDA)).to.be.null;
+  });
+
+  it("Config changes are delayed and cancellable while a timelock is set", async () => {
+    const delaySlots = new anchor.BN(5);
+    let config = await program.account.globalConfig.fetch(globalConfigPDA);
+
+    // Enable the timelock (takes effect immediately since no timelock is set yet)
+    const timelockChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+    await program.methods
+      .queueConfigChange({ timelock: { delaySlots } })
+      .accounts({
+        globalConfig: globalConfigPDA,
+        pendingConfigChange: timelockChangePDA,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+    await program.methods
+      .executeConfigChange()
+      .accounts({
+        pendingConfigChange: timelockChangePDA,
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        authority: authority.publicKey,
+      })
+      .rpc();
+
+    // Direct updates are now rejected
+    try {
+      await program.methods
+        .updateDepositLimit(new anchor.BN(1_000_000_000))
+        .accounts({
+          treeAccount: treeAccountPDA,
+          globalConfig: globalConfigPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      expect.fail("Direct update should have failed while a timelock is set");
+    } catch (error) {
+      expect(error.toString()).to.include("ConfigTimelocked");
+    }
+
+    // A queued change cannot be executed before its effective slot
+    config = await program.account.globalConfig.fetch(globalConfigPDA);
+    const feeChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+    await program.methods
+      .queueConfigChange({
+        fees: {
+          depositFee: null,
+          withdrawalFee: feeScheduleFromRate(500),
+          transferFee: null,
+          feeErrorMargin: null,
+        },
+      })
+      .accounts({
+        globalConfig: globalConfigPDA,
+        pendingConfigChange: feeChangePDA,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+
+    const pendingChange = await program.account.pendingConfigChange.fetch(feeChangePDA);
+    expect(pendingChange.effectiveSlot.sub(pendingChange.queuedSlot).toString()).to.equal(delaySlots.toString());
+
+    try {
+      await program.methods
+        .executeConfigChange()
+        .accounts({
+          pendingConfigChange: feeChangePDA,
+          globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
+          authority: authority.publicKey,
+        })
+        .rpc();
+      expect.fail("Execution should have failed before the effective slot");
+    } catch (error) {
+      expect(error.toString()).to.include("ConfigChangeNotReady");
+    }
+
+    // The authority cancels the fee hike
+    await program.methods
+      .cancelConfigChange(pendingChange.id)
+      .accounts({
+        pendingConfigChange: feeChangePDA,
+        globalConfig: globalConfigPDA,
+        authority: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+    expect(await provider.connection.getAccountInfo(feeChangePDA)).to.be.null;
+
+    // Disable the timelock again so later tests can update the config directly
+    config = await program.account.globalConfig.fetch(globalConfigPDA);
+    const resetChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+    await program.methods
+      .queueConfigChange({ timelock: { delaySlots: new anchor.BN(0) } })
+      .accounts({
+        globalConfig: globalConfigPDA,
+        pendingConfigChange: resetChangePDA,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+    const resetChange = await program.account.pendingConfigChange.fetch(resetChangePDA);
+    while (await provider.connection.getSlot() < resetChange.effectiveSlot.toNumber()) {
+      await new Promise((resolve) => setTimeout(resolve, 400));
+    }
+    await program.methods
+      .executeConfigChange()
+      .accounts({
+        pendingConfigChange: resetChangePDA,
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        authority: authority.publicKey,
+      })
+      .rpc();
+
+    config = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(config.configTimelockSlots.toNumber()).to.equal(0);
+  });
+
+  it("View instructions return fee quotes and pool state through return data", async () => {
+    const withdrawAmount = new anchor.BN(-1_000_000);
+    const quote = await program.methods
+      .quoteFee(withdrawAmount)
+      .accounts({ globalConfig: globalConfigPDA })
+      .view();
+
+    const config = await program.account.globalConfig.fetch(globalConfigPDA);
+    const expectedFee = Math.floor((1_000_000 * config.withdrawalFee.rateBps) / 10000);
+    expect(quote.expectedFee.toNumber()).to.equal(expectedFee);
+    expect(quote.minAcceptableFee.toNumber()).to.equal(
+      Math.floor((expectedFee * (10000 - config.feeErrorMargin)) / 10000)
+    );
+
+    const poolState = await program.methods
+      .getPoolState()
+      .accounts({ treeAccount: treeAccountPDA, globalConfig: globalConfigPDA })
+      .view();
+
+    const merkleTreeAccount = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(poolState.nextIndex.toString()).to.equal(merkleTreeAccount.nextIndex.toString());
+    expect(poolState.root).to.deep.equal(merkleTreeAccount.root);
+    expect(poolState.withdrawalFee.rateBps).to.equal(config.withdrawalFee.rateBps);
+
+    const rootKnown = await program.methods
+      .isRootKnown(merkleTreeAccount.root)
+      .accounts({ treeAccount: treeAccountPDA })
+      .view();
+    expect(rootKnown).to.be.true;
+
+    const unknownRoot = await program.methods
+      .isRootKnown(Array.from(crypto.randomBytes(32)))
+      .accounts({ treeAccount: treeAccountPDA })
+      .view();
+    expect(unknownRoot).to.be.false;
+  });
+
+  it("deposit_for funds a deposit from a separate depositor and emits commitments", async () => {
+    // Stands in for a PDA of a calling program: funds the deposit but not the nullifier rent
+    const depositor = anchor.web3.Keypair.generate();
+    const depositorAirdropSignature = await provider.connection.requestAirdrop(depositor.publicKey, 1 * LAMPORTS_PER_SOL);
+    const latestBlockHash = await provider.connection.getLatestBlockhash();
+    await provider.connection.confirmTransaction({
+      blockhash: latestBlockHash.blockhash,
+      lastValidBlockHeight: latestBlockHash.lastValidBlockHeight,
+      signature: depositorAirdropSignature,
+    });
+
+    const depositAmount = 20000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const depositExtData = {
+      recipient: depositor.publicKey, // deposit_for binds the proof to the depositor
+      extAmount: new anchor.BN(depositAmount),
+      encryptedOutput1: Buffer.from("depositForEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("depositForEncryptedOutput2"),
+      fee: depositFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+
+    const depositInputs = [
+      new Utxo({ lightWasm }),
+      new Utxo({ lightWasm })
+    ];
+    const publicAmountNumber = depositExtData.extAmount.sub(depositFee).add(FIELD_SIZE).mod(FIELD_SIZE);
+    const depositOutputs = [
+      new Utxo({ lightWasm, amount: publicAmountNumber.toString(), index: globalMerkleTree._layers[0].length }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+
+    const depositInputNullifiers = await Promise.all(depositInputs.map(x => x.getNullifier()));
+    const depositOutputCommitments = await Promise.all(depositOutputs.map(x => x.getCommitment()));
+    const depositInput = {
+      root: globalMerkleTree.root(),
+      inputNullifier: depositInputNullifiers,
+      outputCommitment: depositOutputCommitments,
+      publicAmount: publicAmountNumber.toString(),
+      extDataHash: getExtDataHash(depositExtData),
+      inAmount: depositInputs.map(x => x.amount.toString(10)),
+      inPrivateKey: depositInputs.map(x => x.keypair.privkey),
+      inBlinding: depositInputs.map(x => x.blinding.toString(10)),
+      mintAddress: depositInputs[0].mintAddress,
+      inPathIndices: depositInputs.map(() => 0),
+      inPathElements: depositInputs.map(() => [...new Array(globalMerkleTree.levels).fill(0)]),
+      outAmount: depositOutputs.map(x => x.amount.toString(10)),
+      outBlinding: depositOutputs.map(x => x.blinding.toString(10)),
+      outPubkey: depositOutputs.map(x => x.keypair.pubkey),
+    };
+
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const depositProofResult = await prove(depositInput, keyBasePath);
+    const depositProofInBytes = parseProofToBytesArray(depositProofResult.proof);
+    const depositInputsInBytes = parseToBytesArray(depositProofResult.publicSignals);
+    const depositProofToSubmit = {
+      proofA: depositProofInBytes.proofA,
+      proofB: depositProofInBytes.proofB.flat(),
+      proofC: depositProofInBytes.proofC,
+      root: depositInputsInBytes[0],
+      publicAmount: depositInputsInBytes[1],
+      extDataHash: depositInputsInBytes[2],
+      inputNullifiers: [depositInputsInBytes[3], depositInputsInBytes[4]],
+      outputCommitments: [depositInputsInBytes[5], depositInputsInBytes[6]],
+    };
+
+    const depositNullifiers = findNullifierPDAs(program, depositProofToSubmit);
+    const depositCrossCheckNullifiers = findCrossCheckNullifierPDAs(program, depositProofToSubmit);
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    const treeTokenBalanceBefore = await provider.connection.getBalance(treeTokenAccountPDA);
+    const depositorBalanceBefore = await provider.connection.getBalance(depositor.publicKey);
+    const treeBefore = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+
+    const tx = await program.methods
+      .depositFor(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        nullifier0: depositNullifiers.nullifier0PDA,
+        nullifier1: depositNullifiers.nullifier1PDA,
+        nullifier2: depositCrossCheckNullifiers.nullifier2PDA,
+        nullifier3: depositCrossCheckNullifiers.nullifier3PDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, depositProofToSubmit),
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        depositor: depositor.publicKey,
+        payer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .preInstructions([modifyComputeUnits])
+      .transaction();
+
+    const versionedTx = await createVersionedTransactionWithALT(
+      provider.connection,
+      randomUser.publicKey,
+      tx.instructions,
+      lookupTableAddress
+    );
+    const txSig = await sendAndConfirmVersionedTransaction(
+      provider.connection,
+      versionedTx,
+      [randomUser, depositor]
+    );
+
+    for (const commitment of depositOutputCommitments) {
+      globalMerkleTree.insert(commitment);
+    }
+
+    // The depositor only paid the deposit; the payer covered fees and nullifier rent
+    const depositorBalanceAfter = await provider.connection.getBalance(depositor.publicKey);
+    expect(depositorBalanceBefore - depositorBalanceAfter).to.equal(depositAmount);
+    const treeTokenBalanceAfter = await provider.connection.getBalance(treeTokenAccountPDA);
+    expect(treeTokenBalanceAfter - treeTokenBalanceBefore).to.equal(depositAmount - depositFee.toNumber());
+
+    const txDetails = await provider.connection.getTransaction(txSig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const eventParser = new EventParser(program.programId, new BorshCoder(program.idl));
+    const events = Array.from(eventParser.parseLogs(txDetails.meta.logMessages));
+    expect(events.filter((event) => event.name === "commitmentData").length).to.equal(2);
+
+    // Both outputs share one root history slot, stamped with the transaction's slot
+    const treeAfter = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    const rootIndex = treeAfter.rootIndex.toNumber();
+    expect(rootIndex).to.equal((treeBefore.rootIndex.toNumber() + 1) % ROOT_HISTORY_SIZE);
+    expect(treeAfter.rootHistorySlots[rootIndex].toNumber()).to.equal(txDetails.slot);
+
+    // A wallet following the tree from its events keeps the first note's path current without
+    // storing the tree: the second commitment fast-forwards it
+    const tracker = new MerklePathTracker(
+      DEFAULT_HEIGHT,
+      lightWasm,
+      treeBefore.nextIndex.toNumber(),
+      treeBefore.subtrees.map((node: number[]) => new anchor.BN(node).toString()),
+      new anchor.BN(treeBefore.root).toString()
+    );
+    const commitmentEvents = events.filter((event) => event.name === "commitmentData");
+    const trackedIndex = tracker.append(new anchor.BN(commitmentEvents[0].data.commitment).toString(), true);
+    expect(trackedIndex).to.equal(commitmentEvents[0].data.index.toNumber());
+    tracker.append(new anchor.BN(commitmentEvents[1].data.commitment).toString());
+    expect(tracker.root()).to.equal(new anchor.BN(treeAfter.root).toString());
+    expect(tracker.path(trackedIndex)).to.deep.equal(globalMerkleTree.path(trackedIndex));
+  });
+
+  it("withdraw_multi pays several recipients with a single proof", async () => {
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    // Proves spending `inputs` into `outputs` for the given ext data
+    const proveTransaction = async (inputs: Utxo[], outputs: Utxo[], extData: any) => {
+      const inPathIndices = [];
+      const inPathElements = [];
+      for (const input of inputs) {
+        if (input.amount.gt(new BN(0))) {
+          input.index = globalMerkleTree.indexOf(await input.getCommitment());
+          inPathIndices.push(input.index);
+          inPathElements.push(globalMerkleTree.path(input.index).pathElements);
+        } else {
+          inPathIndices.push(0);
+          inPathElements.push(new Array(globalMerkleTree.levels).fill(0));
+        }
+      }
+      const proofResult = await prove({
+        root: globalMerkleTree.root(),
+        inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+        outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+        publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+        extDataHash: getExtDataHash(extData),
+        inAmount: inputs.map(x => x.amount.toString(10)),
+        inPrivateKey: inputs.map(x => x.keypair.privkey),
+        inBlinding: inputs.map(x => x.blinding.toString(10)),
+        mintAddress: inputs[0].mintAddress,
+        inPathIndices,
+        inPathElements,
+        outAmount: outputs.map(x => x.amount.toString(10)),
+        outBlinding: outputs.map(x => x.blinding.toString(10)),
+        outPubkey: outputs.map(x => x.keypair.pubkey),
+      }, keyBasePath);
+      const proofInBytes = parseProofToBytesArray(proofResult.proof);
+      const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+      return {
+        proofA: proofInBytes.proofA,
+        proofB: proofInBytes.proofB.flat(),
+        proofC: proofInBytes.proofC,
+        root: inputsInBytes[0],
+        publicAmount: inputsInBytes[1],
+        extDataHash: inputsInBytes[2],
+        inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+        outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+      };
+    };
+
+    // Deposit a note to withdraw from
+    const depositAmount = 5_000_000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const depositExtData = {
+      recipient: recipient.publicKey,
+      extAmount: new anchor.BN(depositAmount),
+      encryptedOutput1: Buffer.from("multiDepositEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("multiDepositEncryptedOutput2"),
+      fee: depositFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+    const depositOutputs = [
+      new Utxo({ lightWasm, amount: depositExtData.extAmount.sub(depositFee).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    const depositProof = await proveTransaction([new Utxo({ lightWasm }), new Utxo({ lightWasm })], depositOutputs, depositExtData);
+    const depositNullifiers = findNullifierPDAs(program, depositProof);
+    const depositCrossCheckNullifiers = findCrossCheckNullifierPDAs(program, depositProof);
+    const depositTx = await program.methods
+      .transact(depositProof, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        nullifier0: depositNullifiers.nullifier0PDA,
+        nullifier1: depositNullifiers.nullifier1PDA,
+        nullifier2: depositCrossCheckNullifiers.nullifier2PDA,
+        nullifier3: depositCrossCheckNullifiers.nullifier3PDA,
+        recipient: recipient.publicKey,
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, depositProof),
+        pendingWithdrawal: null,
+        signer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .preInstructions([modifyComputeUnits])
+      .transaction();
+    await sendAndConfirmVersionedTransaction(
+      provider.connection,
+      await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, depositTx.instructions, lookupTableAddress),
+      [randomUser]
+    );
+    for (const output of depositOutputs) {
+      globalMerkleTree.insert(await output.getCommitment());
+    }
+
+    // Withdraw 3.7M lamports split over three new recipients (each payout must cover rent exemption)
+    const payouts = [1_000_000, 1_200_000, 1_500_000].map((amount) => ({
+      recipient: anchor.web3.Keypair.generate().publicKey,
+      amount: new anchor.BN(amount),
+    }));
+    const withdrawalAmount = 3_700_000;
+    const withdrawFee = new anchor.BN(calculateWithdrawalFee(withdrawalAmount));
+    const withdrawExtData = {
+      recipient: getPayoutsCommitment(payouts),
+      extAmount: new anchor.BN(-withdrawalAmount),
+      encryptedOutput1: Buffer.from("multiWithdrawEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("multiWithdrawEncryptedOutput2"),
+      fee: withdrawFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+    const withdrawInputs = [depositOutputs[0], new Utxo({ lightWasm })];
+    const withdrawOutputs = [
+      new Utxo({ lightWasm, amount: depositOutputs[0].amount.sub(new BN(withdrawalAmount)).sub(withdrawFee).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    const withdrawProof = await proveTransaction(withdrawInputs, withdrawOutputs, withdrawExtData);
+    const withdrawNullifiers = findNullifierPDAs(program, withdrawProof);
+    const withdrawCrossCheckNullifiers = findCrossCheckNullifierPDAs(program, withdrawProof);
+
+    const withdrawTx = await program.methods
+      .withdrawMulti(withdrawProof, createExtDataMinified(withdrawExtData), payouts, withdrawExtData.encryptedOutput1, withdrawExtData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        nullifier0: withdrawNullifiers.nullifier0PDA,
+        nullifier1: withdrawNullifiers.nullifier1PDA,
+        nullifier2: withdrawCrossCheckNullifiers.nullifier2PDA,
+        nullifier3: withdrawCrossCheckNullifiers.nullifier3PDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        signer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .remainingAccounts(payouts.map((payout) => ({ pubkey: payout.recipient, isSigner: false, isWritable: true })))
+      .preInstructions([modifyComputeUnits])
+      .transaction();
+    await sendAndConfirmVersionedTransaction(
+      provider.connection,
+      await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, withdrawTx.instructions, lookupTableAddress),
+      [randomUser]
+    );
+    for (const output of withdrawOutputs) {
+      globalMerkleTree.insert(await output.getCommitment());
+    }
+
+    for (const payout of payouts) {
+      expect(await provider.connection.getBalance(payout.recipient)).to.equal(payout.amount.toNumber());
+    }
+  });
+
+  it("transact_queued defers commitment insertion to a permissionless crank", async () => {
+    const shard = 0;
+    const [commitmentQueuePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("commitment_queue"), Buffer.from([shard])],
+      program.programId
+    );
+
+    // Only the authority can create queue shards
+    try {
+      await program.methods
+        .initializeCommitmentQueue(shard)
+        .accounts({
+          commitmentQueue: commitmentQueuePDA,
+          globalConfig: globalConfigPDA,
+          authority: randomUser.publicKey,
+          payer: randomUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .signers([randomUser])
+        .rpc();
+      expect.fail("Non-authority should not be able to create a commitment queue");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    await program.methods
+      .initializeCommitmentQueue(shard)
+      .accounts({
+        commitmentQueue: commitmentQueuePDA,
+        globalConfig: globalConfigPDA,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .signers([authority])
+      .rpc();
+
+    const depositAmount = 20000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const depositExtData = {
+      recipient: randomUser.publicKey,
+      extAmount: new anchor.BN(depositAmount),
+      encryptedOutput1: Buffer.from("queuedEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("queuedEncryptedOutput2"),
+      fee: depositFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+
+    const depositInputs = [
+      new Utxo({ lightWasm }),
+      new Utxo({ lightWasm })
+    ];
+    const publicAmountNumber = depositExtData.extAmount.sub(depositFee).add(FIELD_SIZE).mod(FIELD_SIZE);
+    const depositOutputs = [
+      new Utxo({ lightWasm, amount: publicAmountNumber.toString(), index: globalMerkleTree._layers[0].length }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+
+    const depositInputNullifiers = await Promise.all(depositInputs.map(x => x.getNullifier()));
+    const depositOutputCommitments = await Promise.all(depositOutputs.map(x => x.getCommitment()));
+    const depositInput = {
+      root: globalMerkleTree.root(),
+      inputNullifier: depositInputNullifiers,
+      outputCommitment: depositOutputCommitments,
+      publicAmount: publicAmountNumber.toString(),
+      extDataHash: getExtDataHash(depositExtData),
+      inAmount: depositInputs.map(x => x.amount.toString(10)),
+      inPrivateKey: depositInputs.map(x => x.keypair.privkey),
+      inBlinding: depositInputs.map(x => x.blinding.toString(10)),
+      mintAddress: depositInputs[0].mintAddress,
+      inPathIndices: depositInputs.map(() => 0),
+      inPathElements: depositInputs.map(() => [...new Array(globalMerkleTree.levels).fill(0)]),
+      outAmount: depositOutputs.map(x => x.amount.toString(10)),
+      outBlinding: depositOutputs.map(x => x.blinding.toString(10)),
+      outPubkey: depositOutputs.map(x => x.keypair.pubkey),
+    };
+
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const depositProofResult = await prove(depositInput, keyBasePath);
+    const depositProofInBytes = parseProofToBytesArray(depositProofResult.proof);
+    const depositInputsInBytes = parseToBytesArray(depositProofResult.publicSignals);
+    const depositProofToSubmit = {
+      proofA: depositProofInBytes.proofA,
+      proofB: depositProofInBytes.proofB.flat(),
+      proofC: depositProofInBytes.proofC,
+      root: depositInputsInBytes[0],
+      publicAmount: depositInputsInBytes[1],
+      extDataHash: depositInputsInBytes[2],
+      inputNullifiers: [depositInputsInBytes[3], depositInputsInBytes[4]],
+      outputCommitments: [depositInputsInBytes[5], depositInputsInBytes[6]],
+    };
+
+    const depositNullifiers = findNullifierPDAs(program, depositProofToSubmit);
+    const depositCrossCheckNullifiers = findCrossCheckNullifierPDAs(program, depositProofToSubmit);
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    const treeBefore = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+
+    const tx = await program.methods
+      .transactQueued(depositProofToSubmit, createExtDataMinified(depositExtData), depositExtData.encryptedOutput1, depositExtData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        commitmentQueue: commitmentQueuePDA,
+        nullifier0: depositNullifiers.nullifier0PDA,
+        nullifier1: depositNullifiers.nullifier1PDA,
+        nullifier2: depositCrossCheckNullifiers.nullifier2PDA,
+        nullifier3: depositCrossCheckNullifiers.nullifier3PDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, depositProofToSubmit),
+        recipient: randomUser.publicKey,
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        signer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .preInstructions([modifyComputeUnits])
+      .transaction();
+
+    const versionedTx = await createVersionedTransactionWithALT(
+      provider.connection,
+      randomUser.publicKey,
+      tx.instructions,
+      lookupTableAddress
+    );
+    await sendAndConfirmVersionedTransaction(
+      provider.connection,
+      versionedTx,
+      [randomUser]
+    );
+
+    // The tree is untouched until the queue is processed
+    const treeQueued = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(treeQueued.nextIndex.toString()).to.equal(treeBefore.nextIndex.toString());
+    let queue = await program.account.commitmentQueue.fetch(commitmentQueuePDA);
+    expect(queue.len.toNumber()).to.equal(2);
+
+    // The crank needs no signer beyond the fee payer: anyone can run it. Both outputs are
+    // deposit outputs, so the deposit origin record is passed once for each.
+    const queuedDepositOriginPDA = findDepositOriginPDA(program, depositProofToSubmit);
+    await program.methods
+      .processCommitmentQueue(16)
+      .accounts({
+        treeAccount: treeAccountPDA,
+        commitmentQueue: commitmentQueuePDA,
+        globalConfig: globalConfigPDA,
+      })
+      .remainingAccounts([
+        { pubkey: queuedDepositOriginPDA, isSigner: false, isWritable: true },
+        { pubkey: queuedDepositOriginPDA, isSigner: false, isWritable: true },
+      ])
+      .rpc();
+
+    // The outputs' leaf indices are now known for ragequit
+    const queuedDepositOrigin = await program.account.depositOrigin.fetch(queuedDepositOriginPDA);
+    expect(queuedDepositOrigin.leafIndices.map((index: anchor.BN) => index.toNumber())).to.deep.equal([
+      treeBefore.nextIndex.toNumber(),
+      treeBefore.nextIndex.toNumber() + 1,
+    ]);
+
+    for (const commitment of depositOutputCommitments) {
+      globalMerkleTree.insert(commitment);
+    }
+
+    const treeAfter = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(treeAfter.nextIndex.toNumber()).to.equal(treeBefore.nextIndex.toNumber() + 2);
+    expect(treeAfter.root).to.deep.equal(new anchor.BN(globalMerkleTree.root()).toArray("be", 32));
+    queue = await program.account.commitmentQueue.fetch(commitmentQueuePDA);
+    expect(queue.len.toNumber()).to.equal(0);
+
+    try {
+      await program.methods
+        .processCommitmentQueue(16)
+        .accounts({
+          treeAccount: treeAccountPDA,
+          commitmentQueue: commitmentQueuePDA,
+          globalConfig: globalConfigPDA,
+        })
+        .rpc();
+      expect.fail("Processing an empty queue should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("CommitmentQueueEmpty");
+    }
+  });
+
+  it("Anyone can archive recorded roots into paged root archive accounts", async () => {
+    const ROOT_ARCHIVE_PAGE_SIZE = 128;
+    let tree = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    // Roots evicted before the first archive_roots call are skipped
+    const oldestUnarchived = Math.max(tree.archivedRootCount.toNumber(), tree.rootCount.toNumber() - ROOT_HISTORY_SIZE);
+    const page = new anchor.BN(Math.floor(oldestUnarchived / ROOT_ARCHIVE_PAGE_SIZE));
+    const [rootArchivePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("root_archive"), treeAccountPDA.toBuffer(), page.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .initializeRootArchive(page)
+      .accounts({
+        treeAccount: treeAccountPDA,
+        rootArchive: rootArchivePDA,
+        payer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .signers([randomUser])
+      .rpc();
+
+    await program.methods
+      .archiveRoots()
+      .accounts({
+        treeAccount: treeAccountPDA,
+        rootArchive: rootArchivePDA,
+      })
+      .rpc();
+
+    tree = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    const rootArchive = await program.account.rootArchive.fetch(rootArchivePDA);
+    expect(rootArchive.tree.equals(treeAccountPDA)).to.be.true;
+    expect(tree.archivedRootCount.toString()).to.equal(tree.rootCount.toString());
+
+    // The current root is archived with the slot it was recorded at
+    const lastPosition = (tree.rootCount.toNumber() - 1) % ROOT_ARCHIVE_PAGE_SIZE;
+    const rootIndex = tree.rootIndex.toNumber();
+    expect(rootArchive.roots[lastPosition]).to.deep.equal(tree.root);
+    expect(rootArchive.slots[lastPosition].toString()).to.equal(tree.rootHistorySlots[rootIndex].toString());
+    expect(rootArchive.endSlot.toString()).to.equal(tree.rootHistorySlots[rootIndex].toString());
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(globalConfig.maxArchivedRootAgeSlots.toNumber()).to.be.greaterThan(0);
+
+    try {
+      await program.methods
+        .archiveRoots()
+        .accounts({
+          treeAccount: treeAccountPDA,
+          rootArchive: rootArchivePDA,
+        })
+        .rpc();
+      expect.fail("Archiving with no new roots should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("NoRootsToArchive");
+    }
+  });
+
+  it("Tree rollover is refused while the newest tree has room", async () => {
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(globalConfig.currentTreeGeneration.toNumber()).to.equal(0);
+
+    const [nextTreePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("merkle_tree"), new anchor.BN(1).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .rolloverTree()
+        .accounts({
+          treeAccount: treeAccountPDA,
+          newTreeAccount: nextTreePDA,
+          globalConfig: globalConfigPDA,
+          payer: randomUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .signers([randomUser])
+        .rpc();
+      expect.fail("Rollover of a tree with room left should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("TreeNotFull");
+    }
+
+    const merkleTreeAccount = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(merkleTreeAccount.generation.toNumber()).to.equal(0);
+    expect(await provider.connection.getAccountInfo(nextTreePDA)).to.be.null;
+  });
+
+  it("check_invariants proves the vault covers outstanding notes and emits the figures", async () => {
+    const treeToken = await program.account.treeTokenAccount.fetch(treeTokenAccountPDA);
+    // Earlier tests shielded and unshielded funds through transact
+    expect(treeToken.totalDeposited.toNumber()).to.be.greaterThan(0);
+    expect(treeToken.totalWithdrawn.toNumber()).to.be.greaterThan(0);
+
+    const simulation = await program.methods
+      .checkInvariants()
+      .accounts({
+        treeTokenAccount: treeTokenAccountPDA,
+      })
+      .simulate();
+    const report = simulation.events.find((event) => event.name === "solvencyReport").data;
+
+    const vaultBalance = await provider.connection.getBalance(treeTokenAccountPDA);
+    expect(report.vaultBalance.toNumber()).to.equal(vaultBalance);
+    expect(report.totalDeposited.toString()).to.equal(treeToken.totalDeposited.toString());
+    const netShielded = report.totalDeposited.sub(report.totalWithdrawn).sub(report.totalFees);
+    expect(report.vaultBalance.gte(netShielded.add(report.rentExemptMinimum))).to.be.true;
+
+    // It needs no signer beyond the fee payer
+    await program.methods
+      .checkInvariants()
+      .accounts({
+        treeTokenAccount: treeTokenAccountPDA,
+      })
+      .rpc();
+  });
+
+  it("Deposits from addresses on the deny list are rejected", async () => {
+    const oracle = new LocalScreeningOracle();
+    const sanctionedUser = anchor.web3.Keypair.generate();
+    oracle.flag(sanctionedUser.publicKey);
+    const fundTx = new anchor.web3.Transaction().add(
+      anchor.web3.SystemProgram.transfer({
+        fromPubkey: fundingAccount.publicKey,
+        toPubkey: sanctionedUser.publicKey,
+        lamports: LAMPORTS_PER_SOL,
+      })
+    );
+    await provider.connection.confirmTransaction(
+      await provider.connection.sendTransaction(fundTx, [fundingAccount])
+    );
+
+    // Only the authority maintains the list
+    try {
+      await program.methods
+        .addToDenylist(sanctionedUser.publicKey)
+        .accounts({
+          denyList: denyListPDA,
+          globalConfig: globalConfigPDA,
+          authority: randomUser.publicKey,
+        })
+        .signers([randomUser])
+        .rpc();
+      expect.fail("A non-authority should not be able to update the deny list");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    // Push the oracle's flagged addresses on-chain
+    for (const address of oracle.flaggedAddresses()) {
+      await program.methods
+        .addToDenylist(address)
+        .accounts({
+          denyList: denyListPDA,
+          globalConfig: globalConfigPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+    }
+    let denyList = await program.account.denyList.fetch(denyListPDA);
+    expect(denyList.len.toNumber()).to.equal(1);
+
+    const depositAmount = 2_000_000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const extData = {
+      recipient: recipient.publicKey,
+      extAmount: new anchor.BN(depositAmount),
+      encryptedOutput1: Buffer.from("deniedEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("deniedEncryptedOutput2"),
+      fee: depositFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+    const inputs = [new Utxo({ lightWasm }), new Utxo({ lightWasm })];
+    const outputs = [
+      new Utxo({ lightWasm, amount: extData.extAmount.sub(depositFee).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    const proofResult = await prove({
+      root: globalMerkleTree.root(),
+      inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+      outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+      publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+      extDataHash: getExtDataHash(extData),
+      inAmount: inputs.map(x => x.amount.toString(10)),
+      inPrivateKey: inputs.map(x => x.keypair.privkey),
+      inBlinding: inputs.map(x => x.blinding.toString(10)),
+      mintAddress: inputs[0].mintAddress,
+      inPathIndices: [0, 0],
+      inPathElements: [new Array(globalMerkleTree.levels).fill(0), new Array(globalMerkleTree.levels).fill(0)],
+      outAmount: outputs.map(x => x.amount.toString(10)),
+      outBlinding: outputs.map(x => x.blinding.toString(10)),
+      outPubkey: outputs.map(x => x.keypair.pubkey),
+    }, path.resolve(__dirname, '../../artifacts/circuits/transaction2'));
+    const proofInBytes = parseProofToBytesArray(proofResult.proof);
+    const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+    const proof = {
+      proofA: proofInBytes.proofA,
+      proofB: proofInBytes.proofB.flat(),
+      proofC: proofInBytes.proofC,
+      root: inputsInBytes[0],
+      publicAmount: inputsInBytes[1],
+      extDataHash: inputsInBytes[2],
+      inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+      outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+    };
+    const nullifiers = findNullifierPDAs(program, proof);
+    const crossCheckNullifiers = findCrossCheckNullifierPDAs(program, proof);
+    const depositTx = await program.methods
+      .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        nullifier0: nullifiers.nullifier0PDA,
+        nullifier1: nullifiers.nullifier1PDA,
+        nullifier2: crossCheckNullifiers.nullifier2PDA,
+        nullifier3: crossCheckNullifiers.nullifier3PDA,
+        recipient: recipient.publicKey,
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: findDepositOriginPDA(program, proof),
+        pendingWithdrawal: null,
+        signer: sanctionedUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .preInstructions([anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ units: 1_000_000 })])
+      .transaction();
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    try {
+      await sendAndConfirmVersionedTransaction(
+        provider.connection,
+        await createVersionedTransactionWithALT(provider.connection, sanctionedUser.publicKey, depositTx.instructions, lookupTableAddress),
+        [sanctionedUser]
+      );
+      expect.fail("A deposit signed by a denied address should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("DepositorDenied");
+    }
+
+    // Clearing the address removes it from the list again
+    oracle.clear(sanctionedUser.publicKey);
+    await program.methods
+      .removeFromDenylist(sanctionedUser.publicKey)
+      .accounts({
+        denyList: denyListPDA,
+        globalConfig: globalConfigPDA,
+        authority: authority.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+    denyList = await program.account.denyList.fetch(denyListPDA);
+    expect(denyList.len.toNumber()).to.equal(0);
+
+    try {
+      await program.methods
+        .removeFromDenylist(sanctionedUser.publicKey)
+        .accounts({
+          denyList: denyListPDA,
+          globalConfig: globalConfigPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      expect.fail("Removing an address that is not listed should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("AddressNotDenied");
+    }
+  });
+
+  it("Ragequit returns a deposited note to its depositor, and only to them", async () => {
+    const depositAmount = 3_000_000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const extData = {
+      recipient: recipient.publicKey,
+      extAmount: new anchor.BN(depositAmount),
+      encryptedOutput1: Buffer.from("ragequitEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("ragequitEncryptedOutput2"),
+      fee: depositFee,
+      feeRecipient: FEE_RECIPIENT_ACCOUNT,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+    const inputs = [new Utxo({ lightWasm }), new Utxo({ lightWasm })];
+    const outputs = [
+      new Utxo({ lightWasm, amount: extData.extAmount.sub(depositFee).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    const proofResult = await prove({
+      root: globalMerkleTree.root(),
+      inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+      outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+      publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+      extDataHash: getExtDataHash(extData),
+      inAmount: inputs.map(x => x.amount.toString(10)),
+      inPrivateKey: inputs.map(x => x.keypair.privkey),
+      inBlinding: inputs.map(x => x.blinding.toString(10)),
+      mintAddress: inputs[0].mintAddress,
+      inPathIndices: [0, 0],
+      inPathElements: [new Array(globalMerkleTree.levels).fill(0), new Array(globalMerkleTree.levels).fill(0)],
+      outAmount: outputs.map(x => x.amount.toString(10)),
+      outBlinding: outputs.map(x => x.blinding.toString(10)),
+      outPubkey: outputs.map(x => x.keypair.pubkey),
+    }, path.resolve(__dirname, '../../artifacts/circuits/transaction2'));
+    const proofInBytes = parseProofToBytesArray(proofResult.proof);
+    const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+    const proof = {
+      proofA: proofInBytes.proofA,
+      proofB: proofInBytes.proofB.flat(),
+      proofC: proofInBytes.proofC,
+      root: inputsInBytes[0],
+      publicAmount: inputsInBytes[1],
+      extDataHash: inputsInBytes[2],
+      inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+      outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+    };
+    const nullifiers = findNullifierPDAs(program, proof);
+    const crossCheckNullifiers = findCrossCheckNullifierPDAs(program, proof);
+    const depositOriginPDA = findDepositOriginPDA(program, proof);
+    const depositTx = await program.methods
+      .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
+      .accounts({
+        treeAccount: treeAccountPDA,
+        nullifier0: nullifiers.nullifier0PDA,
+        nullifier1: nullifiers.nullifier1PDA,
+        nullifier2: crossCheckNullifiers.nullifier2PDA,
+        nullifier3: crossCheckNullifiers.nullifier3PDA,
+        recipient: recipient.publicKey,
+        feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        denyList: denyListPDA,
+        priceFeed: null,
+        depositorVelocity: null,
+        depositOrigin: depositOriginPDA,
+        pendingWithdrawal: null,
+        signer: randomUser.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .preInstructions([anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ units: 1_000_000 })])
+      .transaction();
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+    await sendAndConfirmVersionedTransaction(
+      provider.connection,
+      await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, depositTx.instructions, lookupTableAddress),
+      [randomUser]
+    );
+    for (const output of outputs) {
+      globalMerkleTree.insert(await output.getCommitment());
+    }
+
+    const depositOrigin = await program.account.depositOrigin.fetch(depositOriginPDA);
+    expect(depositOrigin.depositor.equals(randomUser.publicKey)).to.be.true;
+    expect(depositOrigin.commitments).to.deep.equal(proof.outputCommitments);
+    expect(depositOrigin.amount.toString()).to.equal(extData.extAmount.sub(depositFee).toString());
+    const leafIndex = depositOrigin.leafIndices[0].toNumber();
+    expect(leafIndex).to.equal(globalMerkleTree.indexOf(await outputs[0].getCommitment()));
+
+    // Ragequit half of the note; the rest stays shielded in a change output
+    const ragequitAmount = outputs[0].amount.divn(2);
+    const ragequitInputs = [outputs[0], new Utxo({ lightWasm })];
+    ragequitInputs[0].index = leafIndex;
+    const ragequitOutputs = [
+      new Utxo({ lightWasm, amount: outputs[0].amount.sub(ragequitAmount).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    // The depositor is the recipient and the fee recipient, and no fee is charged
+    const ragequitExtData = {
+      recipient: randomUser.publicKey,
+      extAmount: ragequitAmount.neg(),
+      encryptedOutput1: Buffer.from("ragequitEncryptedOutput1"),
+      encryptedOutput2: Buffer.from("ragequitEncryptedOutput2"),
+      fee: new anchor.BN(0),
+      feeRecipient: randomUser.publicKey,
+      mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+    };
+
+    // The proof's root is the tree holding only the note, at its leaf index
+    const zeros = globalMerkleTree._zeros;
+    let singletonRoot = await outputs[0].getCommitment();
+    for (let level = 0; level < globalMerkleTree.levels; level++) {
+      singletonRoot = ((leafIndex >> level) & 1)
+        ? lightWasm.poseidonHashString([zeros[level], singletonRoot])
+        : lightWasm.poseidonHashString([singletonRoot, zeros[level]]);
+    }
+
+    const ragequitProofResult = await prove({
+      root: singletonRoot,
+      inputNullifier: await Promise.all(ragequitInputs.map(x => x.getNullifier())),
+      outputCommitment: await Promise.all(ragequitOutputs.map(x => x.getCommitment())),
+      publicAmount: ragequitExtData.extAmount.add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+      extDataHash: getExtDataHash(ragequitExtData),
+      inAmount: ragequitInputs.map(x => x.amount.toString(10)),
+      inPrivateKey: ragequitInputs.map(x => x.keypair.privkey),
+      inBlinding: ragequitInputs.map(x => x.blinding.toString(10)),
+      mintAddress: ragequitInputs[0].mintAddress,
+      inPathIndices: [leafIndex, 0],
+      inPathElements: [zeros.slice(0, globalMerkleTree.levels), new Array(globalMerkleTree.levels).fill(0)],
+      outAmount: ragequitOutputs.map(x => x.amount.toString(10)),
+      outBlinding: ragequitOutputs.map(x => x.blinding.toString(10)),
+      outPubkey: ragequitOutputs.map(x => x.keypair.pubkey),
+    }, path.resolve(__dirname, '../../artifacts/circuits/transaction2'));
+    const ragequitProofInBytes = parseProofToBytesArray(ragequitProofResult.proof);
+    const ragequitInputsInBytes = parseToBytesArray(ragequitProofResult.publicSignals);
+    const ragequitProof = {
+      proofA: ragequitProofInBytes.proofA,
+      proofB: ragequitProofInBytes.proofB.flat(),
+      proofC: ragequitProofInBytes.proofC,
+      root: ragequitInputsInBytes[0],
+      publicAmount: ragequitInputsInBytes[1],
+      extDataHash: ragequitInputsInBytes[2],
+      inputNullifiers: [ragequitInputsInBytes[3], ragequitInputsInBytes[4]],
+      outputCommitments: [ragequitInputsInBytes[5], ragequitInputsInBytes[6]],
+    };
+    // The nullifier is the one a private spend of the note would reveal
+    expect(ragequitProof.inputNullifiers[0]).to.deep.equal(
+      new anchor.BN(await outputs[0].getNullifier()).toArray("be", 32)
+    );
+    const ragequitNullifiers = findNullifierPDAs(program, ragequitProof);
+    const ragequitCrossCheckNullifiers = findCrossCheckNullifierPDAs(program, ragequitProof);
+
+    const sendRagequit = async (depositor: PublicKey) => {
+      const ragequitTx = await program.methods
+        .ragequit(ragequitProof, createExtDataMinified(ragequitExtData), ragequitExtData.encryptedOutput1, ragequitExtData.encryptedOutput2, 0)
+        .accounts({
+          treeAccount: treeAccountPDA,
+          depositOrigin: depositOriginPDA,
+          nullifier0: ragequitNullifiers.nullifier0PDA,
+          nullifier1: ragequitNullifiers.nullifier1PDA,
+          nullifier2: ragequitCrossCheckNullifiers.nullifier2PDA,
+          nullifier3: ragequitCrossCheckNullifiers.nullifier3PDA,
+          treeTokenAccount: treeTokenAccountPDA,
+          globalConfig: globalConfigPDA,
+          pendingWithdrawal: null,
+          depositor,
+          signer: recipient.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .preInstructions([anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ units: 1_000_000 })])
+        .transaction();
+      await sendAndConfirmVersionedTransaction(
+        provider.connection,
+        await createVersionedTransactionWithALT(provider.connection, recipient.publicKey, ragequitTx.instructions, lookupTableAddress),
+        [recipient]
+      );
+    };
+
+    // Anyone can submit the proof, but the funds only go to the depositor
+    try {
+      await sendRagequit(recipient.publicKey);
+      expect.fail("A ragequit to anyone but the depositor should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("InvalidRagequitRecipient");
+    }
+
+    const depositorBalanceBefore = await provider.connection.getBalance(randomUser.publicKey);
+    await sendRagequit(randomUser.publicKey);
+    for (const output of ragequitOutputs) {
+      globalMerkleTree.insert(await output.getCommitment());
+    }
+
+    const depositorBalanceAfter = await provider.connection.getBalance(randomUser.publicKey);
+    expect(depositorBalanceAfter - depositorBalanceBefore).to.equal(ragequitAmount.toNumber());
+    const depositOriginAfter = await program.account.depositOrigin.fetch(depositOriginPDA);
+    expect(depositOriginAfter.amount.toString()).to.equal(depositOrigin.amount.sub(ragequitAmount).toString());
+    // The change went into the tree like a transact output
+    const treeAfterRagequit = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(treeAfterRagequit.root).to.deep.equal(new anchor.BN(globalMerkleTree.root()).toArray("be", 32));
+
+    // The note is spent: neither a second ragequit nor a private spend can use it again
+    try {
+      await sendRagequit(randomUser.publicKey);
+      expect.fail("Ragequitting a spent note should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("already in use");
+    }
+  });
+
+  it("Withdrawals above the threshold are queued, then claimed or cancelled by the guardian", async () => {
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    // Applies a config change right away (no timelock is set at this point)
+    const applyConfigChange = async (change: any) => {
+      const config = await program.account.globalConfig.fetch(globalConfigPDA);
+      const pendingConfigChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+      await program.methods
+        .queueConfigChange(change)
+        .accounts({
+          globalConfig: globalConfigPDA,
+          pendingConfigChange: pendingConfigChangePDA,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      await program.methods
+        .executeConfigChange()
+        .accounts({
+          pendingConfigChange: pendingConfigChangePDA,
+          globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
+          authority: authority.publicKey,
+        })
+        .rpc();
+    };
+
+    // Proves and submits a transact spending `inputs` into `outputs`
+    const submitTransaction = async (inputs: Utxo[], outputs: Utxo[], extAmount: number, fee: anchor.BN, txRecipient: PublicKey, queued: boolean) => {
+      const extData = {
+        recipient: txRecipient,
+        extAmount: new anchor.BN(extAmount),
+        encryptedOutput1: Buffer.from("delayedEncryptedOutput1"),
+        encryptedOutput2: Buffer.from("delayedEncryptedOutput2"),
+        fee,
+        feeRecipient: FEE_RECIPIENT_ACCOUNT,
+        mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+      };
+      const inPathIndices = [];
+      const inPathElements = [];
+      for (const input of inputs) {
+        if (input.amount.gt(new BN(0))) {
+          input.index = globalMerkleTree.indexOf(await input.getCommitment());
+          inPathIndices.push(input.index);
+          inPathElements.push(globalMerkleTree.path(input.index).pathElements);
+        } else {
+          inPathIndices.push(0);
+          inPathElements.push(new Array(globalMerkleTree.levels).fill(0));
+        }
+      }
+      const proofResult = await prove({
+        root: globalMerkleTree.root(),
+        inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+        outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+        publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+        extDataHash: getExtDataHash(extData),
+        inAmount: inputs.map(x => x.amount.toString(10)),
+        inPrivateKey: inputs.map(x => x.keypair.privkey),
+        inBlinding: inputs.map(x => x.blinding.toString(10)),
+        mintAddress: inputs[0].mintAddress,
+        inPathIndices,
+        inPathElements,
+        outAmount: outputs.map(x => x.amount.toString(10)),
+        outBlinding: outputs.map(x => x.blinding.toString(10)),
+        outPubkey: outputs.map(x => x.keypair.pubkey),
+      }, keyBasePath);
+      const proofInBytes = parseProofToBytesArray(proofResult.proof);
+      const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+      const proof = {
+        proofA: proofInBytes.proofA,
+        proofB: proofInBytes.proofB.flat(),
+        proofC: proofInBytes.proofC,
+        root: inputsInBytes[0],
+        publicAmount: inputsInBytes[1],
+        extDataHash: inputsInBytes[2],
+        inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+        outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+      };
+      const nullifiers = findNullifierPDAs(program, proof);
+      const crossCheckNullifiers = findCrossCheckNullifierPDAs(program, proof);
+      const [pendingWithdrawalPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_withdrawal"), Buffer.from(proof.inputNullifiers[0])],
+        program.programId
+      );
+      const tx = await program.methods
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
+        .accounts({
+          treeAccount: treeAccountPDA,
+          nullifier0: nullifiers.nullifier0PDA,
+          nullifier1: nullifiers.nullifier1PDA,
+          nullifier2: crossCheckNullifiers.nullifier2PDA,
+          nullifier3: crossCheckNullifiers.nullifier3PDA,
+          recipient: txRecipient,
+          feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+          treeTokenAccount: treeTokenAccountPDA,
+          globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity: null,
+          depositOrigin: extAmount > 0 ? findDepositOriginPDA(program, proof) : null,
+          pendingWithdrawal: queued ? pendingWithdrawalPDA : null,
+          signer: randomUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .preInstructions([modifyComputeUnits])
+        .transaction();
+      await sendAndConfirmVersionedTransaction(
+        provider.connection,
+        await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, tx.instructions, lookupTableAddress),
+        [randomUser]
+      );
+      for (const output of outputs) {
+        globalMerkleTree.insert(await output.getCommitment());
+      }
+      return pendingWithdrawalPDA;
+    };
+
+    const threshold = 1_000_000;
+    const delaySlots = 20;
+    await applyConfigChange({
+      withdrawalDelay: { largeWithdrawalThreshold: new anchor.BN(threshold), delaySlots: new anchor.BN(delaySlots) }
+    });
+
+    // Deposit a note to withdraw from
+    const depositAmount = 10_000_000;
+    const depositFee = new anchor.BN(calculateDepositFee(depositAmount));
+    const depositOutputs = [
+      new Utxo({ lightWasm, amount: new BN(depositAmount).sub(depositFee).toString() }),
+      new Utxo({ lightWasm, amount: '0' })
+    ];
+    await submitTransaction([new Utxo({ lightWasm }), new Utxo({ lightWasm })], depositOutputs, depositAmount, depositFee, recipient.publicKey, false);
+
+    // A withdrawal above the threshold is held in a pending withdrawal
+    const withdrawalAmount = 3_000_000;
+    const withdrawFee = new anchor.BN(calculateWithdrawalFee(withdrawalAmount));
+    const claimRecipient = anchor.web3.Keypair.generate().publicKey;
+    const changeNote = new Utxo({ lightWasm, amount: depositOutputs[0].amount.sub(new BN(withdrawalAmount)).sub(withdrawFee).toString() });
+
+    // Without the pending withdrawal account the large withdrawal is rejected
+    try {
+      await submitTransaction([depositOutputs[0], new Utxo({ lightWasm })], [changeNote, new Utxo({ lightWasm, amount: '0' })], -withdrawalAmount, withdrawFee, claimRecipient, false);
+      expect.fail("A large withdrawal without a pending withdrawal account should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("PendingWithdrawalRequired");
+    }
+
+    const pendingWithdrawalPDA = await submitTransaction([depositOutputs[0], new Utxo({ lightWasm })], [changeNote, new Utxo({ lightWasm, amount: '0' })], -withdrawalAmount, withdrawFee, claimRecipient, true);
+    const pendingWithdrawal = await program.account.pendingWithdrawal.fetch(pendingWithdrawalPDA);
+    expect(pendingWithdrawal.recipient.equals(claimRecipient)).to.be.true;
+    expect(pendingWithdrawal.payer.equals(randomUser.publicKey)).to.be.true;
+    expect(pendingWithdrawal.amount.toNumber()).to.equal(withdrawalAmount);
+    expect(await provider.connection.getBalance(claimRecipient)).to.equal(0);
+
+    const claim = () => program.methods
+      .claimWithdrawal()
+      .accounts({
+        pendingWithdrawal: pendingWithdrawalPDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        recipient: claimRecipient,
+        payer: randomUser.publicKey,
+      })
+      .rpc();
+    try {
+      await claim();
+      expect.fail("Claiming during the delay should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("WithdrawalStillDelayed");
+    }
+
+    while (await provider.connection.getSlot() < pendingWithdrawal.releaseSlot.toNumber()) {
+      await new Promise((resolve) => setTimeout(resolve, 400));
+    }
+    await claim();
+    expect(await provider.connection.getBalance(claimRecipient)).to.equal(withdrawalAmount);
+    expect(await provider.connection.getAccountInfo(pendingWithdrawalPDA)).to.be.null;
+
+    // The guardian cancels a second large withdrawal during its delay
+    const secondWithdrawFee = new anchor.BN(calculateWithdrawalFee(withdrawalAmount));
+    const secondRecipient = anchor.web3.Keypair.generate().publicKey;
+    const secondChangeNote = new Utxo({ lightWasm, amount: changeNote.amount.sub(new BN(withdrawalAmount)).sub(secondWithdrawFee).toString() });
+    const secondPendingWithdrawalPDA = await submitTransaction([changeNote, new Utxo({ lightWasm })], [secondChangeNote, new Utxo({ lightWasm, amount: '0' })], -withdrawalAmount, secondWithdrawFee, secondRecipient, true);
+
+    const cancel = (guardian: anchor.web3.Keypair) => program.methods
+      .cancelWithdrawal()
+      .accounts({
+        pendingWithdrawal: secondPendingWithdrawalPDA,
+        globalConfig: globalConfigPDA,
+        guardian: guardian.publicKey,
+      })
+      .signers([guardian])
+      .rpc();
+    try {
+      await cancel(randomUser);
+      expect.fail("Only the guardian should be able to cancel a queued withdrawal");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    const vaultBalanceBefore = await provider.connection.getBalance(treeTokenAccountPDA);
+    await cancel(authority);
+    expect((await program.account.pendingWithdrawal.fetch(secondPendingWithdrawalPDA)).cancelled).to.be.true;
+    expect(await provider.connection.getBalance(secondRecipient)).to.equal(0);
+    expect(await provider.connection.getBalance(treeTokenAccountPDA)).to.equal(vaultBalanceBefore);
+
+    // A cancelled withdrawal can no longer be claimed, only paid out to a recipient the authority picks
+    const secondPendingWithdrawal = await program.account.pendingWithdrawal.fetch(secondPendingWithdrawalPDA);
+    try {
+      await program.methods
+        .claimWithdrawal()
+        .accounts({
+          pendingWithdrawal: secondPendingWithdrawalPDA,
+          treeTokenAccount: treeTokenAccountPDA,
+          recipient: secondRecipient,
+          payer: randomUser.publicKey,
+        })
+        .rpc();
+      expect.fail("Claiming a cancelled withdrawal should fail");
+    } catch (error) {
+      expect(error.toString()).to.include("PendingWithdrawalCancelled");
+    }
+
+    const resolvedRecipient = anchor.web3.Keypair.generate().publicKey;
+    const resolve = (signer: anchor.web3.Keypair) => program.methods
+      .resolveCancelledWithdrawal(secondPendingWithdrawal.nullifier, resolvedRecipient)
+      .accounts({
+        pendingWithdrawal: secondPendingWithdrawalPDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        globalConfig: globalConfigPDA,
+        authority: signer.publicKey,
+        recipientAccount: resolvedRecipient,
+        payer: randomUser.publicKey,
+      })
+      .signers([signer])
+      .rpc();
+    try {
+      await resolve(randomUser);
+      expect.fail("Only the authority should be able to resolve a cancelled withdrawal");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    await resolve(authority);
+    expect(await provider.connection.getAccountInfo(secondPendingWithdrawalPDA)).to.be.null;
+    expect(await provider.connection.getBalance(resolvedRecipient)).to.equal(withdrawalAmount);
+    expect(await provider.connection.getBalance(treeTokenAccountPDA)).to.equal(vaultBalanceBefore - withdrawalAmount);
+
+    // Pay out withdrawals immediately again for the remaining tests
+    await applyConfigChange({
+      withdrawalDelay: { largeWithdrawalThreshold: new anchor.BN(0), delaySlots: new anchor.BN(delaySlots) }
+    });
+  });
+
+  it("Deposits are limited in USD through the configured price feed", async () => {
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    // Applies a config change right away (no timelock is set at this point)
+    const applyConfigChange = async (change: any) => {
+      const config = await program.account.globalConfig.fetch(globalConfigPDA);
+      const pendingConfigChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+      await program.methods
+        .queueConfigChange(change)
+        .accounts({
+          globalConfig: globalConfigPDA,
+          pendingConfigChange: pendingConfigChangePDA,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      await program.methods
+        .executeConfigChange()
+        .accounts({
+          pendingConfigChange: pendingConfigChangePDA,
+          globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
+          authority: authority.publicKey,
+        })
+        .rpc();
+    };
+
+    // Proves and submits a deposit of `depositAmount` lamports
+    const deposit = async (depositAmount: number, priceFeed: PublicKey | null) => {
+      const fee = new anchor.BN(calculateDepositFee(depositAmount));
+      const extData = {
+        recipient: recipient.publicKey,
+        extAmount: new anchor.BN(depositAmount),
+        encryptedOutput1: Buffer.from("usdLimitEncryptedOutput1"),
+        encryptedOutput2: Buffer.from("usdLimitEncryptedOutput2"),
+        fee,
+        feeRecipient: FEE_RECIPIENT_ACCOUNT,
+        mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+      };
+      const inputs = [new Utxo({ lightWasm }), new Utxo({ lightWasm })];
+      const outputs = [
+        new Utxo({ lightWasm, amount: extData.extAmount.sub(fee).toString() }),
+        new Utxo({ lightWasm, amount: '0' })
+      ];
+      const proofResult = await prove({
+        root: globalMerkleTree.root(),
+        inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+        outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+        publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+        extDataHash: getExtDataHash(extData),
+        inAmount: inputs.map(x => x.amount.toString(10)),
+        inPrivateKey: inputs.map(x => x.keypair.privkey),
+        inBlinding: inputs.map(x => x.blinding.toString(10)),
+        mintAddress: inputs[0].mintAddress,
+        inPathIndices: [0, 0],
+        inPathElements: inputs.map(() => new Array(globalMerkleTree.levels).fill(0)),
+        outAmount: outputs.map(x => x.amount.toString(10)),
+        outBlinding: outputs.map(x => x.blinding.toString(10)),
+        outPubkey: outputs.map(x => x.keypair.pubkey),
+      }, keyBasePath);
+      const proofInBytes = parseProofToBytesArray(proofResult.proof);
+      const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+      const proof = {
+        proofA: proofInBytes.proofA,
+        proofB: proofInBytes.proofB.flat(),
+        proofC: proofInBytes.proofC,
+        root: inputsInBytes[0],
+        publicAmount: inputsInBytes[1],
+        extDataHash: inputsInBytes[2],
+        inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+        outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+      };
+      const nullifiers = findNullifierPDAs(program, proof);
+      const crossCheckNullifiers = findCrossCheckNullifierPDAs(program, proof);
+      const tx = await program.methods
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
+        .accounts({
+          treeAccount: treeAccountPDA,
+          nullifier0: nullifiers.nullifier0PDA,
+          nullifier1: nullifiers.nullifier1PDA,
+          nullifier2: crossCheckNullifiers.nullifier2PDA,
+          nullifier3: crossCheckNullifiers.nullifier3PDA,
+          recipient: recipient.publicKey,
+          feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+          treeTokenAccount: treeTokenAccountPDA,
+          globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed,
+          depositorVelocity: null,
+          depositOrigin: findDepositOriginPDA(program, proof),
+          pendingWithdrawal: null,
+          signer: randomUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .preInstructions([modifyComputeUnits])
+        .transaction();
+      await sendAndConfirmVersionedTransaction(
+        provider.connection,
+        await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, tx.instructions, lookupTableAddress),
+        [randomUser]
+      );
+      for (const output of outputs) {
+        globalMerkleTree.insert(await output.getCommitment());
+      }
+    };
+
+    // $150 per SOL
+    const [priceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mock_price_feed"), authority.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .setMockPrice(new anchor.BN(150_000_000))
+      .accounts({
+        priceFeed: priceFeedPDA,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId
+      })
+      .signers([authority])
+      .rpc();
+    const priceFeed = await program.account.mockPriceFeed.fetch(priceFeedPDA);
+    expect(priceFeed.microUsdPerSol.toNumber()).to.equal(150_000_000);
+
+    // Deposits of at most $1 (~6.67M lamports), no TVL cap
+    await applyConfigChange({
+      usdLimits: {
+        priceFeed: priceFeedPDA,
+        maxPriceAgeSlots: new anchor.BN(150),
+        maxDepositUsd: new anchor.BN(1_000_000),
+        maxTvlUsd: new anchor.BN(0),
+      }
+    });
+
+    try {
+      await deposit(5_000_000, null);
+      expect.fail("A deposit without the configured price feed should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("PriceFeedRequired");
+    }
+
+    try {
+      await deposit(10_000_000, priceFeedPDA);
+      expect.fail("A $1.50 deposit should exceed the $1 limit");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("DepositLimitExceeded");
+    }
+
+    await deposit(5_000_000, priceFeedPDA);
+
+    // Turn the USD limits off again for the remaining tests
+    await applyConfigChange({
+      usdLimits: {
+        priceFeed: PublicKey.default,
+        maxPriceAgeSlots: new anchor.BN(150),
+        maxDepositUsd: new anchor.BN(0),
+        maxTvlUsd: new anchor.BN(0),
+      }
+    });
+  });
+
+  it("Deposits are limited per depositor over the velocity window", async () => {
+    const keyBasePath = path.resolve(__dirname, '../../artifacts/circuits/transaction2');
+    const modifyComputeUnits = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 1_000_000 
+    });
+    const lookupTableAddress = await createGlobalTestALT(
+      provider.connection,
+      authority,
+      getTestProtocolAddresses(program.programId, authority.publicKey, FEE_RECIPIENT_ACCOUNT)
+    );
+
+    // Applies a config change right away (no timelock is set at this point)
+    const applyConfigChange = async (change: any) => {
+      const config = await program.account.globalConfig.fetch(globalConfigPDA);
+      const pendingConfigChangePDA = findPendingConfigChangePDA(program, config.nextConfigChangeId);
+      await program.methods
+        .queueConfigChange(change)
+        .accounts({
+          globalConfig: globalConfigPDA,
+          pendingConfigChange: pendingConfigChangePDA,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      await program.methods
+        .executeConfigChange()
+        .accounts({
+          pendingConfigChange: pendingConfigChangePDA,
+          globalConfig: globalConfigPDA,
+          treeAccount: treeAccountPDA,
+          authority: authority.publicKey,
+        })
+        .rpc();
+    };
+
+    // Proves and submits a deposit of `depositAmount` lamports
+    const deposit = async (depositAmount: number, depositorVelocity: PublicKey | null) => {
+      const fee = new anchor.BN(calculateDepositFee(depositAmount));
+      const extData = {
+        recipient: recipient.publicKey,
+        extAmount: new anchor.BN(depositAmount),
+        encryptedOutput1: Buffer.from("velocityEncryptedOutput1"),
+        encryptedOutput2: Buffer.from("velocityEncryptedOutput2"),
+        fee,
+        feeRecipient: FEE_RECIPIENT_ACCOUNT,
+        mintAddress: new anchor.web3.PublicKey("11111111111111111111111111111112"),
+      };
+      const inputs = [new Utxo({ lightWasm }), new Utxo({ lightWasm })];
+      const outputs = [
+        new Utxo({ lightWasm, amount: extData.extAmount.sub(fee).toString() }),
+        new Utxo({ lightWasm, amount: '0' })
+      ];
+      const proofResult = await prove({
+        root: globalMerkleTree.root(),
+        inputNullifier: await Promise.all(inputs.map(x => x.getNullifier())),
+        outputCommitment: await Promise.all(outputs.map(x => x.getCommitment())),
+        publicAmount: extData.extAmount.sub(extData.fee).add(FIELD_SIZE).mod(FIELD_SIZE).toString(),
+        extDataHash: getExtDataHash(extData),
+        inAmount: inputs.map(x => x.amount.toString(10)),
+        inPrivateKey: inputs.map(x => x.keypair.privkey),
+        inBlinding: inputs.map(x => x.blinding.toString(10)),
+        mintAddress: inputs[0].mintAddress,
+        inPathIndices: [0, 0],
+        inPathElements: inputs.map(() => new Array(globalMerkleTree.levels).fill(0)),
+        outAmount: outputs.map(x => x.amount.toString(10)),
+        outBlinding: outputs.map(x => x.blinding.toString(10)),
+        outPubkey: outputs.map(x => x.keypair.pubkey),
+      }, keyBasePath);
+      const proofInBytes = parseProofToBytesArray(proofResult.proof);
+      const inputsInBytes = parseToBytesArray(proofResult.publicSignals);
+      const proof = {
+        proofA: proofInBytes.proofA,
+        proofB: proofInBytes.proofB.flat(),
+        proofC: proofInBytes.proofC,
+        root: inputsInBytes[0],
+        publicAmount: inputsInBytes[1],
+        extDataHash: inputsInBytes[2],
+        inputNullifiers: [inputsInBytes[3], inputsInBytes[4]],
+        outputCommitments: [inputsInBytes[5], inputsInBytes[6]],
+      };
+      const nullifiers = findNullifierPDAs(program, proof);
+      const crossCheckNullifiers = findCrossCheckNullifierPDAs(program, proof);
+      const tx = await program.methods
+        .transact(proof, createExtDataMinified(extData), extData.encryptedOutput1, extData.encryptedOutput2, new anchor.BN(0))
+        .accounts({
+          treeAccount: treeAccountPDA,
+          nullifier0: nullifiers.nullifier0PDA,
+          nullifier1: nullifiers.nullifier1PDA,
+          nullifier2: crossCheckNullifiers.nullifier2PDA,
+          nullifier3: crossCheckNullifiers.nullifier3PDA,
+          recipient: recipient.publicKey,
+          feeRecipientAccount: FEE_RECIPIENT_ACCOUNT,
+          treeTokenAccount: treeTokenAccountPDA,
+          globalConfig: globalConfigPDA,
+          denyList: denyListPDA,
+          priceFeed: null,
+          depositorVelocity,
+          depositOrigin: findDepositOriginPDA(program, proof),
+          pendingWithdrawal: null,
+          signer: randomUser.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .preInstructions([modifyComputeUnits])
+        .transaction();
+      await sendAndConfirmVersionedTransaction(
+        provider.connection,
+        await createVersionedTransactionWithALT(provider.connection, randomUser.publicKey, tx.instructions, lookupTableAddress),
+        [randomUser]
+      );
+      for (const output of outputs) {
+        globalMerkleTree.insert(await output.getCommitment());
+      }
+    };
+
+    const [depositorVelocityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("depositor_velocity"), randomUser.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // At most 15M lamports per depositor per window
+    await applyConfigChange({
+      velocityLimit: {
+        windowSlots: new anchor.BN(216_000),
+        maxDepositPerWindow: new anchor.BN(15_000_000),
+      }
+    });
+
+    try {
+      await deposit(5_000_000, null);
+      expect.fail("A deposit without the depositor velocity account should fail");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("DepositorVelocityRequired");
+    }
+
+    // The first deposit creates the velocity account
+    await deposit(10_000_000, depositorVelocityPDA);
+    const depositorVelocity = await program.account.depositorVelocity.fetch(depositorVelocityPDA);
+    expect(depositorVelocity.depositor.toBase58()).to.equal(randomUser.publicKey.toBase58());
+
+    try {
+      await deposit(10_000_000, depositorVelocityPDA);
+      expect.fail("A second 10M lamport deposit should exceed the 15M lamport window");
+    } catch (error) {
+      expect(error.toString() + (error.logs ?? []).join("\n")).to.include("VelocityLimitExceeded");
+    }
+
+    await deposit(5_000_000, depositorVelocityPDA);
+
+    // Turn the velocity limit off again for the remaining tests
+    await applyConfigChange({
+      velocityLimit: {
+        windowSlots: new anchor.BN(216_000),
+        maxDepositPerWindow: new anchor.BN(0),
+      }
+    });
+  });
+
+  // Must stay the last test: handing over to the multisig is permanent and the single-key
+  // authority can no longer call admin instructions afterwards.
+  it("Multisig authority executes admin actions once the threshold is met", async () => {
+    const memberA = anchor.web3.Keypair.generate();
+    const memberB = anchor.web3.Keypair.generate();
+    for (const member of [memberA, memberB]) {
+      const signature = await provider.connection.requestAirdrop(member.publicKey, 1 * LAMPORTS_PER_SOL);
+      const latestBlockHash = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({
+        blockhash: latestBlockHash.blockhash,
+        lastValidBlockHeight: latestBlockHash.lastValidBlockHeight,
+        signature,
+      });
+    }
+
+    const [multisigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("multisig")],
+      program.programId
+    );
+
+    // 2-of-3 multisig takes over from the single-key authority
+    await program.methods
+      .createMultisig([authority.publicKey, memberA.publicKey, memberB.publicKey], 2)
+      .accounts({
+        multisig: multisigPDA,
+        globalConfig: globalConfigPDA,
+        treeAccount: treeAccountPDA,
+        treeTokenAccount: treeTokenAccountPDA,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+    const config = await program.account.globalConfig.fetch(globalConfigPDA);
+    expect(config.authority.equals(multisigPDA)).to.be.true;
+    const merkleTreeAccount = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(merkleTreeAccount.authority.equals(multisigPDA)).to.be.true;
+
+    // The old authority can no longer act alone
+    try {
+      await program.methods
+        .updateDepositLimit(new anchor.BN(1_000_000_000))
+        .accounts({
+          treeAccount: treeAccountPDA,
+          globalConfig: globalConfigPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+      expect.fail("Single-key update should have failed after the multisig handover");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    const newLimit = new anchor.BN(3_000_000_000_000); // 3000 SOL
+    const multisig = await program.account.multisig.fetch(multisigPDA);
+    const [proposalPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("multisig_proposal"), multisig.nextProposalId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    // Accounts of the admin instruction the proposal runs, in its declared order
+    const updateDepositLimitAccounts = [
+      { pubkey: treeAccountPDA, isSigner: false, isWritable: true },
+      { pubkey: globalConfigPDA, isSigner: false, isWritable: false },
+      { pubkey: multisigPDA, isSigner: false, isWritable: false },
+    ];
+
+    await program.methods
+      .proposeMultisigAction(
+        { updateDepositLimit: { newLimit } },
+        updateDepositLimitAccounts.map(({ pubkey }) => pubkey)
+      )
+      .accounts({
+        multisig: multisigPDA,
+        proposal: proposalPDA,
+        proposer: memberA.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([memberA])
+      .rpc();
+
+    const executeProposal = (member: anchor.web3.Keypair, accounts = updateDepositLimitAccounts) => program.methods
+      .executeMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: proposalPDA,
+        proposer: memberA.publicKey,
+        member: member.publicKey,
+        zkcashProgram: program.programId,
+      })
+      .remainingAccounts(accounts)
+      .signers([member])
+      .rpc();
+
+    // One approval out of two required
+    try {
+      await executeProposal(memberA);
+      expect.fail("Execution should have failed below the threshold");
+    } catch (error) {
+      expect(error.toString()).to.include("MultisigThresholdNotMet");
+    }
+
+    // Non-members cannot approve
+    try {
+      await program.methods
+        .approveMultisigProposal()
+        .accounts({
+          multisig: multisigPDA,
+          proposal: proposalPDA,
+          member: attacker.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      expect.fail("Approval by a non-member should have failed");
+    } catch (error) {
+      expect(error.toString()).to.include("NotMultisigMember");
+    }
+
+    await program.methods
+      .approveMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: proposalPDA,
+        member: memberB.publicKey,
+      })
+      .signers([memberB])
+      .rpc();
+
+    // The approved action only runs with the approved accounts
+    try {
+      await executeProposal(memberB, [
+        { pubkey: treeAccountPDA, isSigner: false, isWritable: true },
+        { pubkey: globalConfigPDA, isSigner: false, isWritable: false },
+        { pubkey: memberB.publicKey, isSigner: true, isWritable: false },
+      ]);
+      expect.fail("Execution with other accounts than the approved ones should have failed");
+    } catch (error) {
+      expect(error.toString()).to.include("MultisigAccountsMismatch");
+    }
+
+    await executeProposal(memberB);
+
+    const updatedTreeAccount = await program.account.merkleTreeAccount.fetch(treeAccountPDA);
+    expect(updatedTreeAccount.maxDepositAmount.toString()).to.equal(newLimit.toString());
+    expect(await provider.connection.getAccountInfo(proposalPDA)).to.be.null;
+
+    const findProposalPDA = async () => {
+      const { nextProposalId } = await program.account.multisig.fetch(multisigPDA);
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("multisig_proposal"), nextProposalId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    };
+    const propose = async (action: any, accounts: PublicKey[], proposal: PublicKey) => program.methods
+      .proposeMultisigAction(action, accounts)
+      .accounts({
+        multisig: multisigPDA,
+        proposal,
+        proposer: memberA.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([memberA])
+      .rpc();
+    const closeProposal = (proposal: PublicKey) => program.methods
+      .closeMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal,
+        proposer: memberA.publicKey,
+      })
+      .rpc();
+
+    // An open proposal cannot be closed while it can still run
+    const staleProposalPDA = await findProposalPDA();
+    await propose(
+      { updateDepositLimit: { newLimit } },
+      updateDepositLimitAccounts.map(({ pubkey }) => pubkey),
+      staleProposalPDA
+    );
+    try {
+      await closeProposal(staleProposalPDA);
+      expect.fail("Closing a proposal that can still run should have failed");
+    } catch (error) {
+      expect(error.toString()).to.include("MultisigProposalNotStale");
+    }
+
+    // Re-setting the members bumps the nonce and leaves the open proposal stale
+    const membersProposalPDA = await findProposalPDA();
+    await propose({
+      setMultisigMembers: {
+        members: [authority.publicKey, memberA.publicKey, memberB.publicKey],
+        threshold: 2,
+      }
+    }, [multisigPDA, multisigPDA], membersProposalPDA);
+    await program.methods
+      .approveMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: membersProposalPDA,
+        member: memberB.publicKey,
+      })
+      .signers([memberB])
+      .rpc();
+    await program.methods
+      .executeMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: membersProposalPDA,
+        proposer: memberA.publicKey,
+        member: memberB.publicKey,
+        zkcashProgram: program.programId,
+      })
+      .remainingAccounts([
+        { pubkey: multisigPDA, isSigner: false, isWritable: true },
+        { pubkey: multisigPDA, isSigner: false, isWritable: false },
+      ])
+      .signers([memberB])
+      .rpc();
+
+    // Anyone can close it, the rent goes back to the proposer
+    const proposerBalanceBefore = await provider.connection.getBalance(memberA.publicKey);
+    await closeProposal(staleProposalPDA);
+    expect(await provider.connection.getAccountInfo(staleProposalPDA)).to.be.null;
+    expect(await provider.connection.getBalance(memberA.publicKey)).to.be.greaterThan(proposerBalanceBefore);
+  });
 });